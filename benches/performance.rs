@@ -1,6 +1,28 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use circuit_breaker::CircuitBreaker;
-use std::time::Duration;
+use circuit_breaker::{CircuitBreaker, ShardedCircuitBreaker};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Counts heap allocations so the allocation benchmarks can report allocations per call.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
 
 fn circuit_breaker_benchmark(c: &mut Criterion) {
     let cb = CircuitBreaker::new(3, Duration::from_secs(60));
@@ -14,5 +36,107 @@ fn circuit_breaker_benchmark(c: &mut Criterion) {
     // Add more benchmarks as needed
 }
 
-criterion_group!(benches, circuit_breaker_benchmark);
+const THREADS: usize = 8;
+
+fn contended<F>(iters: u64, call: F) -> Duration
+where
+    F: Fn(u64) + Sync,
+{
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for i in 0..iters {
+                    call(i);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn sharded_benchmark(c: &mut Criterion) {
+    // Roughly one call in a hundred fails, never enough to open the circuit.
+    let fails = |i: u64| i.is_multiple_of(100);
+
+    let cb = CircuitBreaker::new(u32::MAX, Duration::from_secs(60));
+    c.bench_function("execute contended", |b| {
+        b.iter_custom(|iters| contended(iters, |i| {
+            let _ = cb.execute(|| if fails(i) { Err(std::io::Error::other("error")) } else { Ok(()) });
+        }))
+    });
+
+    let sharded = ShardedCircuitBreaker::new(
+        CircuitBreaker::new(u32::MAX, Duration::from_secs(60)),
+        THREADS,
+        16,
+        Duration::from_millis(100),
+    );
+    c.bench_function("execute contended sharded", |b| {
+        b.iter_custom(|iters| contended(iters, |i| {
+            let _ = sharded.execute(|| if fails(i) { Err(std::io::Error::other("error")) } else { Ok(()) });
+        }))
+    });
+}
+
+fn peek_state_benchmark(c: &mut Criterion) {
+    // Compare runs with and without `--features arc-swap`.
+    let name = if cfg!(feature = "arc-swap") {
+        "peek_state with concurrent writer (arc-swap)"
+    } else {
+        "peek_state with concurrent writer (mutex)"
+    };
+
+    let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| {
+            while !stop.load(Ordering::Relaxed) {
+                cb.force_open();
+                cb.force_close();
+            }
+        });
+
+        c.bench_function(name, |b| {
+            b.iter_custom(|iters| contended(iters, |_| {
+                black_box(cb.peek_state());
+            }))
+        });
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+/// Returns the average number of allocations made by one call to `call`.
+fn allocations_per_call<F: FnMut()>(mut call: F) -> f64 {
+    const CALLS: usize = 1000;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..CALLS {
+        call();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / CALLS as f64
+}
+
+fn allocation_benchmark(c: &mut Criterion) {
+    // Failures are ignored by the classifier so the circuit stays closed throughout.
+    let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+        .error_classifier(|_| false)
+        .build()
+        .unwrap();
+    let fail = || "not a number".parse::<u32>();
+
+    println!(
+        "allocations per failed call: execute {}, execute_pure {}",
+        allocations_per_call(|| drop(black_box(cb.execute(fail)))),
+        allocations_per_call(|| drop(black_box(cb.execute_pure(fail)))),
+    );
+
+    c.bench_function("execute failed operation", |b| {
+        b.iter(|| cb.execute(fail))
+    });
+    c.bench_function("execute_pure failed operation", |b| {
+        b.iter(|| cb.execute_pure(fail))
+    });
+}
+
+criterion_group!(benches, circuit_breaker_benchmark, sharded_benchmark, peek_state_benchmark, allocation_benchmark);
 criterion_main!(benches);
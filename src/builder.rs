@@ -0,0 +1,856 @@
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::circuit_breaker::{
+    Callback, CallbackPanicHook, CircuitBreaker, ErrorClassifier, Probe, TypedCircuitBreaker,
+};
+use crate::circuit_state::CircuitState;
+use crate::clock::{Clock, SystemClock};
+use crate::error::ConfigError;
+use crate::metrics::{MetricsRecorder, NoopMetricsRecorder};
+use crate::policy::{ClosedResetMode, FailureAction, FailurePolicy, OpenBehavior, PanicBehavior};
+use crate::store::StateStore;
+
+/// The failure threshold used by [`CircuitBreaker::default`] and [`CircuitBreakerBuilder::default`].
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// The reset timeout used by [`CircuitBreaker::default`] and [`CircuitBreakerBuilder::default`].
+pub const DEFAULT_RESET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A builder for configuring a [`CircuitBreaker`] beyond the options accepted by
+/// [`CircuitBreaker::new`].
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::CircuitBreakerBuilder;
+/// use std::time::Duration;
+///
+/// let cb = CircuitBreakerBuilder::new(3, Duration::from_secs(60))
+///     .failure_decay(Duration::from_secs(300))
+///     .build()?;
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+pub struct CircuitBreakerBuilder {
+    pub(crate) failure_threshold: u32,
+    pub(crate) reset_timeout: Duration,
+    pub(crate) failure_decay: Option<Duration>,
+    pub(crate) metrics_recorder: Box<dyn MetricsRecorder>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) failure_policies: Vec<FailurePolicy>,
+    pub(crate) error_classifier: Option<ErrorClassifier>,
+    pub(crate) initial_state: CircuitState,
+    pub(crate) half_open_max_calls: Option<u32>,
+    pub(crate) half_open_single_flight: bool,
+    pub(crate) max_concurrency: Option<usize>,
+    pub(crate) degraded_failure_fraction: f64,
+    pub(crate) success_threshold: u32,
+    pub(crate) outcome_history: usize,
+    pub(crate) latency_histogram: bool,
+    pub(crate) minimum_throughput: u32,
+    pub(crate) repeated_error_threshold: Option<u32>,
+    pub(crate) max_open_duration: Option<Duration>,
+    pub(crate) warmup: Option<Duration>,
+    pub(crate) probation: Option<(Duration, u32)>,
+    pub(crate) state_store: Option<Arc<dyn StateStore>>,
+    pub(crate) unreported_permit_is_failure: bool,
+    pub(crate) count_forced_trips: bool,
+    pub(crate) extend_timeout_on_rejection: bool,
+    pub(crate) open_behavior: OpenBehavior,
+    pub(crate) panic_behavior: PanicBehavior,
+    pub(crate) half_open_probe: Option<Probe>,
+    pub(crate) closed_reset_mode: ClosedResetMode,
+    pub(crate) async_callbacks: bool,
+    pub(crate) on_callback_panic: Option<CallbackPanicHook>,
+    pub(crate) callback_min_interval: Option<Duration>,
+    pub(crate) on_open: Option<Callback>,
+    pub(crate) on_close: Option<Callback>,
+    pub(crate) on_half_open: Option<Callback>,
+    #[cfg(feature = "rand")]
+    pub(crate) half_open_sampling: f64,
+    #[cfg(feature = "rand")]
+    pub(crate) rng_seed: Option<u64>,
+}
+
+impl CircuitBreakerBuilder {
+    /// Creates a new builder with the specified failure threshold and reset timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure_threshold` - The number of failures that must occur before the circuit breaker opens.
+    /// * `reset_timeout` - The duration after which the circuit breaker will transition from Open to Half-Open.
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        CircuitBreakerBuilder {
+            failure_threshold,
+            reset_timeout,
+            failure_decay: None,
+            metrics_recorder: Box::new(NoopMetricsRecorder),
+            clock: Arc::new(SystemClock),
+            failure_policies: vec![FailurePolicy::ConsecutiveFailures],
+            error_classifier: None,
+            initial_state: CircuitState::Closed,
+            half_open_max_calls: None,
+            half_open_single_flight: false,
+            max_concurrency: None,
+            degraded_failure_fraction: 0.5,
+            success_threshold: 1,
+            outcome_history: 0,
+            latency_histogram: false,
+            minimum_throughput: 0,
+            repeated_error_threshold: None,
+            max_open_duration: None,
+            warmup: None,
+            probation: None,
+            state_store: None,
+            unreported_permit_is_failure: false,
+            count_forced_trips: false,
+            extend_timeout_on_rejection: false,
+            open_behavior: OpenBehavior::FailFast,
+            panic_behavior: PanicBehavior::Return,
+            half_open_probe: None,
+            closed_reset_mode: ClosedResetMode::Keep,
+            async_callbacks: false,
+            on_callback_panic: None,
+            callback_min_interval: None,
+            on_open: None,
+            on_close: None,
+            on_half_open: None,
+            #[cfg(feature = "rand")]
+            half_open_sampling: 1.0,
+            #[cfg(feature = "rand")]
+            rng_seed: None,
+        }
+    }
+
+    /// Sets the number of failures that must occur before the circuit breaker opens.
+    ///
+    /// A threshold of `0` means the circuit never opens automatically under
+    /// [`FailurePolicy::ConsecutiveFailures`].
+    pub fn failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    /// Sets the duration after which the circuit breaker will transition from Open to Half-Open.
+    pub fn reset_timeout(mut self, reset_timeout: Duration) -> Self {
+        self.reset_timeout = reset_timeout;
+        self
+    }
+
+    /// Sets a hard ceiling on how long the circuit stays open before moving to Half-Open.
+    ///
+    /// The reset timeout is measured from the last recorded failure, so a timeout lengthened at
+    /// runtime with `set_reset_timeout` can keep a circuit open for a long time. Once the circuit
+    /// has been open for `max_open_duration`, the next `state`, `is_call_permitted` or `execute`
+    /// moves it to Half-Open whatever the reset timeout. A circuit held open with `force_open` is
+    /// exempt. Disabled by default.
+    pub fn max_open_duration(mut self, max_open_duration: Duration) -> Self {
+        self.max_open_duration = Some(max_open_duration);
+        self
+    }
+
+    /// Sets a warmup period after the breaker is built during which failures do not count
+    /// towards opening the circuit, for example while a connection pool is still cold.
+    ///
+    /// Failures during warmup are still reported in the metrics, but they neither add to the
+    /// failure counter or the rolling windows nor trigger any failure policy. Errors the
+    /// classifier maps to `FailureAction::ForceOpen` and failed half-open trials still open the
+    /// circuit. The period is measured with the breaker's clock. Disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(1, Duration::from_secs(30))
+    ///     .warmup(Duration::from_secs(10))
+    ///     .build()?;
+    ///
+    /// cb.handle_failure();
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// assert_eq!(cb.metrics().total_failures, 1);
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
+
+    /// Sets a probation period after the circuit closes from Half-Open, during which the
+    /// [`FailurePolicy::ConsecutiveFailures`] policy opens the circuit at `failure_threshold`
+    /// failures instead of the usual threshold.
+    ///
+    /// This holds a freshly recovered dependency to a stricter standard. Probation only starts
+    /// when a half-open trial closes the circuit; closing it with
+    /// [`CircuitBreaker::force_close`](crate::CircuitBreaker::force_close) ends any probation
+    /// instead. As with the usual threshold, a `failure_threshold` of `0` never opens the
+    /// circuit.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - How long probation lasts after the circuit closes.
+    /// * `failure_threshold` - The failure threshold applied during probation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// // After recovering, a single failure within a minute re-opens the circuit.
+    /// let cb = CircuitBreaker::builder(5, Duration::from_secs(30))
+    ///     .probation(Duration::from_secs(60), 1)
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn probation(mut self, window: Duration, failure_threshold: u32) -> Self {
+        self.probation = Some((window, failure_threshold));
+        self
+    }
+
+    /// Sets how a success made while the circuit is closed affects the failure counter.
+    ///
+    /// Defaults to [`ClosedResetMode::Keep`], which leaves the counter alone.
+    pub fn closed_reset_mode(mut self, mode: ClosedResetMode) -> Self {
+        self.closed_reset_mode = mode;
+        self
+    }
+
+    /// Sets the window after which accumulated failures age out.
+    ///
+    /// If more than `decay` has passed since the last recorded failure, the failure counter is
+    /// reset to zero before the next failure is counted, so an old burst of failures cannot
+    /// combine with a fresh one to open the circuit.
+    pub fn failure_decay(mut self, decay: Duration) -> Self {
+        self.failure_decay = Some(decay);
+        self
+    }
+
+    /// Sets whether state-change callbacks run on a background thread instead of the thread that
+    /// caused the transition.
+    ///
+    /// With `true`, a worker thread is started when the breaker is built, and each transition
+    /// queues its callback there, so a slow callback (sending an alert, say) does not delay the
+    /// call that tripped the circuit. Callbacks run one at a time, in the order of the
+    /// transitions that fired them. The worker exits after the breaker is dropped and its queue
+    /// has drained. Defaults to `false`, which runs callbacks synchronously.
+    pub fn async_callbacks(mut self, enabled: bool) -> Self {
+        self.async_callbacks = enabled;
+        self
+    }
+
+    /// Sets a hook run when a state-change callback panics, receiving the state the callback was
+    /// run for and the panic payload, for example to log it.
+    ///
+    /// A panicking callback never unwinds through the breaker. Without a hook, the panic is
+    /// discarded. The hook runs where the callback ran: on the calling thread with the breaker's
+    /// lock held, or on the callback thread with [`async_callbacks`](Self::async_callbacks).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreaker;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+    ///     .on_callback_panic(|to, payload| {
+    ///         let message = payload.downcast_ref::<&str>().copied().unwrap_or("unknown");
+    ///         eprintln!("callback for transition to {} panicked: {}", to, message);
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn on_callback_panic<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(CircuitState, &(dyn Any + Send)) + Send + Sync + 'static,
+    {
+        self.on_callback_panic = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets the minimum time between two invocations of the same state-change callback.
+    ///
+    /// When the circuit flaps, a transition whose callback already ran less than `interval` ago
+    /// still happens, but its callback is suppressed. Once the interval has passed, a suppressed
+    /// callback runs once more, with the label of the last transition it missed, so the final
+    /// state of a flapping circuit is still reported. This trailing run happens on the first
+    /// call, outcome, state query or transition after the interval, as the breaker has no timer
+    /// of its own. Each of the open, close and half-open callbacks is limited separately, so a
+    /// suppressed open alert does not hold back the close callback. Subscribers and the metrics
+    /// recorder still see every transition. Disabled by default.
+    pub fn callback_min_interval(mut self, interval: Duration) -> Self {
+        self.callback_min_interval = Some(interval);
+        self
+    }
+
+    /// Sets the recorder that receives success, failure, rejection and state-change events.
+    ///
+    /// Defaults to [`NoopMetricsRecorder`].
+    pub fn metrics_recorder(mut self, recorder: Box<dyn MetricsRecorder>) -> Self {
+        self.metrics_recorder = recorder;
+        self
+    }
+
+    /// Keeps the circuit state in `store`, so that every breaker built with the same store shares
+    /// one circuit.
+    ///
+    /// The store's state is authoritative, so `initial_state` only affects a breaker without a
+    /// store. By default the state is kept privately by the breaker.
+    pub fn state_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// Sets the clock used for every time measurement. Defaults to [`SystemClock`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the policy that decides when failures open the circuit, replacing any policies set
+    /// before.
+    ///
+    /// Defaults to [`FailurePolicy::ConsecutiveFailures`].
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policies = vec![policy];
+        self
+    }
+
+    /// Adds a policy that also opens the circuit.
+    ///
+    /// Every policy is evaluated after each failure, and the circuit opens as soon as any of
+    /// them is satisfied. Each rate-based policy keeps its own window, so for example a
+    /// [`FailurePolicy::TimeWindow`] added to the default
+    /// [`FailurePolicy::ConsecutiveFailures`] opens the circuit on either a run of failures or
+    /// a high failure rate, whichever comes first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::{CircuitBreakerBuilder, FailurePolicy};
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreakerBuilder::new(5, Duration::from_secs(60))
+    ///     .or_failure_policy(FailurePolicy::TimeWindow {
+    ///         duration: Duration::from_secs(60),
+    ///         buckets: 6,
+    ///         failure_rate: 0.5,
+    ///     })
+    ///     .minimum_throughput(20)
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn or_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policies.push(policy);
+        self
+    }
+
+    /// Sets the number of calls that must be recorded in the window before a rate-based
+    /// policy such as [`FailurePolicy::TimeWindow`] is evaluated.
+    ///
+    /// Below this many calls the circuit stays closed whatever the failure rate, so a single
+    /// failure at low traffic cannot open it. Defaults to 0. Has no effect on
+    /// [`FailurePolicy::ConsecutiveFailures`].
+    pub fn minimum_throughput(mut self, minimum_throughput: u32) -> Self {
+        self.minimum_throughput = minimum_throughput;
+        self
+    }
+
+    /// Opens the circuit as soon as the last `threshold` failures were caused by identical errors,
+    /// even if the failure threshold has not been reached.
+    ///
+    /// Errors are fingerprinted by hashing their `Display` output, so a persistent fault that
+    /// keeps producing the same message trips the circuit sooner than varied transient errors.
+    /// Failures reported without an error, such as through `handle_failure`, break the run.
+    /// Disabled by default.
+    pub fn repeated_error_threshold(mut self, threshold: u32) -> Self {
+        self.repeated_error_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets a classifier deciding which errors count as failures.
+    ///
+    /// The classifier receives each error returned by an operation and returns `true` if it
+    /// should count against the circuit. Ignored errors are still returned to the caller but are
+    /// recorded as neither a failure nor a success. By default every error counts.
+    pub fn error_classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&(dyn std::error::Error + 'static)) -> bool + Send + Sync + 'static,
+    {
+        self.error_classifier = Some(Arc::new(
+            move |error: &(dyn std::error::Error + 'static)| {
+                if classifier(error) {
+                    FailureAction::Count
+                } else {
+                    FailureAction::Ignore
+                }
+            },
+        ));
+        self
+    }
+
+    /// Sets a classifier deciding what each error does to the circuit.
+    ///
+    /// This generalizes [`error_classifier`](Self::error_classifier): instead of counting or
+    /// ignoring an error, the classifier can give it a weight or open the circuit immediately,
+    /// for example when credentials have been revoked and retrying is pointless. Only one
+    /// classifier is active; setting either replaces the other.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::{CircuitBreakerBuilder, FailureAction};
+    /// use std::io::ErrorKind;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreakerBuilder::new(5, Duration::from_secs(60))
+    ///     .failure_classifier(|e| match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+    ///         Some(ErrorKind::PermissionDenied) => FailureAction::ForceOpen,
+    ///         Some(ErrorKind::NotFound) => FailureAction::Ignore,
+    ///         _ => FailureAction::Count,
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn failure_classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&(dyn std::error::Error + 'static)) -> FailureAction + Send + Sync + 'static,
+    {
+        self.error_classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Sets whether a [`CallPermit`](crate::CallPermit) dropped without a report counts as a
+    /// failure rather than a success. Defaults to `false`.
+    pub fn unreported_permit_is_failure(mut self, is_failure: bool) -> Self {
+        self.unreported_permit_is_failure = is_failure;
+        self
+    }
+
+    /// Sets whether opening the circuit with
+    /// [`CircuitBreaker::force_open`](crate::CircuitBreaker::force_open) counts towards
+    /// [`CircuitBreaker::trip_count`](crate::CircuitBreaker::trip_count). Defaults to `false`.
+    pub fn count_forced_trips(mut self, count: bool) -> Self {
+        self.count_forced_trips = count;
+        self
+    }
+
+    /// Sets whether a call rejected by an open circuit pushes back the reset timeout, as a
+    /// failure would. Defaults to `false`, so the timeout runs from the failure that opened the
+    /// circuit.
+    ///
+    /// This suits callers for whom continued load is itself a sign the dependency should be
+    /// left alone. The circuit then only moves to Half-Open once calls have stopped arriving for
+    /// a full reset timeout. A circuit held open with
+    /// [`CircuitBreaker::force_open`](crate::CircuitBreaker::force_open) is unaffected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(5, Duration::from_secs(30))
+    ///     .extend_timeout_on_rejection(true)
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn extend_timeout_on_rejection(mut self, extend: bool) -> Self {
+        self.extend_timeout_on_rejection = extend;
+        self
+    }
+
+    /// Sets what a call made while the circuit is open does. Defaults to
+    /// [`OpenBehavior::FailFast`], which rejects it immediately.
+    ///
+    /// With [`OpenBehavior::WaitForReset`], the call blocks the calling thread for up to `max`,
+    /// as measured by the breaker's clock, in case the reset timeout elapses or the circuit is
+    /// closed in the meantime, and is then admitted or rejected as usual. A rejection is only
+    /// counted once the wait is over. This applies to `execute` and its variants as well as
+    /// [`CircuitBreaker::try_acquire`](crate::CircuitBreaker::try_acquire). Calls through the
+    /// tower layer or the reqwest middleware fail fast regardless, since waiting would block an
+    /// executor thread. Rejections for the half-open quota or the concurrency limit never wait.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, OpenBehavior};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(5, Duration::from_millis(200))
+    ///     .open_behavior(OpenBehavior::WaitForReset {
+    ///         max: Duration::from_millis(500),
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn open_behavior(mut self, behavior: OpenBehavior) -> Self {
+        self.open_behavior = behavior;
+        self
+    }
+
+    /// Sets what [`execute_catch_unwind`](crate::CircuitBreaker::execute_catch_unwind) does with
+    /// a panic once it has been recorded as a failure. Defaults to [`PanicBehavior::Return`],
+    /// which returns it as `CircuitBreakerError::OperationPanicked`; [`PanicBehavior::Resume`]
+    /// re-raises it in the caller instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, PanicBehavior};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(5, Duration::from_secs(60))
+    ///     .on_panic(PanicBehavior::Resume)
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn on_panic(mut self, behavior: PanicBehavior) -> Self {
+        self.panic_behavior = behavior;
+        self
+    }
+
+    /// Sets the state the circuit breaker starts in. Defaults to [`CircuitState::Closed`].
+    ///
+    /// A breaker starting Open behaves as if it had just tripped: calls are rejected until the
+    /// reset timeout has elapsed from construction, after which it moves to Half-Open as usual.
+    /// A breaker starting Half-Open admits trial calls straight away; the first success closes
+    /// it and the first failure opens it, exactly as after a reset timeout. In both cases the
+    /// failure counter starts at the failure threshold.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::{CircuitBreakerBuilder, CircuitState};
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreakerBuilder::new(3, Duration::from_secs(60))
+    ///     .initial_state(CircuitState::Open)
+    ///     .build()?;
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn initial_state(mut self, state: CircuitState) -> Self {
+        self.initial_state = state;
+        self
+    }
+
+    /// Sets a cheap health check used as the half-open trial instead of real traffic.
+    ///
+    /// When a call is admitted while the circuit is half-open, `probe` runs first. If it returns
+    /// `true`, the success closes the circuit and the real call proceeds. If it returns `false`,
+    /// the failure is recorded and the real call is rejected with
+    /// `CircuitBreakerError::CircuitOpen` without running. The probe runs on the calling thread,
+    /// without the breaker's lock held.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreakerBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreakerBuilder::new(3, Duration::from_secs(60))
+    ///     .half_open_probe(|| {
+    ///         // e.g. call a cheap health endpoint
+    ///         true
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn half_open_probe<F>(mut self, probe: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.half_open_probe = Some(Arc::new(probe));
+        self
+    }
+
+    /// Limits the number of trial calls admitted while the circuit is half-open.
+    ///
+    /// At most `max_calls` trials are admitted per reset timeout spent half-open; further calls
+    /// are rejected with `CircuitBreakerError::HalfOpenQuotaExceeded`, whose `retry_after` is the
+    /// time left until the quota is replenished. The quota is also replenished each time the
+    /// circuit enters the Half-Open state. The quota holds across threads: of many calls racing
+    /// as the reset timeout elapses, at most `max_calls` are admitted. By default the number of
+    /// trials is unlimited.
+    pub fn half_open_max_calls(mut self, max_calls: u32) -> Self {
+        self.half_open_max_calls = Some(max_calls);
+        self
+    }
+
+    /// Sets whether a half-open circuit runs a single trial at a time, coalescing the callers
+    /// that arrive while it is in flight. Defaults to `false`.
+    ///
+    /// While the trial runs, other calls block until it reports. They then share its outcome:
+    /// if it closed the circuit they proceed, and if it re-opened the circuit they are rejected
+    /// with `CircuitBreakerError::CircuitOpen`. If the circuit is still half-open, for example
+    /// because [`success_threshold`](Self::success_threshold) needs further trials, one of them
+    /// becomes the next trial. A trial that has not reported within the reset timeout, such as
+    /// an async call whose future was dropped before completing, is abandoned and the next
+    /// caller takes over.
+    ///
+    /// Waiting blocks the calling thread, and only coordinates callers of this breaker, not
+    /// other breakers sharing its [`StateStore`](crate::StateStore).
+    pub fn half_open_single_flight(mut self, single_flight: bool) -> Self {
+        self.half_open_single_flight = single_flight;
+        self
+    }
+
+    /// Limits the number of calls running through the breaker at once, whatever the state of
+    /// the circuit.
+    ///
+    /// A call made while `max_concurrency` calls are in flight is rejected with
+    /// `CircuitBreakerError::ConcurrencyLimit` without running, and counted as a rejected call.
+    /// A call stops counting when it returns, including by panicking. By default the number of
+    /// concurrent calls is unlimited.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Sets the fraction of the failure threshold, between 0.0 and 1.0, at which a closed
+    /// circuit is reported as [`Health::Degraded`](crate::Health::Degraded) by
+    /// [`CircuitBreaker::health`](crate::CircuitBreaker::health). Defaults to 0.5.
+    ///
+    /// With a threshold of 10 and the default fraction, a closed circuit is degraded from its
+    /// fifth failure. A circuit without failures is always reported healthy.
+    pub fn degraded_failure_fraction(mut self, fraction: f64) -> Self {
+        self.degraded_failure_fraction = fraction;
+        self
+    }
+
+    /// Sets the number of successful trial calls needed to close a half-open circuit.
+    ///
+    /// Successes are counted from the moment the circuit enters Half-Open, and a failing trial
+    /// re-opens it as usual. Defaults to 1, closing the circuit after the first successful trial.
+    /// A threshold of 0 is rejected by [`build`](Self::build).
+    pub fn success_threshold(mut self, success_threshold: u32) -> Self {
+        self.success_threshold = success_threshold;
+        self
+    }
+
+    /// Sets whether the latency of every executed call is recorded in a histogram, for
+    /// inspection with [`CircuitBreaker::latency_percentile`].
+    ///
+    /// The histogram has a fixed size and reports latencies to within about 6%. Calls that are
+    /// rejected, or whose errors the classifier ignores, are not recorded. Defaults to `false`.
+    pub fn latency_histogram(mut self, enabled: bool) -> Self {
+        self.latency_histogram = enabled;
+        self
+    }
+
+    /// Keeps the outcomes of the last `capacity` calls for inspection with
+    /// [`CircuitBreaker::recent_outcomes`].
+    ///
+    /// The history is a fixed-size ring buffer, so recording is cheap and memory use is bounded.
+    /// Defaults to 0, which records nothing.
+    pub fn outcome_history(mut self, capacity: usize) -> Self {
+        self.outcome_history = capacity;
+        self
+    }
+
+    /// Sets a callback to be executed when the circuit breaker opens.
+    ///
+    /// Unlike [`CircuitBreaker::set_on_open`], the callback is in place before the first call, so
+    /// no transition can be missed. It can still be replaced later with the setter.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreakerBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreakerBuilder::new(3, Duration::from_secs(60))
+    ///     .on_open(|| println!("Circuit opened"))
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn on_open<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_open = Some(Arc::new(move |_: Option<&str>| callback()));
+        self
+    }
+
+    /// Sets a callback to be executed when the circuit breaker closes.
+    ///
+    /// See [`on_open`](Self::on_open).
+    pub fn on_close<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_close = Some(Arc::new(move |_: Option<&str>| callback()));
+        self
+    }
+
+    /// Sets a callback to be executed when the circuit breaker transitions to half-open.
+    ///
+    /// See [`on_open`](Self::on_open).
+    pub fn on_half_open<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_half_open = Some(Arc::new(move |_: Option<&str>| callback()));
+        self
+    }
+
+    /// Sets the fraction of calls, between 0.0 and 1.0, admitted as trials while half-open.
+    ///
+    /// Each call made while the circuit is half-open is admitted with probability `sampling`;
+    /// the others are rejected with `CircuitBreakerError::CircuitOpen`. Defaults to 1.0, which
+    /// admits every call. Requires the `rand` feature.
+    #[cfg(feature = "rand")]
+    pub fn half_open_sampling(mut self, sampling: f64) -> Self {
+        self.half_open_sampling = sampling;
+        self
+    }
+
+    /// Seeds the random number generator behind randomized behavior such as
+    /// [`half_open_sampling`](Self::half_open_sampling), so that it is reproducible in tests.
+    ///
+    /// Breakers built with the same seed and fed the same calls make the same decisions on the
+    /// same platform and `rand` version; the generator's output differs between 32-bit and
+    /// 64-bit targets and may change when `rand` is upgraded. By default, the generator is
+    /// seeded from the operating system's entropy. Requires the `rand` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+    ///     .half_open_sampling(0.5)
+    ///     .rng_seed(42)
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
+    /// Builds the configured `CircuitBreaker`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] describing the first invalid option found:
+    ///
+    /// * [`ConfigError::ZeroSuccessThreshold`] for a success threshold of 0.
+    /// * [`ConfigError::ZeroHalfOpenMaxCalls`] for a half-open trial quota of 0.
+    /// * [`ConfigError::ZeroRepeatedErrorThreshold`] for a repeated-error threshold of 0.
+    /// * [`ConfigError::ZeroMaxConcurrency`] for a concurrency limit of 0.
+    /// * [`ConfigError::EmptyTimeWindow`] for a [`FailurePolicy::TimeWindow`] with a zero
+    ///   duration or no buckets.
+    /// * [`ConfigError::TimeWindowTooShort`] for a [`FailurePolicy::TimeWindow`] with more
+    ///   buckets than nanoseconds in its duration.
+    /// * [`ConfigError::ZeroSlowCallWindow`] for a [`FailurePolicy::SlowCallRate`] with a window
+    ///   of 0 calls.
+    /// * [`ConfigError::InvalidFraction`] for a failure rate, slow-call rate, half-open sampling
+    ///   or degraded failure fraction that is NaN or outside `0.0..=1.0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::{CircuitBreakerBuilder, ConfigError};
+    /// use std::time::Duration;
+    ///
+    /// let result = CircuitBreakerBuilder::new(3, Duration::from_secs(60))
+    ///     .success_threshold(0)
+    ///     .build();
+    /// assert_eq!(result.err(), Some(ConfigError::ZeroSuccessThreshold));
+    /// ```
+    pub fn build(self) -> Result<CircuitBreaker, ConfigError> {
+        self.build_typed()
+    }
+
+    /// Builds a [`TypedCircuitBreaker`] for operations with the error type `E`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`build`](Self::build).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::{CircuitBreakerBuilder, TypedCircuitBreaker};
+    /// use std::time::Duration;
+    ///
+    /// let cb: TypedCircuitBreaker<std::io::Error> =
+    ///     CircuitBreakerBuilder::new(3, Duration::from_secs(60))
+    ///         .success_threshold(2)
+    ///         .build_typed()?;
+    /// let result = cb.execute(|| Ok::<_, std::io::Error>(1));
+    /// assert_eq!(result.ok(), Some(1));
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn build_typed<E>(self) -> Result<TypedCircuitBreaker<E>, ConfigError> {
+        self.validate()?;
+        Ok(TypedCircuitBreaker::from_builder(self))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.success_threshold == 0 {
+            return Err(ConfigError::ZeroSuccessThreshold);
+        }
+        if self.half_open_max_calls == Some(0) {
+            return Err(ConfigError::ZeroHalfOpenMaxCalls);
+        }
+        if self.repeated_error_threshold == Some(0) {
+            return Err(ConfigError::ZeroRepeatedErrorThreshold);
+        }
+        if self.max_concurrency == Some(0) {
+            return Err(ConfigError::ZeroMaxConcurrency);
+        }
+        for policy in &self.failure_policies {
+            match *policy {
+                FailurePolicy::ConsecutiveFailures => {}
+                FailurePolicy::TimeWindow {
+                    duration,
+                    buckets,
+                    failure_rate,
+                } => {
+                    if duration.is_zero() || buckets == 0 {
+                        return Err(ConfigError::EmptyTimeWindow);
+                    }
+                    if buckets as u128 > duration.as_nanos() || u32::try_from(buckets).is_err() {
+                        return Err(ConfigError::TimeWindowTooShort);
+                    }
+                    if !(0.0..=1.0).contains(&failure_rate) {
+                        return Err(ConfigError::InvalidFraction {
+                            name: "failure_rate",
+                        });
+                    }
+                }
+                FailurePolicy::SlowCallRate { rate, window, .. } => {
+                    if window == 0 {
+                        return Err(ConfigError::ZeroSlowCallWindow);
+                    }
+                    if !(0.0..=1.0).contains(&rate) {
+                        return Err(ConfigError::InvalidFraction {
+                            name: "slow_call_rate",
+                        });
+                    }
+                }
+            }
+        }
+        if !(0.0..=1.0).contains(&self.degraded_failure_fraction) {
+            return Err(ConfigError::InvalidFraction {
+                name: "degraded_failure_fraction",
+            });
+        }
+        #[cfg(feature = "rand")]
+        if !(0.0..=1.0).contains(&self.half_open_sampling) {
+            return Err(ConfigError::InvalidFraction {
+                name: "half_open_sampling",
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for CircuitBreakerBuilder {
+    /// Creates a builder with a failure threshold of [`DEFAULT_FAILURE_THRESHOLD`] (5) and a reset
+    /// timeout of [`DEFAULT_RESET_TIMEOUT`] (30 seconds).
+    fn default() -> Self {
+        CircuitBreakerBuilder::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_RESET_TIMEOUT)
+    }
+}
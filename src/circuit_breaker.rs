@@ -1,8 +1,46 @@
-use std::sync::{Arc, Mutex};
+use std::any::{Any, TypeId};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::iter;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "async")]
+use std::sync::Weak;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
 use std::time::{Duration, Instant};
 
-use crate::circuit_state::CircuitState;
-use crate::error::CircuitBreakerError;
+#[cfg(feature = "arc-swap")]
+use arc_swap::ArcSwap;
+#[cfg(feature = "rand")]
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::builder::CircuitBreakerBuilder;
+use crate::circuit_state::{CircuitState, Health, StateChange, StateDurations};
+use crate::clock::Clock;
+use crate::config::CircuitConfig;
+use crate::dispatcher::{self, CallbackDispatcher};
+use crate::error::{BreakerError, CircuitBreakerError, ConfigError, RejectionReason};
+use crate::history::{LatencyHistogram, Outcome, OutcomeHistory};
+use crate::metrics::{CircuitMetrics, MetricsRecorder};
+use crate::policy::{
+    ClosedResetMode, FailureAction, FailurePolicy, FailureReason, OpenBehavior, PanicBehavior,
+    SlowCallWindow, TimeWindowCounter,
+};
+use crate::prober::Prober;
+#[cfg(feature = "async")]
+use crate::signal::{Signal, SignalFuture};
+use crate::state_machine::StateMachine;
+use crate::store::{StateStore, StoredState};
+use crate::typed::{TypedClassifier, Untyped};
 
 /// A circuit breaker that can be used to detect failures and encapsulate the logic of preventing a failure from constantly recurring.
 ///
@@ -10,27 +48,347 @@ use crate::error::CircuitBreakerError;
 /// - Closed: Requests are allowed through.
 /// - Open: Requests are not allowed through.
 /// - Half-Open: A limited number of requests are allowed through to test the system.
-pub struct CircuitBreaker {
-    failure_threshold: u32,
-    reset_timeout: Duration,
+///
+/// `CircuitBreaker` is `Send` and `Sync`, and every method takes `&self`, so a single breaker can
+/// be shared between threads by reference (for example with `std::thread::scope`) or by wrapping
+/// it in an `Arc`. No external locking is needed: each call checks and updates the state
+/// atomically with respect to other calls.
+///
+/// The internal state is guarded by a mutex. State-change callbacks run while it is held, but a
+/// panicking callback is caught, and passed to the hook set with
+/// [`CircuitBreakerBuilder::on_callback_panic`] if there is one, rather than unwinding through
+/// the breaker. If a panic does poison the mutex (for example one raised by a [`StateStore`]), the
+/// breaker recovers the guard and keeps working, although the state
+/// may be slightly inconsistent with respect to the operation that panicked.
+///
+/// The type parameter is the error type of the operations the breaker runs. With the default,
+/// [`Untyped`], the breaker is the type-erased [`CircuitBreaker`], whose `execute` methods accept
+/// any error type and return errors boxed. A `TypedCircuitBreaker<E>` for a concrete error type
+/// instead runs operations returning `E` through [`execute`](TypedCircuitBreaker::execute), which
+/// returns their errors unboxed and classifies them with a classifier taking `&E`. Every other
+/// method is shared by both.
+pub struct TypedCircuitBreaker<E = Untyped> {
+    failure_decay: Option<Duration>,
+    recorder: Box<dyn MetricsRecorder>,
+    pub(crate) clock: Arc<dyn Clock>,
+    failure_policies: Vec<FailurePolicy>,
+    error_classifier: Option<ErrorClassifier>,
+    trip_on: RwLock<Vec<(TypeId, ErrorMatcher)>>,
+    half_open_max_calls: Option<u32>,
+    half_open_single_flight: bool,
+    success_threshold: u32,
+    history_capacity: usize,
+    latency_histogram: bool,
+    /// Whether a failure policy needs the latency of every call.
+    tracks_slow_calls: bool,
+    minimum_throughput: u32,
+    repeated_error_threshold: Option<u32>,
+    max_open_duration: Option<Duration>,
+    warmup: Option<Duration>,
+    /// When the breaker was built, by its clock, which the warmup period is measured from.
+    created_at: Instant,
+    probation: Option<(Duration, u32)>,
+    store: Option<Arc<dyn StateStore>>,
+    unreported_permit_is_failure: bool,
+    count_forced_trips: bool,
+    extend_timeout_on_rejection: bool,
+    open_behavior: OpenBehavior,
+    panic_behavior: PanicBehavior,
+    half_open_probe: Option<Probe>,
+    closed_reset_mode: ClosedResetMode,
+    dispatcher: Option<CallbackDispatcher>,
+    on_callback_panic: Option<CallbackPanicHook>,
+    callback_min_interval: Option<Duration>,
+    #[cfg(feature = "rand")]
+    half_open_sampling: f64,
     state: Arc<Mutex<CircuitBreakerState>>,
+    /// A copy of the current state for wait-free reads, updated with the lock held.
+    #[cfg(feature = "arc-swap")]
+    current_state: ArcSwap<CircuitState>,
+    max_concurrency: Option<usize>,
+    in_flight: AtomicUsize,
+    degraded_failure_fraction: f64,
+    closed: Condvar,
+    /// Notified when a single-flight half-open trial finishes.
+    trial_finished: Condvar,
+    /// Notified when the circuit moves from Open to another state.
+    left_open: Condvar,
+    prober: Mutex<Option<Prober>>,
+    /// Decides what an error returned by [`execute`](TypedCircuitBreaker::execute) does to the
+    /// circuit, for typed breakers.
+    pub(crate) typed_classifier: Option<TypedClassifier<E>>,
 }
 
+/// A circuit breaker for operations with any error type, which it returns boxed.
+///
+/// This is the type-erased form of [`TypedCircuitBreaker`].
+pub type CircuitBreaker = TypedCircuitBreaker<Untyped>;
+
 struct CircuitBreakerState {
+    /// The state, failure counter and last failure time, with the threshold and reset timeout
+    /// they are checked against.
+    machine: StateMachine<Instant>,
+    last_success_time: Option<Instant>,
+    on_open: Option<Callback>,
+    on_close: Option<Callback>,
+    on_half_open: Option<Callback>,
+    transition_guard: Option<TransitionGuard>,
+    /// When the open, close and half-open callbacks last ran, for `callback_min_interval`.
+    callbacks_fired_at: [Option<Instant>; 3],
+    /// The label of the last open, close and half-open transition whose callback was suppressed
+    /// by `callback_min_interval` and has not run since.
+    callbacks_pending: [Option<Option<String>>; 3],
+    subscribers: Vec<Sender<StateChange>>,
+    #[cfg(feature = "async")]
+    open_signals: Vec<Weak<Signal>>,
+    cached_result: Option<Box<dyn Any + Send>>,
+    /// The rolling window of each failure policy, in the same order, for rate-based policies.
+    windows: Vec<Option<TimeWindowCounter>>,
+    /// The slow-call window of each failure policy, in the same order, for slow-call policies.
+    slow_calls: Vec<Option<SlowCallWindow>>,
+    total_successes: u64,
+    total_failures: u64,
+    rejected_calls: u64,
+    /// Why the most recent call was rejected, for `last_rejection_reason`.
+    last_rejection: Option<RejectionReason>,
+    /// How many times the circuit has opened, for `trip_count`.
+    trip_count: u64,
+    /// The number of successes since the last failure, for `consecutive_successes`.
+    consecutive_successes: u64,
+    failure_reasons: BTreeMap<FailureReason, u64>,
+    half_open_since: Instant,
+    half_open_calls: u32,
+    half_open_successes: u32,
+    /// Whether a single-flight trial is running, and a count of trials that identifies it.
+    trial_in_flight: bool,
+    trial_generation: u64,
+    /// Trials reserved with `try_admit_trial` and not yet reported, each counted as in flight.
+    trials_reserved: usize,
+    history: OutcomeHistory,
+    latencies: Option<LatencyHistogram>,
+    last_fingerprint: Option<u64>,
+    fingerprint_repeats: u32,
+    /// When a half-open trial last closed the circuit, which probation is measured from.
+    recovered_at: Option<Instant>,
+    state_entered_at: Instant,
+    durations: StateDurations,
+    #[cfg(feature = "rand")]
+    rng: SmallRng,
+}
+
+impl CircuitBreakerState {
+    /// Returns the fields shared through a [`StateStore`].
+    fn stored(&self) -> StoredState {
+        StoredState {
+            state: self.machine.state(),
+            failures: self.machine.failures(),
+            last_failure_time: self.machine.last_failure(),
+        }
+    }
+
+    /// Replaces the fields shared through a [`StateStore`].
+    fn restore(&mut self, stored: StoredState) {
+        self.machine.set_state(stored.state);
+        self.machine.set_failures(stored.failures);
+        self.machine.set_last_failure(stored.last_failure_time);
+    }
+}
+
+/// The locked state of a breaker. With a [`StateStore`] configured, the shared fields are loaded
+/// from the store when the lock is taken and written back, if they changed, before it is
+/// released.
+struct StateGuard<'a> {
+    guard: MutexGuard<'a, CircuitBreakerState>,
+    store: Option<&'a dyn StateStore>,
+    loaded: Option<StoredState>,
+}
+
+impl Deref for StateGuard<'_> {
+    type Target = CircuitBreakerState;
+
+    fn deref(&self) -> &CircuitBreakerState {
+        &self.guard
+    }
+}
+
+impl DerefMut for StateGuard<'_> {
+    fn deref_mut(&mut self) -> &mut CircuitBreakerState {
+        &mut self.guard
+    }
+}
+
+impl Drop for StateGuard<'_> {
+    fn drop(&mut self) {
+        if let (Some(store), Some(loaded)) = (self.store, self.loaded) {
+            let stored = self.guard.stored();
+            if stored != loaded {
+                store.store(stored);
+            }
+        }
+    }
+}
+
+/// The outcome of a call made through [`CircuitBreaker::execute_with_outcome`].
+pub struct ExecOutcome<T> {
+    /// The result of the call, as [`CircuitBreaker::execute`] would have returned it.
+    pub result: Result<T, Box<dyn std::error::Error>>,
+    /// Whether the call was recorded as a failure against the circuit. This is `false` for
+    /// successes, rejected calls, and errors the error classifier chose to ignore.
+    pub counted: bool,
+}
+
+/// Permission to make one call through a [`CircuitBreaker`], returned by
+/// [`CircuitBreaker::try_acquire`].
+///
+/// Report the outcome of the call with [`report_success`](Self::report_success) or
+/// [`report_failure`](Self::report_failure). A permit dropped without a report counts as a
+/// success, or as a failure if the breaker was built with
+/// `CircuitBreakerBuilder::unreported_permit_is_failure(true)`. A permit dropped while its thread
+/// is panicking always counts as a failure.
+pub struct CallPermit<'a, E = Untyped> {
+    breaker: &'a TypedCircuitBreaker<E>,
+    admission: Admission,
+    reported: bool,
+    _in_flight: InFlight<&'a TypedCircuitBreaker<E>, E>,
+}
+
+impl<E> CallPermit<'_, E> {
+    /// Returns the state the call was admitted in.
+    pub fn state(&self) -> CircuitState {
+        self.admission.state
+    }
+
+    /// Reports that the call succeeded.
+    pub fn report_success(mut self) {
+        self.report(true);
+    }
+
+    /// Reports that the call failed.
+    pub fn report_failure(mut self) {
+        self.report(false);
+    }
+
+    fn report(&mut self, success: bool) {
+        self.reported = true;
+        self.breaker.complete(self.admission, success, None);
+    }
+}
+
+impl<E> Drop for CallPermit<'_, E> {
+    fn drop(&mut self) {
+        if !self.reported {
+            let success = !std::thread::panicking() && !self.breaker.unreported_permit_is_failure;
+            self.report(success);
+        }
+    }
+}
+
+/// Returns the position of the callback for transitions to `to` in the per-callback arrays.
+fn callback_index(to: CircuitState) -> usize {
+    match to {
+        CircuitState::Open => 0,
+        CircuitState::Closed => 1,
+        CircuitState::HalfOpen => 2,
+    }
+}
+
+/// Returns the callback for transitions to `to`.
+fn callback_for(state: &CircuitBreakerState, to: CircuitState) -> Option<&Callback> {
+    match to {
+        CircuitState::Open => state.on_open.as_ref(),
+        CircuitState::Closed => state.on_close.as_ref(),
+        CircuitState::HalfOpen => state.on_half_open.as_ref(),
+    }
+}
+
+/// The longest a call waiting for the circuit to leave Open sleeps before re-reading the clock.
+const WAIT_FOR_RESET_RECHECK: Duration = Duration::from_millis(10);
+
+/// Decides whether an error returned by an operation counts as a failure.
+pub(crate) type ErrorClassifier =
+    Arc<dyn Fn(&(dyn std::error::Error + 'static)) -> FailureAction + Send + Sync>;
+
+/// Tests whether an error is of a type registered with [`CircuitBreaker::trip_on`].
+type ErrorMatcher = fn(&(dyn std::error::Error + 'static)) -> bool;
+
+/// A health check run in place of a real call while the circuit is half-open.
+pub(crate) type Probe = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Decides whether the circuit may move from the first state to the second.
+type TransitionGuard = Arc<dyn Fn(CircuitState, CircuitState) -> bool + Send + Sync>;
+
+/// A state-change callback, receiving the label of the call that caused the transition.
+pub(crate) type Callback = Arc<dyn Fn(Option<&str>) + Send + Sync>;
+
+/// Receives the state a panicking callback was run for and the panic payload.
+pub(crate) type CallbackPanicHook = Arc<dyn Fn(CircuitState, &(dyn Any + Send)) + Send + Sync>;
+
+/// A call admitted by [`CircuitBreaker::acquire`]: the state it was admitted in, when it
+/// started and, if it is a single-flight half-open trial, the generation identifying the trial.
+#[derive(Clone, Copy)]
+pub(crate) struct Admission {
     state: CircuitState,
-    failures: u32,
-    last_failure_time: Option<Instant>,
-    on_open: Option<Arc<dyn Fn() + Send + Sync>>,
-    on_close: Option<Arc<dyn Fn() + Send + Sync>>,
-    on_half_open: Option<Arc<dyn Fn() + Send + Sync>>,
+    started: Instant,
+    trial: Option<u64>,
 }
 
-impl CircuitBreaker {
+/// A call counted as in flight through a breaker until dropped, which also happens when the call
+/// panics or its future is cancelled.
+pub(crate) struct InFlight<B: Deref<Target = TypedCircuitBreaker<E>>, E = Untyped> {
+    breaker: B,
+    trial: Option<u64>,
+    _error: PhantomData<fn() -> E>,
+}
+
+impl<B: Deref<Target = TypedCircuitBreaker<E>>, E> InFlight<B, E> {
+    /// Returns the breaker the call is running through.
+    pub(crate) fn breaker(&self) -> &TypedCircuitBreaker<E> {
+        &self.breaker
+    }
+
+    /// Ties the single-flight trial `admission` holds, if any, to this call, so that the trial
+    /// is released when the call is dropped without its outcome having been recorded.
+    pub(crate) fn hold_trial(&mut self, admission: &Admission) {
+        self.trial = admission.trial;
+    }
+}
+
+impl<B: Deref<Target = TypedCircuitBreaker<E>>, E> Drop for InFlight<B, E> {
+    fn drop(&mut self) {
+        if let Some(generation) = self.trial {
+            self.breaker.release_trial(generation);
+        }
+        self.breaker.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// One or more failures recorded together, as a sharded breaker flushes them.
+struct Failures {
+    /// The combined weight added to the failure counter.
+    weight: u32,
+    /// The number of failed calls, added to `total_failures` and reported to the recorder.
+    count: u32,
+    /// Identifies the error for the repeated-error check.
+    fingerprint: Option<u64>,
+    /// Counted in the per-reason breakdown returned by `metrics`.
+    reason: Option<FailureReason>,
+    /// Opens the circuit whatever the failure policy says.
+    force_open: bool,
+    /// Whether the failures come from calls that skipped admission.
+    bypass: bool,
+}
+
+/// A change of state, as `(from, to)`.
+type Transition = (CircuitState, CircuitState);
+
+impl<E> TypedCircuitBreaker<E> {
     /// Creates a new `CircuitBreaker` with the specified failure threshold and reset timeout.
     ///
     /// # Arguments
     ///
     /// * `failure_threshold` - The number of failures that must occur before the circuit breaker opens.
+    ///   A threshold of `0` means the circuit never opens automatically and is only controlled
+    ///   through [`force_open`](Self::force_open) and [`force_close`](Self::force_close).
     /// * `reset_timeout` - The duration after which the circuit breaker will transition from Open to Half-Open.
     ///
     /// # Example
@@ -42,173 +400,291 @@ impl CircuitBreaker {
     /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
     /// ```
     pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
-        CircuitBreaker {
-            failure_threshold,
-            reset_timeout,
-            state: Arc::new(Mutex::new(CircuitBreakerState {
-                state: CircuitState::Closed,
-                failures: 0,
-                last_failure_time: None,
-                on_open: None,
-                on_close: None,
-                on_half_open: None,
-            })),
-        }
+        // Every failure threshold and reset timeout is valid, so there is nothing to validate.
+        Self::from_builder(CircuitBreakerBuilder::new(failure_threshold, reset_timeout))
     }
 
-    /// Executes the given function within the circuit breaker.
+    /// Returns a [`CircuitBreakerBuilder`] for configuring additional options.
     ///
-    /// If the circuit is Open, this method will return an error without executing the function.
-    /// If the circuit is Half-Open, it will allow the function to execute and transition to Closed on success.
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreaker;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+    ///     .failure_decay(Duration::from_secs(300))
+    ///     .build()?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn builder(failure_threshold: u32, reset_timeout: Duration) -> CircuitBreakerBuilder {
+        CircuitBreakerBuilder::new(failure_threshold, reset_timeout)
+    }
+
+    /// Creates a new `CircuitBreaker` from a [`CircuitConfig`].
     ///
     /// # Arguments
     ///
-    /// * `f` - A function that returns a `Result`.
+    /// * `config` - The options for the new breaker.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// Returns the result of the function if successful, or a `CircuitBreakerError` if the circuit is open.
+    /// Returns a [`ConfigError`] if the config is invalid, as described for
+    /// [`CircuitBreakerBuilder::build`].
     ///
     /// # Example
     ///
     /// ```
-    /// # use circuit_breaker::CircuitBreaker;
-    /// # use std::time::Duration;
-    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-    /// let result = cb.execute(|| {
-    ///     // Simulating an operation that might fail
-    ///     Ok::<_, std::io::Error>("Operation successful")
-    /// });
+    /// use circuit_breaker::{CircuitBreaker, CircuitConfig};
+    /// use std::time::Duration;
+    ///
+    /// let config = CircuitConfig {
+    ///     failure_threshold: 3,
+    ///     reset_timeout: Duration::from_secs(60),
+    ///     ..CircuitConfig::default()
+    /// };
+    /// let cb = CircuitBreaker::with_config(config)?;
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
     /// ```
-    pub fn execute<F, T, E>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
-    where
-        F: FnOnce() -> Result<T, E>,
-        E: std::error::Error + 'static,
-    {
-        let mut state = self.state.lock().unwrap();
-
-        match state.state {
-            CircuitState::Open => {
-                if let Some(last_failure_time) = state.last_failure_time {
-                    if last_failure_time.elapsed() >= self.reset_timeout {
-                        state.state = CircuitState::HalfOpen;
-                        if let Some(ref callback) = state.on_half_open {
-                            callback();
-                        }
-                    } else {
-                        return Err(Box::new(CircuitBreakerError::CircuitOpen));
-                    }
-                }
-            }
-            CircuitState::Closed | CircuitState::HalfOpen => {}
-        }
+    pub fn with_config(config: CircuitConfig) -> Result<Self, ConfigError> {
+        CircuitBreakerBuilder::from(config).build_typed()
+    }
 
-        let current_state = state.state;
-        drop(state);
+    /// Creates a new `CircuitBreaker` configured from environment variables.
+    ///
+    /// See [`CircuitConfig::from_env`] for the variables read and how they are parsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Prepended as is to each variable name, for example `"CB_"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::InvalidEnvVar`] if a variable holds a malformed value, or another
+    /// [`ConfigError`] if the resulting configuration is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreaker;
+    ///
+    /// std::env::set_var("INVENTORY_CB_FAILURE_THRESHOLD", "3");
+    /// let cb = CircuitBreaker::from_env("INVENTORY_CB_")?;
+    /// assert_eq!(cb.failure_threshold(), 3);
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn from_env(prefix: &str) -> Result<Self, ConfigError> {
+        CircuitConfig::from_env(prefix).and_then(Self::with_config)
+    }
 
-        match f() {
-            Ok(result) => {
-                if current_state == CircuitState::HalfOpen {
-                    self.handle_success();
-                }
-                Ok(result)
-            }
-            Err(e) => {
-                self.handle_failure();
-                Err(Box::new(e))
-            }
+    pub(crate) fn from_builder(builder: CircuitBreakerBuilder) -> Self {
+        // A breaker starting Open or Half-Open is seeded as if it had just tripped, so a
+        // failing trial re-opens it just as it would after a real trip.
+        let now = builder.clock.now();
+        let (failures, last_failure_time) = match builder.initial_state {
+            CircuitState::Closed => (0, None),
+            CircuitState::Open => (builder.failure_threshold, Some(now)),
+            CircuitState::HalfOpen => (builder.failure_threshold, None),
+        };
+        let mut machine = StateMachine::with_tick(builder.failure_threshold, builder.reset_timeout);
+        machine.set_state(builder.initial_state);
+        machine.set_failures(failures);
+        machine.set_last_failure(last_failure_time);
+        let windows = builder
+            .failure_policies
+            .iter()
+            .map(|policy| match *policy {
+                FailurePolicy::ConsecutiveFailures => None,
+                FailurePolicy::TimeWindow {
+                    duration, buckets, ..
+                } => Some(TimeWindowCounter::new(duration, buckets)),
+                FailurePolicy::SlowCallRate { .. } => None,
+            })
+            .collect();
+        let slow_calls: Vec<_> = builder
+            .failure_policies
+            .iter()
+            .map(|policy| match *policy {
+                FailurePolicy::SlowCallRate {
+                    threshold,
+                    rate,
+                    window,
+                } => Some(SlowCallWindow::new(threshold, rate, window)),
+                _ => None,
+            })
+            .collect();
+        TypedCircuitBreaker {
+            failure_decay: builder.failure_decay,
+            recorder: builder.metrics_recorder,
+            clock: builder.clock,
+            failure_policies: builder.failure_policies,
+            error_classifier: builder.error_classifier,
+            trip_on: RwLock::new(Vec::new()),
+            half_open_max_calls: builder.half_open_max_calls,
+            half_open_single_flight: builder.half_open_single_flight,
+            success_threshold: builder.success_threshold,
+            history_capacity: builder.outcome_history,
+            latency_histogram: builder.latency_histogram,
+            tracks_slow_calls: slow_calls.iter().any(Option::is_some),
+            minimum_throughput: builder.minimum_throughput,
+            repeated_error_threshold: builder.repeated_error_threshold,
+            max_open_duration: builder.max_open_duration,
+            warmup: builder.warmup,
+            probation: builder.probation,
+            created_at: now,
+            store: builder.state_store,
+            unreported_permit_is_failure: builder.unreported_permit_is_failure,
+            count_forced_trips: builder.count_forced_trips,
+            extend_timeout_on_rejection: builder.extend_timeout_on_rejection,
+            open_behavior: builder.open_behavior,
+            panic_behavior: builder.panic_behavior,
+            half_open_probe: builder.half_open_probe,
+            closed_reset_mode: builder.closed_reset_mode,
+            dispatcher: builder
+                .async_callbacks
+                .then(|| CallbackDispatcher::spawn(builder.on_callback_panic.clone())),
+            on_callback_panic: builder.on_callback_panic,
+            callback_min_interval: builder.callback_min_interval,
+            #[cfg(feature = "rand")]
+            half_open_sampling: builder.half_open_sampling,
+            state: Arc::new(Mutex::new(CircuitBreakerState {
+                machine,
+                last_success_time: None,
+                on_open: builder.on_open,
+                on_close: builder.on_close,
+                on_half_open: builder.on_half_open,
+                transition_guard: None,
+                callbacks_fired_at: [None; 3],
+                callbacks_pending: [None, None, None],
+                subscribers: Vec::new(),
+                #[cfg(feature = "async")]
+                open_signals: Vec::new(),
+                cached_result: None,
+                total_successes: 0,
+                total_failures: 0,
+                rejected_calls: 0,
+                last_rejection: None,
+                trip_count: 0,
+                consecutive_successes: 0,
+                failure_reasons: BTreeMap::new(),
+                half_open_since: now,
+                half_open_calls: 0,
+                half_open_successes: 0,
+                trial_in_flight: false,
+                trial_generation: 0,
+                trials_reserved: 0,
+                history: OutcomeHistory::new(builder.outcome_history),
+                latencies: builder.latency_histogram.then(LatencyHistogram::new),
+                last_fingerprint: None,
+                fingerprint_repeats: 0,
+                recovered_at: None,
+                state_entered_at: now,
+                durations: StateDurations::default(),
+                windows,
+                slow_calls,
+                #[cfg(feature = "rand")]
+                rng: builder
+                    .rng_seed
+                    .map_or_else(SmallRng::from_entropy, SmallRng::seed_from_u64),
+            })),
+            #[cfg(feature = "arc-swap")]
+            current_state: ArcSwap::from_pointee(builder.initial_state),
+            max_concurrency: builder.max_concurrency,
+            in_flight: AtomicUsize::new(0),
+            degraded_failure_fraction: builder.degraded_failure_fraction,
+            closed: Condvar::new(),
+            trial_finished: Condvar::new(),
+            left_open: Condvar::new(),
+            prober: Mutex::new(None),
+            typed_classifier: None,
         }
     }
 
-    /// Returns the current state of the circuit breaker.
+    /// Returns the number of failures that must occur before the circuit breaker opens.
     ///
-    /// This method may transition the state from Open to Half-Open if the reset timeout has elapsed.
+    /// # Example
     ///
-    /// # Returns
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// assert_eq!(cb.failure_threshold(), 3);
+    /// ```
+    pub fn failure_threshold(&self) -> u32 {
+        self.lock().machine.failure_threshold()
+    }
+
+    /// Updates the failure threshold used for future trip decisions.
     ///
-    /// Returns the current `CircuitState`.
+    /// The new threshold is applied the next time a failure is recorded: lowering it below the
+    /// current failure count does not open the circuit by itself, but the next failure does. A
+    /// threshold of `0` disables automatic tripping, as it does when building the breaker.
     ///
     /// # Example
     ///
     /// ```
     /// # use circuit_breaker::{CircuitBreaker, CircuitState};
     /// # use std::time::Duration;
-    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let cb = CircuitBreaker::new(5, Duration::from_secs(60));
+    /// cb.handle_failure();
+    /// cb.handle_failure();
+    ///
+    /// cb.set_failure_threshold(2);
     /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// cb.handle_failure();
+    /// assert_eq!(cb.state(), CircuitState::Open);
     /// ```
-    pub fn state(&self) -> CircuitState {
-        let mut state = self.state.lock().unwrap();
-        if state.state == CircuitState::Open {
-            if let Some(last_failure_time) = state.last_failure_time {
-                if last_failure_time.elapsed() >= self.reset_timeout {
-                    state.state = CircuitState::HalfOpen;
-                    if let Some(ref callback) = state.on_half_open {
-                        callback();
-                    }
-                }
-            }
-        }
-        state.state
+    pub fn set_failure_threshold(&self, failure_threshold: u32) {
+        self.lock().machine.set_failure_threshold(failure_threshold);
     }
 
-    /// Handles a failure, incrementing the failure counter and potentially opening the circuit.
+    /// Returns the duration after which the circuit breaker will transition from Open to Half-Open.
     ///
     /// # Example
     ///
     /// ```
     /// # use circuit_breaker::CircuitBreaker;
     /// # use std::time::Duration;
-    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-    /// cb.handle_failure();
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// assert_eq!(cb.reset_timeout(), Duration::from_secs(60));
     /// ```
-    pub fn handle_failure(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.failures += 1;
-        state.last_failure_time = Some(Instant::now());
-
-        if state.failures >= self.failure_threshold {
-            self.trip(&mut state);
-        }
+    pub fn reset_timeout(&self) -> Duration {
+        self.lock().machine.reset_timeout()
     }
 
-    /// Handles a success, potentially closing the circuit if it was half-open.
+    /// Updates the reset timeout used for future Open to Half-Open checks.
+    ///
+    /// The new timeout takes effect immediately, including for a circuit that is already open:
+    /// it is measured from the last recorded failure, so shortening it can move an open circuit
+    /// to Half-Open on the next check.
     ///
     /// # Example
     ///
     /// ```
     /// # use circuit_breaker::CircuitBreaker;
     /// # use std::time::Duration;
-    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-    /// cb.handle_success();
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_reset_timeout(Duration::from_secs(10));
+    /// assert_eq!(cb.reset_timeout(), Duration::from_secs(10));
     /// ```
-    pub fn handle_success(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.failures = 0;
-        if state.state == CircuitState::HalfOpen {
-            self.reset(&mut state);
-        }
-    }
-
-    fn trip(&self, state: &mut CircuitBreakerState) {
-        state.state = CircuitState::Open;
-        if let Some(ref callback) = state.on_open {
-            callback();
-        }
-    }
-
-    fn reset(&self, state: &mut CircuitBreakerState) {
-        state.state = CircuitState::Closed;
-        state.failures = 0;
-        if let Some(ref callback) = state.on_close {
-            callback();
-        }
+    pub fn set_reset_timeout(&self, reset_timeout: Duration) {
+        self.lock().machine.set_reset_timeout(reset_timeout);
     }
+}
 
-    /// Sets a callback function to be executed when the circuit breaker opens.
+impl CircuitBreaker {
+    /// Executes the given function within the circuit breaker.
+    ///
+    /// If the circuit is Open, this method will return an error without executing the function.
+    /// If the circuit is Half-Open, it will allow the function to execute and transition to Closed on success.
     ///
     /// # Arguments
     ///
-    /// * `callback` - A function to be called when the circuit opens.
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the result of the function if successful, or a `CircuitBreakerError` if the circuit is open.
     ///
     /// # Example
     ///
@@ -216,23 +692,61 @@ impl CircuitBreaker {
     /// # use circuit_breaker::CircuitBreaker;
     /// # use std::time::Duration;
     /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-    /// cb.set_on_open(|| {
-    ///     println!("Circuit opened!");
+    /// let result = cb.execute(|| {
+    ///     // Simulating an operation that might fail
+    ///     Ok::<_, std::io::Error>("Operation successful")
     /// });
     /// ```
-    pub fn set_on_open<F>(&self, callback: F)
+    pub fn execute<F, T, E>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
     where
-        F: Fn() + Send + Sync + 'static,
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
     {
-        let mut state = self.state.lock().unwrap();
-        state.on_open = Some(Arc::new(callback));
+        self.run(None, f).result
     }
 
-    /// Sets a callback function to be executed when the circuit breaker closes.
+    /// Executes the given function within the circuit breaker, reporting whether an error
+    /// counted against the circuit.
+    ///
+    /// This behaves like [`execute`](Self::execute), but returns an [`ExecOutcome`] whose
+    /// `counted` flag tells whether the call was recorded as a failure. This is useful together
+    /// with an error classifier to log errors the breaker ignored.
     ///
     /// # Arguments
     ///
-    /// * `callback` - A function to be called when the circuit closes.
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+    ///     .error_classifier(|e| !e.is::<std::fmt::Error>())
+    ///     .build()?;
+    ///
+    /// let outcome = cb.execute_with_outcome(|| Err::<(), _>(std::fmt::Error));
+    /// assert!(outcome.result.is_err());
+    /// assert!(!outcome.counted);
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn execute_with_outcome<F, T, E>(&self, f: F) -> ExecOutcome<T>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        self.run(None, f)
+    }
+
+    /// Executes a reusable, mutable function within the circuit breaker.
+    ///
+    /// This behaves like [`execute`](Self::execute), but borrows an `FnMut` so the same closure
+    /// can be passed to the breaker repeatedly, for example from a retry loop. The internal lock
+    /// is released before `f` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A mutable reference to a function that returns a `Result`.
     ///
     /// # Example
     ///
@@ -240,23 +754,34 @@ impl CircuitBreaker {
     /// # use circuit_breaker::CircuitBreaker;
     /// # use std::time::Duration;
     /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-    /// cb.set_on_close(|| {
-    ///     println!("Circuit closed!");
-    /// });
+    /// let mut attempts = 0;
+    /// let mut operation = || {
+    ///     attempts += 1;
+    ///     Ok::<_, std::io::Error>(attempts)
+    /// };
+    /// cb.execute_mut(&mut operation).unwrap();
+    /// cb.execute_mut(&mut operation).unwrap();
+    /// assert_eq!(attempts, 2);
     /// ```
-    pub fn set_on_close<F>(&self, callback: F)
+    pub fn execute_mut<F, T, E>(&self, f: &mut F) -> Result<T, Box<dyn std::error::Error>>
     where
-        F: Fn() + Send + Sync + 'static,
+        F: FnMut() -> Result<T, E>,
+        E: std::error::Error + 'static,
     {
-        let mut state = self.state.lock().unwrap();
-        state.on_close = Some(Arc::new(callback));
+        self.execute(f)
     }
 
-    /// Sets a callback function to be executed when the circuit breaker transitions to half-open.
+    /// Executes the given function within the circuit breaker, retrying it on failure.
+    ///
+    /// The function is attempted once and then retried up to `max_retries` times, sleeping for
+    /// `backoff` between attempts. Every failed attempt is recorded as a failure, and retrying
+    /// stops as soon as the circuit is open, returning the last error.
     ///
     /// # Arguments
     ///
-    /// * `callback` - A function to be called when the circuit transitions to half-open.
+    /// * `max_retries` - The maximum number of retries after the first attempt.
+    /// * `backoff` - The duration to wait between attempts.
+    /// * `f` - A function that returns a `Result`.
     ///
     /// # Example
     ///
@@ -264,15 +789,2882 @@ impl CircuitBreaker {
     /// # use circuit_breaker::CircuitBreaker;
     /// # use std::time::Duration;
     /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-    /// cb.set_on_half_open(|| {
-    ///     println!("Circuit is half-open!");
+    /// let result = cb.execute_with_retry(2, Duration::from_millis(10), || {
+    ///     Ok::<_, std::io::Error>("Operation successful")
     /// });
     /// ```
-    pub fn set_on_half_open<F>(&self, callback: F)
+    pub fn execute_with_retry<F, T, E>(
+        &self,
+        max_retries: u32,
+        backoff: Duration,
+        mut f: F,
+    ) -> Result<T, Box<dyn std::error::Error>>
     where
-        F: Fn() + Send + Sync + 'static,
+        F: FnMut() -> Result<T, E>,
+        E: std::error::Error + 'static,
     {
-        let mut state = self.state.lock().unwrap();
-        state.on_half_open = Some(Arc::new(callback));
+        let mut attempt = 0;
+        loop {
+            match self.execute_mut(&mut f) {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    if attempt >= max_retries || self.state() == CircuitState::Open {
+                        return Err(e);
+                    }
+                }
+            }
+            attempt += 1;
+            std::thread::sleep(backoff);
+        }
     }
-}
\ No newline at end of file
+
+    /// Executes the given function within the circuit breaker, falling back to the last
+    /// successful result while the circuit is open.
+    ///
+    /// Every `Ok` value returned by `f` is cloned into a cache held in the breaker's state. When
+    /// the circuit is open, a clone of the cached value is returned instead of an error. The cache
+    /// is only populated by successful calls made through this method; if it is empty (or holds a
+    /// value of a different type), the usual `CircuitBreakerError::CircuitOpen` is returned. This
+    /// is intended for idempotent reads where a stale value is better than none. Other
+    /// rejections, such as `HalfOpenQuotaExceeded` or `ConcurrencyLimit`, are returned unchanged
+    /// since the circuit is not open.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.execute_cached(|| Ok::<_, std::io::Error>(String::from("fresh"))).unwrap();
+    /// cb.handle_failure();
+    ///
+    /// // The circuit is open, so the last good value is returned.
+    /// let value = cb.execute_cached(|| Ok::<_, std::io::Error>(String::from("unused")));
+    /// assert_eq!(value.unwrap(), "fresh");
+    /// ```
+    pub fn execute_cached<F, T, E>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        T: Clone + Send + 'static,
+        E: std::error::Error + 'static,
+    {
+        let (_in_flight, admission) = match self.admit(None) {
+            Ok(admitted) => admitted,
+            Err(e @ CircuitBreakerError::CircuitOpen { .. }) => {
+                let state = self.lock();
+                let cached = state
+                    .cached_result
+                    .as_ref()
+                    .and_then(|cached| cached.downcast_ref::<T>());
+                return match cached {
+                    Some(cached) => Ok(cached.clone()),
+                    None => Err(Box::new(e)),
+                };
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        match f() {
+            Ok(result) => {
+                // Cloning runs user code, so it happens before the lock is taken.
+                let cached: Box<dyn Any + Send> = Box::new(result.clone());
+                self.lock().cached_result = Some(cached);
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                self.fail(admission, &e, None);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Executes the given function within the circuit breaker, treating a panic as a failure.
+    ///
+    /// This behaves like [`execute`](Self::execute), but the call is wrapped in
+    /// `std::panic::catch_unwind`. A panic is recorded as a failure that counts toward the
+    /// threshold. It is then returned as `CircuitBreakerError::OperationPanicked` instead of
+    /// unwinding through the caller or, with
+    /// [`on_panic(PanicBehavior::Resume)`](crate::CircuitBreakerBuilder::on_panic), re-raised
+    /// with its original payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitBreakerError};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let result = cb.execute_catch_unwind(|| -> Result<(), std::io::Error> {
+    ///     panic!("dependency exploded");
+    /// });
+    /// assert!(matches!(
+    ///     result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+    ///     Some(CircuitBreakerError::OperationPanicked)
+    /// ));
+    /// ```
+    pub fn execute_catch_unwind<F, T, E>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        let (_in_flight, admission) = self.admit(None)?;
+
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(Ok(result)) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Ok(Err(e)) => {
+                self.fail(admission, &e, None);
+                Err(Box::new(e))
+            }
+            Err(payload) => {
+                self.complete(admission, false, None);
+                match self.panic_behavior {
+                    PanicBehavior::Return => Err(Box::new(CircuitBreakerError::OperationPanicked)),
+                    PanicBehavior::Resume => panic::resume_unwind(payload),
+                }
+            }
+        }
+    }
+
+    /// Executes the given function within the circuit breaker, returning `default` instead of an
+    /// error if the call is rejected.
+    ///
+    /// When the circuit admits the call, `f`'s result is recorded as with
+    /// [`execute`](Self::execute) and returned unchanged, without boxing the error. When the call
+    /// is rejected, `f` is not run and `Ok(default)` is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The value to return if the call is rejected.
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.handle_failure();
+    ///
+    /// let value = cb.execute_or(0, || Ok::<_, std::io::Error>(42));
+    /// assert_eq!(value.unwrap(), 0);
+    /// ```
+    pub fn execute_or<F, T, E>(&self, default: T, f: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        let (_in_flight, admission) = match self.admit(None) {
+            Ok(admitted) => admitted,
+            Err(_) => return Ok(default),
+        };
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                self.fail(admission, &e, None);
+                Err(e)
+            }
+        }
+    }
+
+    /// Executes a function whose error converts into `Box<dyn Error>`, within the circuit
+    /// breaker.
+    ///
+    /// This behaves like [`execute`](Self::execute), but accepts closures returning
+    /// `Result<T, Box<dyn Error>>` directly, as well as any other error type that converts into
+    /// one, such as `Box<dyn Error + Send + Sync>` or `anyhow::Error`. The error classifier sees
+    /// the boxed error.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result` with a boxable error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let result = cb.execute_boxed(|| -> Result<u16, Box<dyn std::error::Error>> {
+    ///     let port = "8080".parse::<u16>()?;
+    ///     Ok(port)
+    /// });
+    /// assert_eq!(result.unwrap(), 8080);
+    /// ```
+    pub fn execute_boxed<F, T, E>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: Into<Box<dyn std::error::Error>>,
+    {
+        let (_in_flight, admission) = self.admit(None)?;
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                let e = e.into();
+                self.fail(admission, &*e, None);
+                Err(e)
+            }
+        }
+    }
+
+    /// Wraps `f` into a reusable function that runs it through the circuit breaker.
+    ///
+    /// Each call of the returned function behaves like `self.execute(&f)`, so it can be stored
+    /// or handed to code that should not know about the breaker.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let fetch = cb.guard(|| Ok::<_, std::io::Error>("Operation successful"));
+    ///
+    /// assert_eq!(fetch().unwrap(), "Operation successful");
+    /// assert_eq!(fetch().unwrap(), "Operation successful");
+    /// ```
+    pub fn guard<'a, F, T, E>(
+        &'a self,
+        f: F,
+    ) -> impl Fn() -> Result<T, Box<dyn std::error::Error>> + 'a
+    where
+        F: Fn() -> Result<T, E> + 'a,
+        E: std::error::Error + 'static,
+    {
+        move || self.execute(&f)
+    }
+
+    /// Lazily runs `f` through the circuit breaker for each item, stopping once the circuit is
+    /// open.
+    ///
+    /// Each item is only taken from `items` and passed to `f` when the returned iterator is
+    /// advanced, and each call behaves like [`execute`](Self::execute). The iterator yields
+    /// the result of every call it makes. Once the circuit is open after a call, whether that
+    /// call opened it, was rejected by it, or it was opened elsewhere, the iterator ends without
+    /// taking further items.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The inputs of the operations to run.
+    /// * `f` - A function running the operation for one item.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// let results: Vec<_> = cb
+    ///     .guard_iter(["1", "two", "3"], |item| item.parse::<u32>())
+    ///     .collect();
+    ///
+    /// // The failure on "two" opens the circuit, so "3" is never parsed.
+    /// assert_eq!(results.len(), 2);
+    /// assert_eq!(*results[0].as_ref().unwrap(), 1);
+    /// assert!(results[1].is_err());
+    /// ```
+    pub fn guard_iter<'a, I, F, T, E>(
+        &'a self,
+        items: I,
+        mut f: F,
+    ) -> impl Iterator<Item = Result<T, Box<dyn std::error::Error>>> + 'a
+    where
+        I: IntoIterator,
+        I::IntoIter: 'a,
+        F: FnMut(I::Item) -> Result<T, E> + 'a,
+        E: std::error::Error + 'static,
+    {
+        let mut items = items.into_iter();
+        let mut open = false;
+        iter::from_fn(move || {
+            if open {
+                return None;
+            }
+            let item = items.next()?;
+            let result = self.run(None, || f(item)).result;
+            open = self.peek_state() == CircuitState::Open;
+            Some(result)
+        })
+    }
+
+    /// Executes the given function within the circuit breaker, keeping its error type and any
+    /// lifetimes it carries.
+    ///
+    /// [`execute`](Self::execute) boxes errors into `Box<dyn Error>`, which requires them to be
+    /// `'static`. This variant returns the operation's error unchanged in
+    /// [`BreakerError::Inner`], so both `T` and `E` may borrow from the caller's data for any
+    /// lifetime that outlives the call. A rejected call returns [`BreakerError::Open`].
+    ///
+    /// Because the error classifier only accepts `'static` errors, every error returned here
+    /// counts as a failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let buffer = String::from("key=value");
+    /// let value = cb.execute_ref(|| buffer.split_once('=').map(|(_, v)| v).ok_or(std::fmt::Error));
+    /// assert_eq!(value.unwrap(), "value");
+    /// ```
+    pub fn execute_ref<F, T, E>(&self, f: F) -> Result<T, BreakerError<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error,
+    {
+        let (_in_flight, admission) = self.admit(None).map_err(BreakerError::Open)?;
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                let fingerprint = self.fingerprint(&e);
+                self.record_history(admission, false);
+                self.record_failure(None, 1, fingerprint, None, false);
+                Err(BreakerError::Inner(e))
+            }
+        }
+    }
+
+    /// Executes the given function within the circuit breaker without boxing its error.
+    ///
+    /// This is the allocation-free counterpart to [`execute`](Self::execute): the operation's
+    /// error is passed to the error classifier by reference and returned unchanged in
+    /// [`BreakerError::Inner`], and a rejected call returns [`BreakerError::Open`]. Unlike
+    /// [`execute_ref`](Self::execute_ref), the classifier applies, so `E` must be `'static`.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{BreakerError, CircuitBreaker};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let result = cb.execute_pure(|| Err::<(), _>(std::fmt::Error));
+    /// assert!(matches!(result, Err(BreakerError::Inner(std::fmt::Error))));
+    /// ```
+    pub fn execute_pure<F, T, E>(&self, f: F) -> Result<T, BreakerError<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        let (_in_flight, admission) = self.admit(None).map_err(BreakerError::Open)?;
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                self.fail(admission, &e, None);
+                Err(BreakerError::Inner(e))
+            }
+        }
+    }
+
+    /// Executes the given function within the circuit breaker, letting `map_err_for_circuit`
+    /// decide which errors count and why.
+    ///
+    /// Each error is passed to `map_err_for_circuit`: `Some(reason)` records a failure tagged
+    /// with `reason`, which is broken down per reason in
+    /// [`CircuitMetrics::failure_reasons`], while `None` returns the error without recording it
+    /// as a failure or a success. The breaker's error classifier is not consulted. The error is
+    /// returned unchanged in [`BreakerError::Inner`], and a rejected call returns
+    /// [`BreakerError::Open`].
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    /// * `map_err_for_circuit` - Maps an error to the reason it counts as a failure, or `None`
+    ///   if it does not say anything about the dependency's health.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, FailureReason};
+    /// # use std::time::Duration;
+    /// #[derive(Debug)]
+    /// enum ApiError {
+    ///     Timeout,
+    ///     NotFound,
+    /// }
+    /// # impl std::fmt::Display for ApiError {
+    /// #     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    /// #         write!(f, "{:?}", self)
+    /// #     }
+    /// # }
+    /// # impl std::error::Error for ApiError {}
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let classify = |e: &ApiError| match e {
+    ///     ApiError::Timeout => Some(FailureReason::from("timeout")),
+    ///     ApiError::NotFound => None,
+    /// };
+    ///
+    /// let _ = cb.execute_with_reason(|| Err::<(), _>(ApiError::Timeout), classify);
+    /// let _ = cb.execute_with_reason(|| Err::<(), _>(ApiError::NotFound), classify);
+    /// assert_eq!(cb.metrics().failure_reasons[&FailureReason::from("timeout")], 1);
+    /// assert_eq!(cb.metrics().total_failures, 1);
+    /// ```
+    pub fn execute_with_reason<F, T, E, M>(
+        &self,
+        f: F,
+        map_err_for_circuit: M,
+    ) -> Result<T, BreakerError<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error,
+        M: FnOnce(&E) -> Option<FailureReason>,
+    {
+        let (_in_flight, admission) = self.admit(None).map_err(BreakerError::Open)?;
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                if let Some(reason) = map_err_for_circuit(&e) {
+                    let fingerprint = self.fingerprint(&e);
+                    self.record_history(admission, false);
+                    self.record_failure(None, 1, fingerprint, Some(reason), false);
+                }
+                Err(BreakerError::Inner(e))
+            }
+        }
+    }
+
+    /// Executes a function that does not return a `Result`, deciding from its return value
+    /// whether the call succeeded.
+    ///
+    /// `f`'s value is passed to `is_success`, and the call is recorded as a success or a failure
+    /// accordingly. The value is returned either way, so the caller can still inspect a failed
+    /// response. A rejected call returns the rejection error without running `f`.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function returning a value that encodes its own outcome.
+    /// * `is_success` - Decides whether the value returned by `f` is a success.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    ///
+    /// let status = cb.execute_with_predicate(|| 503u16, |status| *status < 500);
+    /// assert_eq!(status.unwrap(), 503);
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    /// ```
+    pub fn execute_with_predicate<F, T, P>(
+        &self,
+        f: F,
+        is_success: P,
+    ) -> Result<T, CircuitBreakerError>
+    where
+        F: FnOnce() -> T,
+        P: FnOnce(&T) -> bool,
+    {
+        let (_in_flight, admission) = self.admit(None)?;
+        let result = f();
+        self.complete(admission, is_success(&result), None);
+        Ok(result)
+    }
+
+    /// Executes the given function within the circuit breaker, deciding from a successful
+    /// half-open trial's value whether the circuit may close.
+    ///
+    /// This behaves like [`execute`](Self::execute), but when the call is a half-open trial and
+    /// returns `Ok`, the circuit only counts it towards closing if `is_ready` returns `true`.
+    /// Otherwise the call is recorded as a success but the circuit stays half-open, so a
+    /// dependency that answers but is not yet ready does not close it. `is_ready` is not
+    /// consulted for calls made while the circuit is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    /// * `is_ready` - Returns whether the trial's value shows the dependency is ready.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// struct Status {
+    ///     ready: bool,
+    /// }
+    ///
+    /// let cb = CircuitBreaker::new(1, Duration::ZERO);
+    /// cb.handle_failure();
+    ///
+    /// let is_ready = |status: &Status| status.ready;
+    /// let _ = cb.execute_with_decider(|| Ok::<_, std::io::Error>(Status { ready: false }), is_ready);
+    /// assert_eq!(cb.state(), CircuitState::HalfOpen);
+    /// let _ = cb.execute_with_decider(|| Ok::<_, std::io::Error>(Status { ready: true }), is_ready);
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn execute_with_decider<F, T, E, D>(
+        &self,
+        f: F,
+        is_ready: D,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+        D: FnOnce(&T) -> bool,
+    {
+        let (_in_flight, admission) = self.admit(None)?;
+        match f() {
+            Ok(result) => {
+                if admission.state == CircuitState::HalfOpen && !is_ready(&result) {
+                    self.record_history(admission, true);
+                    self.record_success(None, false);
+                    self.finish_trial(&mut self.lock());
+                } else {
+                    self.complete(admission, true, None);
+                }
+                Ok(result)
+            }
+            Err(e) => {
+                self.fail(admission, &e, None);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Executes the given function within the circuit breaker, tagging the call with a label.
+    ///
+    /// This behaves like [`execute`](Self::execute), but any state transition caused by this
+    /// call passes `label` to the callbacks registered with the `set_on_*_labeled` methods.
+    /// Transitions caused by unlabeled calls pass `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - A name for the logical operation, such as `"fetch_user"`.
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let result = cb.execute_labeled("fetch_user", || {
+    ///     Ok::<_, std::io::Error>("Operation successful")
+    /// });
+    /// ```
+    pub fn execute_labeled<F, T, E>(
+        &self,
+        label: &str,
+        f: F,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        self.run(Some(label), f).result
+    }
+
+    /// Executes the given function regardless of the circuit's state, still recording its
+    /// outcome.
+    ///
+    /// This is meant for administrative calls and forced health checks. `f` always runs, even
+    /// while the circuit is open or the concurrency limit has been reached, and its error is
+    /// returned unchanged. The outcome is recorded like that of any other call: an error is
+    /// passed to the error classifier and may open a closed circuit, and a success updates the
+    /// counters. A bypassing call is not a half-open trial, though: while the circuit is
+    /// half-open its outcome is only counted, so a success never closes the circuit and a
+    /// failure never re-opens it or disturbs the trial another caller is running.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.force_open();
+    ///
+    /// let result = cb.execute_bypass(|| Ok::<_, std::io::Error>("healthy"));
+    /// assert_eq!(result.unwrap(), "healthy");
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    /// assert_eq!(cb.metrics().total_successes, 1);
+    /// ```
+    pub fn execute_bypass<F, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        // Recorded as if made while closed, so the outcome never settles a half-open trial.
+        let admission = Admission {
+            state: CircuitState::Closed,
+            started: self.clock.now(),
+            trial: None,
+        };
+
+        let result = f();
+        match result {
+            Ok(_) => self.complete(admission, true, None),
+            Err(ref e) => {
+                let (weight, force_open) = match self.classify(e) {
+                    FailureAction::Ignore => return result,
+                    FailureAction::Count => (1, false),
+                    FailureAction::CountWeighted(weight) => (weight, false),
+                    FailureAction::ForceOpen => (1, true),
+                };
+                let failures = Failures {
+                    weight,
+                    count: 1,
+                    fingerprint: self.fingerprint(e),
+                    reason: None,
+                    force_open,
+                    bypass: true,
+                };
+                self.record_history(admission, false);
+                self.record_failures_as(None, failures);
+            }
+        }
+        result
+    }
+
+    /// Runs the given function regardless of the circuit's state, recording its outcome and
+    /// discarding its result.
+    ///
+    /// This is meant for fire-and-forget teardown, such as closing a connection, which must
+    /// never be blocked by the breaker but still says something about the health of the
+    /// dependency. It never rejects: `f` always runs, even while the circuit is open or the
+    /// concurrency limit has been reached. The outcome is recorded exactly as with
+    /// [`execute_bypass`](Self::execute_bypass), so a failure may open a closed circuit but
+    /// never settles a half-open trial.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.execute_best_effort(|| Err::<(), _>(std::io::Error::other("close failed")));
+    /// assert_eq!(cb.metrics().total_failures, 1);
+    /// ```
+    pub fn execute_best_effort<F, E>(&self, f: F)
+    where
+        F: FnOnce() -> Result<(), E>,
+        E: std::error::Error + 'static,
+    {
+        let _ = self.execute_bypass(f);
+    }
+
+    /// Executes the given function within the circuit breaker, ignoring its error if the call
+    /// was cancelled.
+    ///
+    /// This behaves like [`execute`](Self::execute), except that when `f` returns an error,
+    /// `cancel` is checked: if it is set, the error is returned to the caller but recorded as
+    /// neither a success nor a failure, since a cancelled operation says nothing about the
+    /// dependency. Otherwise the error goes through the error classifier as usual. Successes
+    /// are always recorded, whether or not `cancel` is set.
+    ///
+    /// `cancel` is read once, after `f` returns. An error is therefore ignored if cancellation
+    /// was requested at any point before `f` returned, including before it started, while a
+    /// cancellation requested after `f` has returned an error comes too late and the failure
+    /// counts. `cancel` is not checked before calling `f`, so a cancelled call is still made if
+    /// the circuit admits it; `f` is expected to watch the flag itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - A flag set by whoever cancels the operation.
+    /// * `f` - A function that returns a `Result`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::sync::atomic::{AtomicBool, Ordering};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// let cancel = AtomicBool::new(false);
+    ///
+    /// let result = cb.execute_cancellable(&cancel, || {
+    ///     cancel.store(true, Ordering::SeqCst);
+    ///     Err::<(), _>(std::io::Error::from(std::io::ErrorKind::Interrupted))
+    /// });
+    /// assert!(result.is_err());
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn execute_cancellable<F, T, E>(
+        &self,
+        cancel: &AtomicBool,
+        f: F,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        let (_in_flight, admission) = self.admit(None)?;
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                if cancel.load(Ordering::Acquire) {
+                    self.fail_as(admission, FailureAction::Ignore, &e, None);
+                } else {
+                    self.fail(admission, &e, None);
+                }
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Runs `f` through the breaker, recording its outcome.
+    fn run<F, T, E>(&self, label: Option<&str>, f: F) -> ExecOutcome<T>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        let (_in_flight, admission) = match self.admit(label) {
+            Ok(admitted) => admitted,
+            Err(e) => {
+                return ExecOutcome {
+                    result: Err(Box::new(e)),
+                    counted: false,
+                }
+            }
+        };
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, label);
+                ExecOutcome {
+                    result: Ok(result),
+                    counted: false,
+                }
+            }
+            Err(e) => {
+                let counted = self.fail(admission, &e, label);
+                ExecOutcome {
+                    result: Err(Box::new(e)),
+                    counted,
+                }
+            }
+        }
+    }
+}
+
+impl<E> TypedCircuitBreaker<E> {
+    /// Acquires permission to make a call, for operations that cannot be wrapped in a single
+    /// closure, such as consuming a stream.
+    ///
+    /// If the circuit admits the call, the returned [`CallPermit`] must be used to report its
+    /// outcome once the work is done. If not, the rejection error is returned, exactly as
+    /// [`execute`](Self::execute) would return it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// match cb.try_acquire() {
+    ///     Ok(permit) => {
+    ///         // Do the work, then report how it went.
+    ///         permit.report_success();
+    ///     }
+    ///     Err(e) => println!("Rejected: {}", e),
+    /// };
+    /// ```
+    pub fn try_acquire(&self) -> Result<CallPermit<'_, E>, CircuitBreakerError> {
+        let (in_flight, admission) = self.admit(None)?;
+        Ok(CallPermit {
+            breaker: self,
+            admission,
+            reported: false,
+            _in_flight: in_flight,
+        })
+    }
+
+    /// Reserves a half-open trial for a caller that schedules trial calls itself, returning
+    /// whether one was reserved.
+    ///
+    /// A trial is reserved when the circuit is Half-Open, or Open with the reset timeout
+    /// elapsed, in which case it moves to Half-Open first. The reservation takes a slot of the
+    /// half-open quota just as a call made with [`execute`](Self::execute) would. Nothing is
+    /// reserved while the circuit is Closed, while it is Open with the timeout still running,
+    /// or while a single-flight trial is already in flight; this never waits for that trial to
+    /// finish. A trial refused by the half-open quota or the concurrency limit counts as a
+    /// rejected call.
+    ///
+    /// A reserved trial counts as in flight until its outcome is reported with
+    /// [`report_trial`](Self::report_trial).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::ZERO);
+    /// cb.handle_failure();
+    ///
+    /// if cb.try_admit_trial() {
+    ///     // Send the trial request, then report how it went.
+    ///     cb.report_trial(true);
+    /// }
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn try_admit_trial(&self) -> bool {
+        let Ok(in_flight) = Self::enter(self) else {
+            return false;
+        };
+        match self.acquire_as(None, OpenBehavior::FailFast, true) {
+            Ok(admission) if admission.state == CircuitState::HalfOpen => {
+                // The trial stays in flight until `report_trial`.
+                self.lock().trials_reserved += 1;
+                mem::forget(in_flight);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reports the outcome of a trial reserved with [`try_admit_trial`](Self::try_admit_trial),
+    /// returning whether there was a reserved trial to report it for.
+    ///
+    /// A successful trial counts towards the success threshold and closes the circuit once it
+    /// is reached; a failed trial re-opens the circuit. A report made while no trial is
+    /// reserved, such as a second report for the same trial, is ignored and returns `false`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.force_half_open();
+    ///
+    /// assert!(cb.try_admit_trial());
+    /// assert!(cb.report_trial(false));
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    ///
+    /// // Nothing is reserved any more, so a stray report changes nothing.
+    /// assert!(!cb.report_trial(true));
+    /// assert_eq!(cb.metrics().total_successes, 0);
+    /// ```
+    pub fn report_trial(&self, success: bool) -> bool {
+        let mut state = self.lock();
+        if state.trials_reserved == 0 {
+            return false;
+        }
+        state.trials_reserved -= 1;
+        drop(state);
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        if success {
+            self.record_success(None, true);
+        } else {
+            self.record_failure(None, 1, None, None, false);
+        }
+        true
+    }
+
+    /// Returns the number of calls currently running through the breaker.
+    ///
+    /// Calls made with [`try_acquire`](Self::try_acquire) count until their permit is dropped
+    /// or reported. Calls rejected without running are not counted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let permit = cb.try_acquire().unwrap();
+    /// assert_eq!(cb.in_flight(), 1);
+    /// permit.report_success();
+    /// assert_eq!(cb.in_flight(), 0);
+    /// ```
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Returns the current state of the circuit breaker.
+    ///
+    /// This method may transition the state from Open to Half-Open if the reset timeout has elapsed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the current `CircuitState`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn state(&self) -> CircuitState {
+        let mut state = self.lock();
+        self.fire_trailing_callbacks(&mut state, self.clock.now());
+        let mut transition = None;
+        if state.machine.state() == CircuitState::Open && self.reset_timeout_elapsed(&state) {
+            transition = Some(self.half_open(&mut state, None));
+        }
+        let current_state = state.machine.state();
+        drop(state);
+
+        self.record_transition(transition);
+        current_state
+    }
+
+    /// Returns the current state of the circuit breaker without performing any transition.
+    ///
+    /// Unlike [`state`](Self::state), an Open circuit whose reset timeout has elapsed is still
+    /// reported as Open, and no callback fires. Use this to observe the circuit without
+    /// affecting it.
+    ///
+    /// With the `arc-swap` feature, the state is read from a copy kept outside the lock, so this
+    /// never waits for other calls, however contended the breaker is. A breaker with a
+    /// [`StateStore`] always reads the store instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::ZERO);
+    /// cb.handle_failure();
+    ///
+    /// assert_eq!(cb.peek_state(), CircuitState::Open);
+    /// assert_eq!(cb.state(), CircuitState::HalfOpen);
+    /// ```
+    pub fn peek_state(&self) -> CircuitState {
+        #[cfg(feature = "arc-swap")]
+        if self.store.is_none() {
+            return **self.current_state.load();
+        }
+        self.lock().machine.state()
+    }
+
+    /// Returns the current value of the failure counter compared against the failure threshold.
+    ///
+    /// This never changes the state of the circuit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.handle_failure();
+    /// assert_eq!(cb.current_failures(), 1);
+    /// ```
+    pub fn current_failures(&self) -> u32 {
+        self.lock().machine.failures()
+    }
+
+    /// Returns how long ago the last success was recorded, or `None` if there has been none.
+    ///
+    /// Every success counts, whether from a call executed through the breaker or reported with
+    /// `handle_success`. A long time since the last success while calls keep failing or being
+    /// rejected points to a stale dependency.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// assert_eq!(cb.time_since_last_success(), None);
+    /// cb.handle_success();
+    /// assert!(cb.time_since_last_success().unwrap() < Duration::from_secs(1));
+    /// ```
+    pub fn time_since_last_success(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        let last_success_time = self.lock().last_success_time?;
+        Some(now.saturating_duration_since(last_success_time))
+    }
+
+    /// Returns whether a call made right now would be allowed through.
+    ///
+    /// Nothing is executed and no failure or success is recorded. Like [`state`](Self::state),
+    /// this does perform the Open to Half-Open transition (firing the half-open callback) when
+    /// the reset timeout has elapsed, so the answer matches what `execute` would do.
+    ///
+    /// The same checks `execute` makes are evaluated without using up anything: the concurrency
+    /// limit, and while half-open the trial quota, an in-flight single-flight trial and sampling.
+    /// Sampling draws from a copy of the breaker's random number generator, so the answer
+    /// matches the draw the next call will make. A half-open probe is not run.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// if cb.is_call_permitted() {
+    ///     // route the request to this dependency
+    /// }
+    /// ```
+    pub fn is_call_permitted(&self) -> bool {
+        if let Some(limit) = self.max_concurrency {
+            if self.in_flight.load(Ordering::Acquire) >= limit {
+                return false;
+            }
+        }
+
+        let mut state = self.lock();
+        let mut transition = None;
+        if state.machine.state() == CircuitState::Open && self.reset_timeout_elapsed(&state) {
+            transition = Some(self.half_open(&mut state, None));
+        }
+        let permitted = match state.machine.state() {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => self.half_open_call_permitted(&state),
+        };
+        drop(state);
+
+        self.record_transition(transition);
+        permitted
+    }
+
+    /// Returns whether [`acquire_with`](Self::acquire_with) would admit a half-open trial right
+    /// now, without counting it.
+    fn half_open_call_permitted(&self, state: &CircuitBreakerState) -> bool {
+        if self.half_open_single_flight && state.trial_in_flight {
+            return false;
+        }
+        if let Some(max_calls) = self.half_open_max_calls {
+            let elapsed = self
+                .clock
+                .now()
+                .saturating_duration_since(state.half_open_since);
+            if elapsed < state.machine.reset_timeout() && state.half_open_calls >= max_calls {
+                return false;
+            }
+        }
+        #[cfg(feature = "rand")]
+        if self.half_open_sampling < 1.0
+            && state.rng.clone().gen::<f64>() >= self.half_open_sampling
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Handles a failure, incrementing the failure counter and potentially opening the circuit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.handle_failure();
+    /// ```
+    pub fn handle_failure(&self) {
+        self.handle_failure_weighted(1);
+    }
+
+    /// Handles a failure with the given weight, adding `weight` to the failure counter.
+    ///
+    /// The failure threshold is compared against the accumulated weight, so severe failures can
+    /// open the circuit faster. A `TimeWindow` failure policy likewise counts the failure as
+    /// `weight` failed calls. [`handle_failure`](Self::handle_failure) is equivalent to a
+    /// weight of 1.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.handle_failure_weighted(3);
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    /// ```
+    pub fn handle_failure_weighted(&self, weight: u32) {
+        self.record_failure(None, weight, None, None, false);
+    }
+
+    /// Handles a success, potentially closing the circuit if it was half-open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.handle_success();
+    /// ```
+    pub fn handle_success(&self) {
+        self.record_success(None, true);
+    }
+
+    /// Records the outcome of a check made outside the breaker, such as by a separate health
+    /// poller, so the circuit follows the dependency's health without any calls being executed.
+    ///
+    /// Like [`state`](Self::state), this first performs the Open to Half-Open transition if the
+    /// reset timeout has elapsed, so the outcome is then recorded as a half-open trial: a success
+    /// may close the circuit and a failure re-opens it. Otherwise it behaves exactly like
+    /// [`handle_success`](Self::handle_success) or [`handle_failure`](Self::handle_failure).
+    ///
+    /// # Arguments
+    ///
+    /// * `success` - Whether the check succeeded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::ZERO);
+    /// cb.record_outcome(false);
+    ///
+    /// // The reset timeout has elapsed, so this success is a trial and closes the circuit.
+    /// cb.record_outcome(true);
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn record_outcome(&self, success: bool) {
+        self.state();
+        if success {
+            self.handle_success();
+        } else {
+            self.handle_failure();
+        }
+    }
+
+    /// Forces the circuit open, holding it open until [`force_close`](Self::force_close) is called.
+    ///
+    /// The recorded failure time is cleared, so the reset timeout does not apply and the circuit
+    /// never moves to Half-Open on its own. Calls are rejected with
+    /// `CircuitBreakerError::CircuitOpen` while it is held open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.force_open();
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    /// ```
+    pub fn force_open(&self) {
+        let mut state = self.lock();
+        state.machine.set_last_failure(None);
+        let transition = self.trip(&mut state, true, None);
+        drop(state);
+
+        self.record_transition(Some(transition));
+    }
+
+    /// Forces the circuit closed, clearing the failure counter.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.force_open();
+    /// cb.force_close();
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn force_close(&self) {
+        let mut state = self.lock();
+        let transition = self.reset(&mut state, None);
+        drop(state);
+
+        self.record_transition(Some(transition));
+    }
+
+    /// Forces the circuit into the Half-Open state, as if the reset timeout had just elapsed.
+    ///
+    /// This bypasses the reset timeout entirely and works from any state, which makes it useful
+    /// for exercising recovery paths in chaos tests. The half-open trial quota and success count
+    /// start afresh and the half-open callback fires. The next trials then behave as usual: a
+    /// failure re-opens the circuit and enough successes close it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.force_half_open();
+    /// assert_eq!(cb.state(), CircuitState::HalfOpen);
+    /// ```
+    pub fn force_half_open(&self) {
+        let mut state = self.lock();
+        let transition = self.half_open(&mut state, None);
+        drop(state);
+
+        self.record_transition(Some(transition));
+    }
+
+    /// Clears the failure counter without changing the state.
+    ///
+    /// Unlike [`force_close`](Self::force_close), an open circuit stays open and the time of the
+    /// last failure is kept, so the reset timeout is unaffected. This forgives a transient blip
+    /// that has left a closed circuit close to its threshold. The run of identical errors
+    /// tracked by the repeated-error check is cleared too; the time window of a rate-based
+    /// policy is not.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.handle_failure();
+    /// cb.clear_failures();
+    /// assert_eq!(cb.current_failures(), 0);
+    /// ```
+    pub fn clear_failures(&self) {
+        let mut state = self.lock();
+        state.machine.set_failures(0);
+        state.last_fingerprint = None;
+        state.fingerprint_repeats = 0;
+    }
+
+    /// Writes the circuit's state, failure counter and last failure time to `w`, for example
+    /// on shutdown, so that [`restore_from`](Self::restore_from) can pick them up on the next
+    /// boot.
+    ///
+    /// The state is written in a small line-based text format. The last failure time is written
+    /// as wall-clock time, so the reset timeout keeps running while the process is down.
+    /// Counters, callbacks and configuration are not persisted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.handle_failure();
+    ///
+    /// let mut saved = Vec::new();
+    /// cb.persist_to(&mut saved)?;
+    ///
+    /// let restored = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// restored.restore_from(&mut saved.as_slice())?;
+    /// assert_eq!(restored.state(), CircuitState::Open);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn persist_to(&self, w: &mut impl Write) -> io::Result<()> {
+        let stored = self.lock().stored();
+        stored.write_to(w, self.clock.now())
+    }
+
+    /// Replaces the circuit's state, failure counter and last failure time with those written
+    /// by [`persist_to`](Self::persist_to).
+    ///
+    /// Moving to the restored state fires the matching callback and notifies subscribers, as
+    /// any other transition does, but does not count as a trip. A last failure time too old
+    /// for the breaker's clock to represent, which can happen across a reboot, is taken as now,
+    /// so the reset timeout starts afresh.
+    ///
+    /// # Errors
+    ///
+    /// Input that ends early fails with `io::ErrorKind::UnexpectedEof`, and any other malformed
+    /// input with `io::ErrorKind::InvalidData`. The breaker is left unchanged in either case.
+    pub fn restore_from(&self, r: &mut impl Read) -> io::Result<()> {
+        let stored = StoredState::read_from(r, self.clock.now())?;
+
+        let mut state = self.lock();
+        let mut transition = None;
+        if state.machine.state() != stored.state {
+            transition = Some(match stored.state {
+                CircuitState::Open => self.transition(&mut state, CircuitState::Open, None),
+                CircuitState::HalfOpen => self.half_open(&mut state, None),
+                CircuitState::Closed => self.reset(&mut state, None),
+            });
+        }
+        state.machine.set_failures(stored.failures);
+        state.machine.set_last_failure(stored.last_failure_time);
+        drop(state);
+
+        self.record_transition(transition);
+        Ok(())
+    }
+
+    /// Checks whether a call may proceed, counting it as in flight for the concurrency limit
+    /// and then admitting it as [`acquire_with`](Self::acquire_with) does.
+    pub(crate) fn admit(
+        &self,
+        label: Option<&str>,
+    ) -> Result<(InFlight<&Self, E>, Admission), CircuitBreakerError> {
+        self.admit_with(label, self.open_behavior)
+    }
+
+    /// Like [`admit`](Self::admit), but handles a call made while the circuit is open as
+    /// `open_behavior` says rather than as configured. Async callers pass
+    /// [`OpenBehavior::FailFast`] so that they never block an executor thread.
+    pub(crate) fn admit_with(
+        &self,
+        label: Option<&str>,
+        open_behavior: OpenBehavior,
+    ) -> Result<(InFlight<&Self, E>, Admission), CircuitBreakerError> {
+        let mut in_flight = Self::enter(self)?;
+        let admission = self.acquire_with(label, open_behavior)?;
+        in_flight.hold_trial(&admission);
+        Ok((in_flight, admission))
+    }
+
+    /// Counts a call as in flight, or rejects it, counting the rejection, if the concurrency
+    /// limit has been reached. The call stops counting when the returned guard is dropped.
+    pub(crate) fn enter<B: Deref<Target = Self>>(
+        breaker: B,
+    ) -> Result<InFlight<B, E>, CircuitBreakerError> {
+        let in_flight = breaker.in_flight.fetch_add(1, Ordering::AcqRel);
+        let guard = InFlight {
+            breaker,
+            trial: None,
+            _error: PhantomData,
+        };
+        if let Some(limit) = guard.breaker.max_concurrency {
+            if in_flight >= limit {
+                let breaker = guard.breaker();
+                let mut state = breaker.lock();
+                state.rejected_calls = state.rejected_calls.saturating_add(1);
+                state.last_rejection = Some(RejectionReason::ConcurrencyLimit);
+                drop(state);
+
+                breaker.recorder.record_rejected();
+                return Err(CircuitBreakerError::ConcurrencyLimit { limit });
+            }
+        }
+        Ok(guard)
+    }
+
+    /// Checks whether a call may proceed, transitioning from Open to Half-Open if the reset
+    /// timeout has elapsed. Returns the state the call was admitted in and when it started.
+    ///
+    /// The transition, the half-open quota check and the trial count all happen under one lock,
+    /// so callers racing at the end of the reset timeout cannot be admitted beyond the quota.
+    /// In single-flight mode, a call made while a half-open trial is in flight waits for it
+    /// and then starts over, seeing the state the trial left behind. Likewise, with
+    /// [`OpenBehavior::WaitForReset`] a call made while the circuit is open waits for it to leave
+    /// Open and then starts over. A call made while the circuit is open is handled as
+    /// `open_behavior` says, which callers pass as configured unless they must never block.
+    pub(crate) fn acquire_with(
+        &self,
+        label: Option<&str>,
+        open_behavior: OpenBehavior,
+    ) -> Result<Admission, CircuitBreakerError> {
+        self.acquire_as(label, open_behavior, false)
+    }
+
+    /// Like [`acquire_with`](Self::acquire_with). With `trial_only`, only a half-open trial is
+    /// admitted: a call made while the circuit is Closed or Open, or while a single-flight trial
+    /// is in flight, is refused at once, without waiting and without counting a rejection.
+    fn acquire_as(
+        &self,
+        label: Option<&str>,
+        open_behavior: OpenBehavior,
+        trial_only: bool,
+    ) -> Result<Admission, CircuitBreakerError> {
+        let mut abandoned = None;
+        let mut wait_deadline = None;
+        let (mut state, transition) = loop {
+            let mut state = self.lock();
+            self.fire_trailing_callbacks(&mut state, self.clock.now());
+            let mut transition = None;
+
+            if state.machine.state() == CircuitState::Open {
+                if self.reset_timeout_elapsed(&state) {
+                    transition = Some(self.half_open(&mut state, label));
+                }
+                // Still open if the timeout is running or the transition guard vetoed the move.
+                if state.machine.state() == CircuitState::Open && !trial_only {
+                    let OpenBehavior::WaitForReset { max } = open_behavior else {
+                        return self.reject_open(state);
+                    };
+                    let now = self.clock.now();
+                    let deadline = *wait_deadline.get_or_insert_with(|| now.checked_add(max));
+                    let remaining = deadline.map_or(Duration::MAX, |deadline| {
+                        deadline.saturating_duration_since(now)
+                    });
+                    if remaining.is_zero() {
+                        return self.reject_open(state);
+                    }
+                    // A zero retry_after means the guard vetoed the move, so only a close made
+                    // elsewhere can end the wait early.
+                    let timeout = match self.retry_after(&state) {
+                        Some(retry_after) if !retry_after.is_zero() => retry_after.min(remaining),
+                        _ => remaining,
+                    };
+                    drop(state);
+                    self.record_transition(transition);
+                    // The wait is timed in real time, so re-read the clock regularly in case it
+                    // is not the system clock.
+                    self.wait_for_reset(timeout.min(WAIT_FOR_RESET_RECHECK));
+                    continue;
+                }
+            }
+
+            if trial_only
+                && (state.machine.state() != CircuitState::HalfOpen
+                    || (self.half_open_single_flight && state.trial_in_flight))
+            {
+                drop(state);
+                self.record_transition(transition);
+                return Err(CircuitBreakerError::CircuitOpen { retry_after: None });
+            }
+
+            if self.half_open_single_flight
+                && state.machine.state() == CircuitState::HalfOpen
+                && state.trial_in_flight
+            {
+                let generation = state.trial_generation;
+                if abandoned == Some(generation) {
+                    // The trial has not reported within the reset timeout, so this call takes
+                    // over as the trial.
+                    state.trial_generation = generation.wrapping_add(1);
+                } else {
+                    let timeout = state.machine.reset_timeout();
+                    drop(state);
+                    self.record_transition(transition);
+                    if !self.wait_for_trial(generation, timeout) {
+                        abandoned = Some(generation);
+                    }
+                    continue;
+                }
+            }
+            break (state, transition);
+        };
+
+        if let (CircuitState::HalfOpen, Some(max_calls)) =
+            (state.machine.state(), self.half_open_max_calls)
+        {
+            let now = self.clock.now();
+            let mut elapsed = now.saturating_duration_since(state.half_open_since);
+            if elapsed >= state.machine.reset_timeout() {
+                state.half_open_since = now;
+                state.half_open_calls = 0;
+                elapsed = Duration::ZERO;
+            }
+            if state.half_open_calls >= max_calls {
+                let retry_after = state.machine.reset_timeout().saturating_sub(elapsed);
+                return self.reject(
+                    state,
+                    transition,
+                    CircuitBreakerError::HalfOpenQuotaExceeded { retry_after },
+                );
+            }
+        }
+
+        #[cfg(feature = "rand")]
+        if state.machine.state() == CircuitState::HalfOpen && self.half_open_sampling < 1.0 {
+            let sampling = self.half_open_sampling;
+            if state.rng.gen::<f64>() >= sampling {
+                // The next call may be sampled in, so there is nothing to wait for.
+                let retry_after = Some(Duration::ZERO);
+                return self.reject(
+                    state,
+                    transition,
+                    CircuitBreakerError::CircuitOpen { retry_after },
+                );
+            }
+        }
+
+        let mut trial = None;
+        if state.machine.state() == CircuitState::HalfOpen {
+            state.half_open_calls = state.half_open_calls.saturating_add(1);
+            state.trial_in_flight = self.half_open_single_flight;
+            trial = self
+                .half_open_single_flight
+                .then_some(state.trial_generation);
+        }
+        let mut admission = Admission {
+            state: state.machine.state(),
+            started: self.clock.now(),
+            trial,
+        };
+        drop(state);
+
+        self.record_transition(transition);
+
+        if let (CircuitState::HalfOpen, Some(probe)) = (admission.state, &self.half_open_probe) {
+            if probe() {
+                self.record_success(label, true);
+                admission.state = self.lock().machine.state();
+                admission.started = self.clock.now();
+            } else {
+                self.record_failure(label, 1, None, None, false);
+                let retry_after = self.open_retry_after().or(Some(Duration::ZERO));
+                return self.reject(
+                    self.lock(),
+                    None,
+                    CircuitBreakerError::CircuitOpen { retry_after },
+                );
+            }
+        }
+
+        Ok(admission)
+    }
+
+    /// Blocks until the single-flight trial identified by `generation` has finished, returning
+    /// `false` if it has not within `timeout`.
+    fn wait_for_trial(&self, generation: u64, timeout: Duration) -> bool {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while state.trial_in_flight && state.trial_generation == generation {
+            state = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return false;
+                    }
+                    self.trial_finished
+                        .wait_timeout(state, remaining)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .trial_finished
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+        true
+    }
+
+    /// Blocks for at most `timeout` until the circuit leaves Open, returning immediately if it
+    /// already has.
+    fn wait_for_reset(&self, timeout: Duration) {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.machine.state() == CircuitState::Open {
+            drop(
+                self.left_open
+                    .wait_timeout(state, timeout)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+        }
+    }
+
+    /// Ends the single-flight trial identified by `generation` if it is still in flight, as it is
+    /// when the call holding it finished without its outcome being recorded.
+    fn release_trial(&self, generation: u64) {
+        let mut state = self.lock();
+        if state.trial_generation == generation {
+            self.finish_trial(&mut state);
+        }
+    }
+
+    /// Ends the single-flight trial in flight, if any, waking the calls waiting for it.
+    fn finish_trial(&self, state: &mut CircuitBreakerState) {
+        if state.trial_in_flight {
+            state.trial_in_flight = false;
+            state.trial_generation = state.trial_generation.wrapping_add(1);
+            self.trial_finished.notify_all();
+        }
+    }
+
+    /// Returns the rejection for a call made right now if the circuit is open, counting it, or
+    /// `None` if calls are admitted. Like [`state`](Self::state), this performs the Open to
+    /// Half-Open transition once the reset timeout has elapsed.
+    #[cfg(feature = "tower")]
+    pub(crate) fn check_open(&self) -> Option<CircuitBreakerError> {
+        let mut state = self.lock();
+        if state.machine.state() != CircuitState::Open {
+            return None;
+        }
+        if self.reset_timeout_elapsed(&state) {
+            let transition = self.half_open(&mut state, None);
+            if state.machine.state() != CircuitState::Open {
+                drop(state);
+                self.record_transition(Some(transition));
+                return None;
+            }
+        }
+        self.reject_open(state).err()
+    }
+
+    /// Rejects a call because the circuit is open, pushing back the reset timeout first if
+    /// `extend_timeout_on_rejection` is set.
+    fn reject_open(&self, mut state: StateGuard<'_>) -> Result<Admission, CircuitBreakerError> {
+        if self.extend_timeout_on_rejection && state.machine.last_failure().is_some() {
+            state.machine.set_last_failure(Some(self.clock.now()));
+        }
+        let retry_after = self.retry_after(&state);
+        self.reject(
+            state,
+            None,
+            CircuitBreakerError::CircuitOpen { retry_after },
+        )
+    }
+
+    /// Rejects a call without running it, counting the rejection.
+    fn reject(
+        &self,
+        mut state: StateGuard<'_>,
+        transition: Option<Transition>,
+        error: CircuitBreakerError,
+    ) -> Result<Admission, CircuitBreakerError> {
+        state.rejected_calls = state.rejected_calls.saturating_add(1);
+        state.last_rejection = error.rejection_reason();
+        drop(state);
+
+        self.record_transition(transition);
+        self.recorder.record_rejected();
+        Err(error)
+    }
+
+    /// Returns how long an Open circuit has left before it is due to move to Half-Open, or `None`
+    /// if it is held open without a recorded failure time.
+    fn retry_after(&self, state: &CircuitBreakerState) -> Option<Duration> {
+        let last_failure_time = state.machine.last_failure()?;
+        let now = self.clock.now();
+        let mut remaining = state
+            .machine
+            .reset_timeout()
+            .saturating_sub(now.saturating_duration_since(last_failure_time));
+        if let Some(max_open_duration) = self.max_open_duration {
+            remaining = remaining.min(
+                max_open_duration
+                    .saturating_sub(now.saturating_duration_since(state.state_entered_at)),
+            );
+        }
+        Some(remaining)
+    }
+
+    /// Returns how long this circuit has left before it is due to move to Half-Open, as carried
+    /// by `CircuitBreakerError::CircuitOpen`, or `None` if it is not open or is held open.
+    pub(crate) fn open_retry_after(&self) -> Option<Duration> {
+        let state = self.lock();
+        if state.machine.state() == CircuitState::Open {
+            self.retry_after(&state)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether an Open circuit is due to move to Half-Open: either the reset timeout has
+    /// elapsed since the last failure, or the circuit has been open for `max_open_duration`.
+    ///
+    /// An Open circuit without a recorded failure time (as left by `force_open`) never is: it
+    /// keeps rejecting calls until it is closed explicitly.
+    fn reset_timeout_elapsed(&self, state: &CircuitBreakerState) -> bool {
+        let now = self.clock.now();
+        state.machine.reset_due(now)
+            || (state.machine.last_failure().is_some()
+                && self.max_open_duration.is_some_and(|max_open_duration| {
+                    now.saturating_duration_since(state.state_entered_at) >= max_open_duration
+                }))
+    }
+
+    /// Records the error returned by an admitted call, unless the error
+    /// classifier ignores it. Returns whether the error counted as a failure.
+    pub(crate) fn fail(
+        &self,
+        admission: Admission,
+        error: &(dyn std::error::Error + 'static),
+        label: Option<&str>,
+    ) -> bool {
+        self.fail_as(admission, self.classify(error), error, label)
+    }
+
+    /// Records the error returned by an admitted call as `action` decides. Returns whether the
+    /// error counted as a failure.
+    pub(crate) fn fail_as(
+        &self,
+        admission: Admission,
+        action: FailureAction,
+        error: &dyn fmt::Display,
+        label: Option<&str>,
+    ) -> bool {
+        let (weight, force_open) = match action {
+            FailureAction::Ignore => {
+                // An ignored error says nothing about recovery, so another call may try.
+                if admission.state == CircuitState::HalfOpen {
+                    self.finish_trial(&mut self.lock());
+                }
+                return false;
+            }
+            FailureAction::Count => (1, false),
+            FailureAction::CountWeighted(weight) => (weight, false),
+            FailureAction::ForceOpen => (1, true),
+        };
+
+        let fingerprint = self.fingerprint(error);
+        self.record_history(admission, false);
+        self.record_failure(label, weight, fingerprint, None, force_open);
+        true
+    }
+
+    /// Fingerprints `error` for the repeated-error check, if it is enabled.
+    fn fingerprint(&self, error: &dyn fmt::Display) -> Option<u64> {
+        self.repeated_error_threshold.map(|_| {
+            let mut hasher = DefaultHasher::new();
+            error.to_string().hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// Returns what the error classifier decides `error` does to the circuit. Errors of a type
+    /// registered with `trip_on` open it regardless of the classifier.
+    pub(crate) fn classify(&self, error: &(dyn std::error::Error + 'static)) -> FailureAction {
+        // Registrations are rare, so classifying errors concurrently only takes a shared lock.
+        let trip_on = self.trip_on.read().unwrap_or_else(PoisonError::into_inner);
+        if trip_on.iter().any(|(_, matches)| matches(error)) {
+            return FailureAction::ForceOpen;
+        }
+        drop(trip_on);
+        match self.error_classifier {
+            Some(ref classifier) => classifier(error),
+            None => FailureAction::Count,
+        }
+    }
+
+    /// Records the outcome of an admitted call.
+    pub(crate) fn complete(&self, admission: Admission, success: bool, label: Option<&str>) {
+        self.record_history(admission, success);
+        if success {
+            self.record_success(label, admission.state == CircuitState::HalfOpen);
+        } else {
+            self.record_failure(label, 1, None, None, false);
+        }
+    }
+
+    /// Adds the outcome of an admitted call to the outcome history and its latency to the
+    /// latency histogram and the slow-call windows, if enabled.
+    fn record_history(&self, admission: Admission, success: bool) {
+        if self.history_capacity > 0 || self.latency_histogram || self.tracks_slow_calls {
+            let now = self.clock.now();
+            let latency = now.saturating_duration_since(admission.started);
+            let mut state = self.lock();
+            state.history.record(Outcome {
+                at: now,
+                success,
+                latency,
+            });
+            if let Some(latencies) = state.latencies.as_mut() {
+                latencies.record(latency);
+            }
+            for window in state.slow_calls.iter_mut().flatten() {
+                window.record(latency);
+            }
+        }
+    }
+
+    /// Records a failure. `fingerprint` identifies the error for the repeated-error check; a
+    /// failure without one breaks any run of identical errors. A `reason` is counted in the
+    /// per-reason breakdown returned by `metrics`. With `force_open`, or when the
+    /// failure is a half-open trial, the circuit opens whatever the failure policy says.
+    pub(crate) fn record_failure(
+        &self,
+        label: Option<&str>,
+        weight: u32,
+        fingerprint: Option<u64>,
+        reason: Option<FailureReason>,
+        force_open: bool,
+    ) {
+        let failures = Failures {
+            weight,
+            count: 1,
+            fingerprint,
+            reason,
+            force_open,
+            bypass: false,
+        };
+        self.record_failures_as(label, failures);
+    }
+
+    /// Records `count` failures at once, under a single lock, as a sharded breaker does when it
+    /// flushes the failures its shards have batched up. `weight` is their combined weight.
+    pub(crate) fn record_failures(&self, weight: u32, count: u32, force_open: bool) {
+        let failures = Failures {
+            weight,
+            count,
+            fingerprint: None,
+            reason: None,
+            force_open,
+            bypass: false,
+        };
+        self.record_failures_as(None, failures);
+    }
+
+    /// Records failures as [`record_failure`](Self::record_failure) does. Failures from calls
+    /// that bypassed admission are only counted while the circuit is half-open: they neither
+    /// re-open the circuit nor settle another caller's trial.
+    fn record_failures_as(&self, label: Option<&str>, failures: Failures) {
+        let Failures {
+            weight,
+            count,
+            fingerprint,
+            reason,
+            force_open,
+            bypass,
+        } = failures;
+        let now = self.clock.now();
+        let mut state = self.lock();
+        self.fire_trailing_callbacks(&mut state, now);
+        if let (Some(decay), Some(last_failure_time)) =
+            (self.failure_decay, state.machine.last_failure())
+        {
+            if now.saturating_duration_since(last_failure_time) > decay {
+                state.machine.set_failures(0);
+            }
+        }
+        // Once the circuit is open, late failures (from calls admitted before the trip or reported
+        // with `handle_failure`) neither grow the counter nor re-open the circuit.
+        let open = state.machine.state() == CircuitState::Open;
+        // Failures during warmup are only counted in the metrics.
+        let warming_up = self.warming_up(now);
+        state
+            .machine
+            .count_failure(if warming_up { 0 } else { weight }, now);
+        state.total_failures = state.total_failures.saturating_add(count.into());
+        state.consecutive_successes = 0;
+        if let Some(reason) = reason {
+            *state.failure_reasons.entry(reason).or_insert(0) += 1;
+        }
+        if !warming_up {
+            for window in state.windows.iter_mut().flatten() {
+                window.record(now, false, weight);
+            }
+        }
+
+        let mut repeated = false;
+        if let Some(threshold) = self.repeated_error_threshold {
+            match fingerprint {
+                Some(fingerprint) if state.last_fingerprint == Some(fingerprint) => {
+                    state.fingerprint_repeats = state.fingerprint_repeats.saturating_add(1);
+                }
+                _ => {
+                    state.last_fingerprint = fingerprint;
+                    state.fingerprint_repeats = u32::from(fingerprint.is_some());
+                }
+            }
+            repeated = threshold > 0 && state.fingerprint_repeats >= threshold;
+        }
+
+        // A failed trial re-opens a half-open circuit however the counter stands, for example
+        // after failure decay cleared it while the circuit was open. A bypassed failure leaves a
+        // half-open circuit to its trial.
+        let half_open = state.machine.state() == CircuitState::HalfOpen;
+        let trial = half_open && !bypass;
+        let mut transition = None;
+        if !open
+            && (trial || !half_open)
+            && (force_open
+                || trial
+                || (!warming_up && (repeated || self.should_trip(&mut state, now))))
+        {
+            transition = Some(self.trip(&mut state, false, label));
+        }
+        drop(state);
+
+        for _ in 0..count {
+            self.recorder.record_failure();
+        }
+        self.record_transition(transition);
+    }
+
+    /// Records a success. With `reset`, the failure counter is cleared and a half-open circuit
+    /// is closed once it reaches the success threshold; otherwise the counter is updated
+    /// according to the closed reset mode.
+    fn record_success(&self, label: Option<&str>, reset: bool) {
+        self.record_successes(label, reset, 1);
+    }
+
+    /// Records `count` successes at once, under a single lock, as a sharded breaker does when it
+    /// flushes the successes its shards have batched up.
+    pub(crate) fn record_successes(&self, label: Option<&str>, reset: bool, count: u32) {
+        if count == 0 {
+            return;
+        }
+        let now = self.clock.now();
+        let mut state = self.lock();
+        self.fire_trailing_callbacks(&mut state, now);
+        state.total_successes = state.total_successes.saturating_add(count.into());
+        state.consecutive_successes = state.consecutive_successes.saturating_add(count.into());
+        state.last_success_time = Some(now);
+        for window in state.windows.iter_mut().flatten() {
+            window.record(now, true, count);
+        }
+        let mut transition = None;
+        if reset {
+            state.machine.set_failures(0);
+            if state.machine.state() == CircuitState::HalfOpen {
+                self.finish_trial(&mut state);
+                state.half_open_successes = state.half_open_successes.saturating_add(count);
+                if state.half_open_successes >= self.success_threshold {
+                    transition = Some(self.reset(&mut state, label));
+                    if state.machine.state() == CircuitState::Closed {
+                        state.recovered_at = Some(now);
+                    }
+                }
+            }
+        } else if state.machine.state() == CircuitState::Closed {
+            match self.closed_reset_mode {
+                ClosedResetMode::Keep => {}
+                ClosedResetMode::Reset => state.machine.set_failures(0),
+                ClosedResetMode::Decrement => {
+                    let failures = state.machine.failures().saturating_sub(count);
+                    state.machine.set_failures(failures);
+                }
+            }
+        }
+        // A slow success opens the circuit just as a failure would.
+        if state.machine.state() == CircuitState::Closed
+            && !self.warming_up(now)
+            && state
+                .slow_calls
+                .iter()
+                .flatten()
+                .any(SlowCallWindow::rate_reached)
+        {
+            transition = Some(self.trip(&mut state, false, label));
+        }
+        drop(state);
+
+        for _ in 0..count {
+            self.recorder.record_success();
+        }
+        self.record_transition(transition);
+    }
+
+    /// Evaluates the failure policies after a failure has been recorded, returning whether any
+    /// of them is satisfied.
+    fn should_trip(&self, state: &mut CircuitBreakerState, now: Instant) -> bool {
+        let threshold = match (self.probation, state.recovered_at) {
+            (Some((window, threshold)), Some(recovered_at))
+                if now.saturating_duration_since(recovered_at) < window =>
+            {
+                threshold
+            }
+            _ => state.machine.failure_threshold(),
+        };
+        self.failure_policies
+            .iter()
+            .zip(state.windows.iter_mut())
+            .zip(state.slow_calls.iter())
+            .any(|((policy, window), slow_calls)| match *policy {
+                FailurePolicy::ConsecutiveFailures => state.machine.threshold_reached(threshold),
+                FailurePolicy::TimeWindow { failure_rate, .. } => {
+                    window.as_mut().is_some_and(|window| {
+                        window.calls(now) >= u64::from(self.minimum_throughput)
+                            && window.failure_rate(now) >= failure_rate
+                    })
+                }
+                FailurePolicy::SlowCallRate { .. } => slow_calls
+                    .as_ref()
+                    .is_some_and(SlowCallWindow::rate_reached),
+            })
+    }
+
+    /// Returns whether `now` falls within the warmup period.
+    pub(crate) fn warming_up(&self, now: Instant) -> bool {
+        self.warmup
+            .is_some_and(|warmup| now.saturating_duration_since(self.created_at) < warmup)
+    }
+
+    fn lock(&self) -> StateGuard<'_> {
+        let mut guard = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let store = self.store.as_deref();
+        let loaded = store.map(|store| {
+            let stored = store.load();
+            guard.restore(stored);
+            stored
+        });
+        StateGuard {
+            guard,
+            store,
+            loaded,
+        }
+    }
+
+    /// Reports a transition to the metrics recorder. Must be called without the lock held.
+    fn record_transition(&self, transition: Option<Transition>) {
+        if let Some((from, to)) = transition {
+            if from != to {
+                self.recorder.record_state_change(from, to);
+            }
+        }
+    }
+
+    /// Opens the circuit, counting the trip unless it was already open or, with `forced` and
+    /// `count_forced_trips` off, it was opened by hand.
+    fn trip(
+        &self,
+        state: &mut CircuitBreakerState,
+        forced: bool,
+        label: Option<&str>,
+    ) -> Transition {
+        let transition = self.transition(state, CircuitState::Open, label);
+        if matches!(
+            transition,
+            (
+                CircuitState::Closed | CircuitState::HalfOpen,
+                CircuitState::Open
+            )
+        ) && (!forced || self.count_forced_trips)
+        {
+            state.trip_count = state.trip_count.saturating_add(1);
+        }
+        transition
+    }
+
+    fn half_open(&self, state: &mut CircuitBreakerState, label: Option<&str>) -> Transition {
+        let transition = self.transition(state, CircuitState::HalfOpen, label);
+        if state.machine.state() == CircuitState::HalfOpen {
+            state.half_open_since = self.clock.now();
+            state.half_open_calls = 0;
+            state.half_open_successes = 0;
+        }
+        transition
+    }
+
+    fn reset(&self, state: &mut CircuitBreakerState, label: Option<&str>) -> Transition {
+        let transition = self.transition(state, CircuitState::Closed, label);
+        if state.machine.state() == CircuitState::Closed {
+            state.recovered_at = None;
+            state.machine.set_failures(0);
+            state.last_fingerprint = None;
+            state.fingerprint_repeats = 0;
+            for window in state.windows.iter_mut().flatten() {
+                window.clear();
+            }
+            for window in state.slow_calls.iter_mut().flatten() {
+                window.clear();
+            }
+        }
+        transition
+    }
+
+    /// Moves the circuit to `to`, firing the matching callback and notifying subscribers. If the
+    /// transition guard vetoes the move, the circuit stays where it is and `(from, from)` is
+    /// returned.
+    fn transition(
+        &self,
+        state: &mut CircuitBreakerState,
+        to: CircuitState,
+        label: Option<&str>,
+    ) -> Transition {
+        let from = state.machine.state();
+        if from == CircuitState::HalfOpen {
+            self.finish_trial(state);
+        }
+        if let (true, Some(guard)) = (from != to, &state.transition_guard) {
+            if !guard(from, to) {
+                return (from, from);
+            }
+        }
+        let now = self.clock.now();
+        // Moving to the current state, as forcing an Open circuit open does, keeps the time
+        // already spent in it.
+        if from != to {
+            let elapsed = now.saturating_duration_since(state.state_entered_at);
+            state.durations.add(from, elapsed);
+            state.state_entered_at = now;
+        }
+        state.machine.set_state(to);
+        #[cfg(feature = "arc-swap")]
+        self.current_state.store(Arc::new(to));
+
+        let index = callback_index(to);
+        let suppressed = !self.callback_due(state, index, now);
+        if !suppressed {
+            // This transition's own callback supersedes a trailing one of the same kind.
+            state.callbacks_pending[index] = None;
+        }
+        self.fire_trailing_callbacks(state, now);
+        if suppressed {
+            if callback_for(state, to).is_some() {
+                state.callbacks_pending[index] = Some(label.map(str::to_owned));
+            }
+        } else {
+            self.fire_callback(state, to, label, now);
+        }
+
+        if from != to {
+            let change = StateChange { from, to, at: now };
+            state
+                .subscribers
+                .retain(|subscriber| subscriber.send(change).is_ok());
+
+            #[cfg(feature = "async")]
+            if to == CircuitState::Open {
+                for signal in state.open_signals.drain(..) {
+                    if let Some(signal) = signal.upgrade() {
+                        signal.fire();
+                    }
+                }
+            }
+        }
+
+        if to == CircuitState::Closed {
+            self.closed.notify_all();
+        }
+        if from == CircuitState::Open && to != CircuitState::Open {
+            self.left_open.notify_all();
+        }
+
+        (from, to)
+    }
+
+    /// Returns whether the callback at `index` may run at `now` under `callback_min_interval`.
+    fn callback_due(&self, state: &CircuitBreakerState, index: usize, now: Instant) -> bool {
+        match (self.callback_min_interval, state.callbacks_fired_at[index]) {
+            (Some(interval), Some(fired_at)) => now.saturating_duration_since(fired_at) >= interval,
+            _ => true,
+        }
+    }
+
+    /// Runs the callback for a transition to `to`, if one is set.
+    fn fire_callback(
+        &self,
+        state: &mut CircuitBreakerState,
+        to: CircuitState,
+        label: Option<&str>,
+        now: Instant,
+    ) {
+        if let Some(callback) = callback_for(state, to) {
+            match self.dispatcher {
+                Some(ref dispatcher) => dispatcher.dispatch(callback.clone(), label, to),
+                None => dispatcher::invoke(callback, label, to, self.on_callback_panic.as_ref()),
+            }
+            state.callbacks_fired_at[callback_index(to)] = Some(now);
+        }
+    }
+
+    /// Runs, once each, the callbacks suppressed by `callback_min_interval` whose interval has
+    /// since passed, passing the label of the last suppressed transition.
+    fn fire_trailing_callbacks(&self, state: &mut CircuitBreakerState, now: Instant) {
+        if state.callbacks_pending.iter().all(Option::is_none) {
+            return;
+        }
+        for to in [
+            CircuitState::Open,
+            CircuitState::Closed,
+            CircuitState::HalfOpen,
+        ] {
+            let index = callback_index(to);
+            if !self.callback_due(state, index, now) {
+                continue;
+            }
+            if let Some(label) = state.callbacks_pending[index].take() {
+                self.fire_callback(state, to, label.as_deref(), now);
+            }
+        }
+    }
+
+    /// Sets a callback function to be executed when the circuit breaker opens.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A function to be called when the circuit opens.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_on_open(|| {
+    ///     println!("Circuit opened!");
+    /// });
+    /// ```
+    pub fn set_on_open<F>(&self, callback: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.on_open = Some(Arc::new(move |_: Option<&str>| callback()));
+    }
+
+    /// Sets a callback function to be executed when the circuit breaker closes.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A function to be called when the circuit closes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_on_close(|| {
+    ///     println!("Circuit closed!");
+    /// });
+    /// ```
+    pub fn set_on_close<F>(&self, callback: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.on_close = Some(Arc::new(move |_: Option<&str>| callback()));
+    }
+
+    /// Sets a callback function to be executed when the circuit breaker transitions to half-open.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A function to be called when the circuit transitions to half-open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_on_half_open(|| {
+    ///     println!("Circuit is half-open!");
+    /// });
+    /// ```
+    pub fn set_on_half_open<F>(&self, callback: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.on_half_open = Some(Arc::new(move |_: Option<&str>| callback()));
+    }
+
+    /// Sets a guard consulted before every change of state, which can veto it.
+    ///
+    /// `guard` receives the current state and the state the circuit is about to move to, and the
+    /// move only happens if it returns `true`. A vetoed move leaves the circuit where it is
+    /// without firing callbacks: a circuit kept Half-Open keeps admitting trials and tries to
+    /// close again on the next successful one, a circuit kept Open keeps rejecting calls, and a
+    /// circuit kept Closed keeps counting failures. The guard applies to every transition,
+    /// including `force_open`, `force_close` and `force_half_open`. Setting a guard replaces the
+    /// previous one.
+    ///
+    /// The guard runs while the breaker's lock is held, so it must not call back into the
+    /// breaker.
+    ///
+    /// # Arguments
+    ///
+    /// * `guard` - Returns whether the circuit may move from the first state to the second.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::sync::atomic::{AtomicBool, Ordering};
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::ZERO);
+    /// let may_close = Arc::new(AtomicBool::new(false));
+    /// let flag = may_close.clone();
+    /// cb.set_transition_guard(move |_, to| to != CircuitState::Closed || flag.load(Ordering::SeqCst));
+    ///
+    /// cb.handle_failure();
+    /// let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+    /// assert_eq!(cb.state(), CircuitState::HalfOpen);
+    ///
+    /// may_close.store(true, Ordering::SeqCst);
+    /// let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+    /// assert_eq!(cb.state(), CircuitState::Closed);
+    /// ```
+    pub fn set_transition_guard<F>(&self, guard: F)
+    where
+        F: Fn(CircuitState, CircuitState) -> bool + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.transition_guard = Some(Arc::new(guard));
+    }
+
+    /// Sets a callback function to be executed when the circuit breaker opens, receiving the
+    /// label of the call that caused the transition.
+    ///
+    /// The label is `None` when the transition was caused by an unlabeled call.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A function to be called with the label when the circuit opens.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_on_open_labeled(|label| {
+    ///     println!("Circuit opens because of {:?}", label);
+    /// });
+    /// ```
+    pub fn set_on_open_labeled<F>(&self, callback: F)
+    where
+        F: Fn(Option<&str>) + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.on_open = Some(Arc::new(callback));
+    }
+
+    /// Sets a callback function to be executed when the circuit breaker closes, receiving the
+    /// label of the call that caused the transition.
+    ///
+    /// The label is `None` when the transition was caused by an unlabeled call.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A function to be called with the label when the circuit closes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_on_close_labeled(|label| {
+    ///     println!("Circuit closes because of {:?}", label);
+    /// });
+    /// ```
+    pub fn set_on_close_labeled<F>(&self, callback: F)
+    where
+        F: Fn(Option<&str>) + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.on_close = Some(Arc::new(callback));
+    }
+
+    /// Sets a callback function to be executed when the circuit breaker transitions to half-open, receiving the
+    /// label of the call that caused the transition.
+    ///
+    /// The label is `None` when the transition was caused by an unlabeled call.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A function to be called with the label when the circuit transitions to half-open.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.set_on_half_open_labeled(|label| {
+    ///     println!("Circuit transitions to half-open because of {:?}", label);
+    /// });
+    /// ```
+    pub fn set_on_half_open_labeled<F>(&self, callback: F)
+    where
+        F: Fn(Option<&str>) + Send + Sync + 'static,
+    {
+        let mut state = self.lock();
+        state.on_half_open = Some(Arc::new(callback));
+    }
+
+    /// Subscribes to state transitions of the circuit breaker.
+    ///
+    /// Every transition is sent to each subscriber as a [`StateChange`]. Each call returns a new,
+    /// independent receiver; subscribers whose receiver has been dropped are pruned on the next
+    /// transition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// let events = cb.subscribe();
+    /// cb.handle_failure();
+    ///
+    /// let change = events.recv().unwrap();
+    /// assert_eq!(change.to, CircuitState::Open);
+    /// ```
+    pub fn subscribe(&self) -> Receiver<StateChange> {
+        let (sender, receiver) = mpsc::channel();
+        let mut state = self.lock();
+        state.subscribers.push(sender);
+        receiver
+    }
+
+    /// Returns a future that completes the next time the circuit transitions to Open.
+    ///
+    /// The future is registered when this method is called, so a transition that happens before
+    /// it is first polled still completes it. Any number of futures may be waiting at once, and
+    /// one transition completes them all. The future does not borrow the breaker and works with
+    /// any executor. Requires the `async` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// let opened = cb.on_open_future();
+    /// cb.handle_failure();
+    /// // In an async context: `opened.await;`
+    /// # drop(opened);
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn on_open_future(&self) -> impl Future<Output = ()> + Send + 'static {
+        let signal = Arc::new(Signal::default());
+        let mut state = self.lock();
+        // Futures dropped without the circuit opening leave dead entries behind.
+        state
+            .open_signals
+            .retain(|signal| signal.strong_count() > 0);
+        state.open_signals.push(Arc::downgrade(&signal));
+        SignalFuture::new(signal)
+    }
+
+    /// Starts a background thread that probes the dependency while the circuit is half-open.
+    ///
+    /// Every `interval`, the thread checks the circuit's state (performing the Open to Half-Open
+    /// transition once the reset timeout has elapsed). While half-open it runs `probe`: `true`
+    /// is recorded as a success, closing the circuit without any foreground traffic, and `false`
+    /// as a failure. Any previously started prober is stopped first.
+    ///
+    /// This takes an `Arc` because the thread needs a handle to the breaker. It only holds a weak
+    /// reference, so dropping the breaker stops the thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let cb = Arc::new(CircuitBreaker::new(3, Duration::from_secs(60)));
+    /// cb.start_prober(Duration::from_secs(5), || {
+    ///     // e.g. call a cheap health endpoint
+    ///     true
+    /// });
+    /// cb.stop_prober();
+    /// ```
+    pub fn start_prober<P>(self: &Arc<Self>, interval: Duration, probe: P)
+    where
+        P: Fn() -> bool + Send + Sync + 'static,
+        E: 'static,
+    {
+        self.stop_prober();
+        let prober = Prober::spawn(Arc::downgrade(self), interval, probe);
+        *self.prober.lock().unwrap_or_else(PoisonError::into_inner) = Some(prober);
+    }
+
+    /// Stops the background prober started by [`start_prober`](Self::start_prober), if any, and
+    /// waits for its thread to exit.
+    pub fn stop_prober(&self) {
+        let prober = self
+            .prober
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(prober) = prober {
+            prober.stop();
+        }
+    }
+
+    /// Returns a snapshot of the circuit breaker's counters.
+    ///
+    /// The reported state is read as-is, without performing the Open to Half-Open transition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+    /// assert_eq!(cb.metrics().total_successes, 1);
+    /// ```
+    pub fn metrics(&self) -> CircuitMetrics {
+        let state = self.lock();
+        CircuitMetrics {
+            state: state.machine.state(),
+            failures: state.machine.failures(),
+            total_successes: state.total_successes,
+            total_failures: state.total_failures,
+            rejected_calls: state.rejected_calls,
+            trip_count: state.trip_count,
+            consecutive_successes: state.consecutive_successes,
+            failure_reasons: state.failure_reasons.clone(),
+        }
+    }
+
+    /// Returns a coarse indicator of the circuit breaker's health.
+    ///
+    /// An open circuit is [`Health::Unhealthy`], and a half-open one is [`Health::Degraded`]. A
+    /// closed circuit is degraded once its failure counter reaches the fraction of the failure
+    /// threshold set with [`CircuitBreakerBuilder::degraded_failure_fraction`], and healthy
+    /// otherwise. A closed circuit with a threshold of `0` is always healthy. Like
+    /// [`metrics`](Self::metrics), the state is read as-is, without performing the Open to
+    /// Half-Open transition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, Health};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(4, Duration::from_secs(60));
+    /// assert_eq!(cb.health(), Health::Healthy);
+    ///
+    /// cb.handle_failure();
+    /// cb.handle_failure();
+    /// assert_eq!(cb.health(), Health::Degraded);
+    /// ```
+    pub fn health(&self) -> Health {
+        let state = self.lock();
+        match state.machine.state() {
+            CircuitState::Open => Health::Unhealthy,
+            CircuitState::HalfOpen => Health::Degraded,
+            CircuitState::Closed => {
+                let degraded_at =
+                    self.degraded_failure_fraction * f64::from(state.machine.failure_threshold());
+                if state.machine.failure_threshold() > 0
+                    && state.machine.failures() > 0
+                    && f64::from(state.machine.failures()) >= degraded_at
+                {
+                    Health::Degraded
+                } else {
+                    Health::Healthy
+                }
+            }
+        }
+    }
+
+    /// Returns a snapshot of the circuit breaker's counters and resets the cumulative ones.
+    ///
+    /// The total successes, total failures, rejected calls, trip count and per-reason failure
+    /// counts start again from zero. The state and the failure counter used for the threshold
+    /// are reported but left alone, so draining does not affect when the circuit opens. The
+    /// snapshot and the reset happen under one lock, so no call is lost between them, which
+    /// makes this suitable for reporting metrics over consecutive periods.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+    /// assert_eq!(cb.drain_metrics().total_successes, 1);
+    /// assert_eq!(cb.metrics().total_successes, 0);
+    /// ```
+    pub fn drain_metrics(&self) -> CircuitMetrics {
+        let mut state = self.lock();
+        CircuitMetrics {
+            state: state.machine.state(),
+            failures: state.machine.failures(),
+            total_successes: mem::take(&mut state.total_successes),
+            total_failures: mem::take(&mut state.total_failures),
+            rejected_calls: mem::take(&mut state.rejected_calls),
+            trip_count: mem::take(&mut state.trip_count),
+            consecutive_successes: state.consecutive_successes,
+            failure_reasons: mem::take(&mut state.failure_reasons),
+        }
+    }
+
+    /// Returns how many times the circuit has opened since the breaker was created, or since
+    /// the counters were last reset with [`drain_metrics`](Self::drain_metrics).
+    ///
+    /// Every move from Closed or Half-Open to Open counts, including a failed half-open trial
+    /// re-opening the circuit. Opens made with [`force_open`](Self::force_open) only count if
+    /// enabled with [`CircuitBreakerBuilder::count_forced_trips`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// let _ = cb.execute(|| Err::<(), _>(std::fmt::Error));
+    /// assert_eq!(cb.trip_count(), 1);
+    /// ```
+    pub fn trip_count(&self) -> u64 {
+        self.lock().trip_count
+    }
+
+    /// Returns the number of successes recorded since the last failure.
+    ///
+    /// Any failure resets it to zero, whatever the state of the circuit and whether or not the
+    /// failure counts towards the threshold. Errors ignored by the error classifier are not
+    /// failures and leave it alone. It is not reset by [`drain_metrics`](Self::drain_metrics).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// cb.handle_success();
+    /// cb.handle_success();
+    /// assert_eq!(cb.consecutive_successes(), 2);
+    /// cb.handle_failure();
+    /// assert_eq!(cb.consecutive_successes(), 0);
+    /// ```
+    pub fn consecutive_successes(&self) -> u64 {
+        self.lock().consecutive_successes
+    }
+
+    /// Returns why the most recent rejected call was rejected, or `None` if no call has been.
+    ///
+    /// This tells a rejection apart from an error returned by the operation when the error
+    /// itself has been boxed or converted. The reason is kept until the next rejection, whatever
+    /// happens to the circuit in between, and is not reset by
+    /// [`drain_metrics`](Self::drain_metrics).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, RejectionReason};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// assert_eq!(cb.last_rejection_reason(), None);
+    ///
+    /// cb.handle_failure();
+    /// let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+    /// assert_eq!(cb.last_rejection_reason(), Some(RejectionReason::Open));
+    /// ```
+    pub fn last_rejection_reason(&self) -> Option<RejectionReason> {
+        self.lock().last_rejection
+    }
+
+    /// Returns how long the circuit has been in its current state.
+    ///
+    /// The time is measured with the breaker's clock and starts again from zero whenever the
+    /// state changes, but not on a transition to the state the circuit is already in, which
+    /// makes it suitable for displays such as "open for 3m12s". It is kept out of
+    /// [`CircuitMetrics`] so that snapshots taken without a transition in between still compare
+    /// equal. Like [`metrics`](Self::metrics), this does not perform the Open to Half-Open
+    /// transition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.handle_failure();
+    /// println!("open for {:?}", cb.state_since());
+    /// ```
+    pub fn state_since(&self) -> Duration {
+        let now = self.clock.now();
+        now.saturating_duration_since(self.lock().state_entered_at)
+    }
+
+    /// Returns the most recent call outcomes, oldest first.
+    ///
+    /// Only calls executed through the breaker are recorded: rejected calls, errors ignored by
+    /// the error classifier, and outcomes reported with `handle_success` or `handle_failure` are
+    /// not. The history is empty unless enabled with
+    /// [`CircuitBreakerBuilder::outcome_history`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+    ///     .outcome_history(100)
+    ///     .build()?;
+    /// cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+    ///
+    /// let outcomes = cb.recent_outcomes();
+    /// assert_eq!(outcomes.len(), 1);
+    /// assert!(outcomes[0].success);
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn recent_outcomes(&self) -> Vec<Outcome> {
+        self.lock().history.to_vec()
+    }
+
+    /// Blocks the current thread until the circuit is closed or `timeout` elapses.
+    ///
+    /// With a [`StateStore`] configured, only a close made by this breaker wakes the waiting
+    /// thread early; a close made by another breaker sharing the store is noticed on the next
+    /// wakeup.
+    ///
+    /// Returns `true` if the circuit is closed, immediately if it already was. With a timeout of
+    /// `None` the wait is unbounded. Waiting does not itself move the circuit towards Closed:
+    /// another thread, a prober, or an explicit `force_close` must close it.
+    ///
+    /// The timeout is measured in real time, not by the configured [`Clock`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+    /// cb.handle_failure();
+    /// assert!(!cb.wait_until_closed(Some(Duration::from_millis(10))));
+    ///
+    /// cb.force_close();
+    /// assert!(cb.wait_until_closed(None));
+    /// ```
+    pub fn wait_until_closed(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(store) = self.store.as_deref() {
+                state.restore(store.load());
+            }
+            if state.machine.state() == CircuitState::Closed {
+                return true;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return false;
+                    }
+                    self.closed
+                        .wait_timeout(state, remaining)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .closed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    /// Returns the total time the circuit has spent in each state since it was built.
+    ///
+    /// The time spent in the current state is included up to now. Like
+    /// [`metrics`](Self::metrics), this does not perform the Open to Half-Open transition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// # let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let durations = cb.state_durations();
+    /// println!("closed for {:?}, open for {:?}", durations.closed, durations.open);
+    /// ```
+    pub fn state_durations(&self) -> StateDurations {
+        let now = self.clock.now();
+        let state = self.lock();
+        let mut durations = state.durations;
+        durations.add(
+            state.machine.state(),
+            now.saturating_duration_since(state.state_entered_at),
+        );
+        durations
+    }
+
+    /// Registers an error type that opens the circuit on its first occurrence.
+    ///
+    /// An error returned through `execute` (or any other method that classifies errors) whose
+    /// concrete type is `E2` trips the circuit immediately, whatever the failure threshold,
+    /// failure policy and error classifier say. Registering the same type twice has no further
+    /// effect. Registration briefly blocks calls classifying an error, which otherwise only
+    /// share a read lock, so it is best done at startup.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitState};
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::new(10, Duration::from_secs(60));
+    /// cb.trip_on::<std::fmt::Error>();
+    ///
+    /// let _ = cb.execute(|| Err::<(), _>(std::fmt::Error));
+    /// assert_eq!(cb.state(), CircuitState::Open);
+    /// ```
+    pub fn trip_on<E2: std::error::Error + 'static>(&self) {
+        fn is<E2: std::error::Error + 'static>(error: &(dyn std::error::Error + 'static)) -> bool {
+            error.is::<E2>()
+        }
+
+        let type_id = TypeId::of::<E2>();
+        let mut trip_on = self.trip_on.write().unwrap_or_else(PoisonError::into_inner);
+        if !trip_on.iter().any(|&(registered, _)| registered == type_id) {
+            trip_on.push((type_id, is::<E2>));
+        }
+    }
+
+    /// Returns the latency below which `p` percent of the executed calls completed.
+    ///
+    /// Returns `None` unless the histogram is enabled with
+    /// [`CircuitBreakerBuilder::latency_histogram`], if no calls have been recorded yet, or if `p`
+    /// is outside `0.0..=100.0`. The result is the upper bound of the histogram bucket holding
+    /// the requested rank, within about 6% of the exact value.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - The percentile, for example `50.0` for the median or `99.0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::CircuitBreaker;
+    /// # use std::time::Duration;
+    /// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+    ///     .latency_histogram(true)
+    ///     .build()?;
+    /// cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+    ///
+    /// let p99 = cb.latency_percentile(99.0).unwrap();
+    /// assert!(p99 < Duration::from_secs(1));
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn latency_percentile(&self, p: f64) -> Option<Duration> {
+        self.lock().latencies.as_ref()?.percentile(p)
+    }
+}
+
+impl<E> Default for TypedCircuitBreaker<E> {
+    /// Creates a circuit breaker with a failure threshold of 5 and a reset timeout of 30 seconds.
+    ///
+    /// These values are part of the stable API and are also available as
+    /// [`DEFAULT_FAILURE_THRESHOLD`](crate::DEFAULT_FAILURE_THRESHOLD) and
+    /// [`DEFAULT_RESET_TIMEOUT`](crate::DEFAULT_RESET_TIMEOUT).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreaker;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreaker::default();
+    /// assert_eq!(cb.failure_threshold(), 5);
+    /// assert_eq!(cb.reset_timeout(), Duration::from_secs(30));
+    /// ```
+    fn default() -> Self {
+        Self::from_builder(CircuitBreakerBuilder::default())
+    }
+}
+
+impl<E> Drop for TypedCircuitBreaker<E> {
+    fn drop(&mut self) {
+        self.stop_prober();
+    }
+}
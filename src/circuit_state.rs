@@ -1,4 +1,7 @@
-use std::fmt;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
 /// Represents the state of a circuit breaker.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,3 +23,116 @@ impl fmt::Display for CircuitState {
         }
     }
 }
+
+impl FromStr for CircuitState {
+    type Err = ParseStateError;
+
+    /// Parses the names written by `Display`, `"Closed"`, `"Open"` and `"HalfOpen"`, ignoring
+    /// ASCII case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitState;
+    ///
+    /// assert_eq!("halfopen".parse(), Ok(CircuitState::HalfOpen));
+    /// assert!("ajar".parse::<CircuitState>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("Closed") {
+            Ok(CircuitState::Closed)
+        } else if s.eq_ignore_ascii_case("Open") {
+            Ok(CircuitState::Open)
+        } else if s.eq_ignore_ascii_case("HalfOpen") {
+            Ok(CircuitState::HalfOpen)
+        } else {
+            Err(ParseStateError { _private: () })
+        }
+    }
+}
+
+/// The error returned when parsing a [`CircuitState`] from a string that names no state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseStateError {
+    _private: (),
+}
+
+impl fmt::Display for ParseStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid circuit state, expected Closed, Open or HalfOpen"
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseStateError {}
+
+/// A coarse indicator of a circuit breaker's health, returned by [`CircuitBreaker::health`] for
+/// status pages and dashboards.
+///
+/// [`CircuitBreaker::health`]: crate::CircuitBreaker::health
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Health {
+    /// The circuit is closed with few recent failures.
+    Healthy,
+    /// The circuit is closed but its failures are approaching the threshold, or it is
+    /// half-open and testing whether the dependency has recovered.
+    Degraded,
+    /// The circuit is open.
+    Unhealthy,
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::Healthy => write!(f, "Healthy"),
+            Health::Degraded => write!(f, "Degraded"),
+            Health::Unhealthy => write!(f, "Unhealthy"),
+        }
+    }
+}
+
+/// A transition of a circuit breaker from one state to another.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateChange {
+    /// The state the circuit was in before the transition.
+    pub from: CircuitState,
+    /// The state the circuit is in after the transition.
+    pub to: CircuitState,
+    /// When the transition happened.
+    pub at: Instant,
+}
+
+/// The total time a circuit breaker has spent in each state, returned by
+/// [`CircuitBreaker::state_durations`].
+///
+/// [`CircuitBreaker::state_durations`]: crate::CircuitBreaker::state_durations
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StateDurations {
+    /// Time spent Closed.
+    pub closed: Duration,
+    /// Time spent Open.
+    pub open: Duration,
+    /// Time spent Half-Open.
+    pub half_open: Duration,
+}
+
+#[cfg(feature = "std")]
+impl StateDurations {
+    /// Adds `elapsed` to the total for `state`.
+    pub(crate) fn add(&mut self, state: CircuitState, elapsed: Duration) {
+        let total = match state {
+            CircuitState::Closed => &mut self.closed,
+            CircuitState::Open => &mut self.open,
+            CircuitState::HalfOpen => &mut self.half_open,
+        };
+        *total = total.saturating_add(elapsed);
+    }
+}
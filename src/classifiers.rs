@@ -0,0 +1,58 @@
+//! Ready-made error classifiers for [`CircuitBreakerBuilder::error_classifier`].
+//!
+//! [`CircuitBreakerBuilder::error_classifier`]: crate::CircuitBreakerBuilder::error_classifier
+
+use std::error::Error;
+use std::io;
+
+/// Returns a classifier that only counts transient I/O errors as failures.
+///
+/// The classifier looks for an [`io::Error`] in the error and its chain of sources. Connection
+/// and timeout kinds such as `ConnectionRefused`, `ConnectionReset` and `TimedOut` count, while
+/// every other kind, such as `NotFound`, `PermissionDenied` or `InvalidInput`, describes a
+/// problem with the request rather than the dependency and is ignored. Errors that do not
+/// contain an `io::Error` always count.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{classifiers, CircuitBreaker};
+/// use std::io::{Error, ErrorKind};
+/// use std::time::Duration;
+///
+/// let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+///     .error_classifier(classifiers::io_transient_only())
+///     .build()?;
+///
+/// let _ = cb.execute(|| Err::<(), _>(Error::from(ErrorKind::NotFound)));
+/// assert!(cb.is_call_permitted());
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+pub fn io_transient_only() -> impl Fn(&(dyn Error + 'static)) -> bool + Send + Sync + 'static {
+    |error| {
+        let mut current = Some(error);
+        while let Some(error) = current {
+            if let Some(io_error) = error.downcast_ref::<io::Error>() {
+                return is_transient(io_error.kind());
+            }
+            current = error.source();
+        }
+        true
+    }
+}
+
+fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::WouldBlock
+    )
+}
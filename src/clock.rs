@@ -0,0 +1,135 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A source of the current time for a circuit breaker.
+///
+/// The breaker reads every timestamp it needs (failure times, reset timeouts, rolling windows)
+/// through its clock. The default is [`SystemClock`]; tests can supply their own implementation
+/// to advance time deterministically instead of sleeping.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, Clock};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::{Duration, Instant};
+///
+/// struct ManualClock {
+///     start: Instant,
+///     offset: Mutex<Duration>,
+/// }
+///
+/// impl Clock for ManualClock {
+///     fn now(&self) -> Instant {
+///         self.start + *self.offset.lock().unwrap()
+///     }
+/// }
+///
+/// let clock = Arc::new(ManualClock { start: Instant::now(), offset: Mutex::new(Duration::ZERO) });
+/// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+///     .clock(clock.clone())
+///     .build()?;
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+pub trait Clock: Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// A [`Clock`] backed by `Instant::now()`. This is the default clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [`Clock`] driven by an integer tick counter that the caller advances, such as the
+/// milliseconds counted by a hardware timer.
+///
+/// Each tick is worth `tick` of time, so timeouts configured on the breaker are measured in
+/// ticks: with one-millisecond ticks, a reset timeout of 100 milliseconds elapses once the
+/// counter has advanced by 100. The breaker only moves forward when the counter does.
+///
+/// Ticks are converted to an `Instant` anchored at `Instant::now()` when the clock is created,
+/// so tick 0 reads as that instant. A tick count too large to represent as an `Instant` on this
+/// platform saturates at the latest representable one instead of panicking.
+///
+/// For targets without `std`, see [`StateMachine`](crate::StateMachine), which takes tick
+/// timestamps directly.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitState, TickClock};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let ticks = Arc::new(AtomicU64::new(0));
+/// let cb = CircuitBreaker::builder(1, Duration::from_millis(100))
+///     .clock(Arc::new(TickClock::new(ticks.clone(), Duration::from_millis(1))))
+///     .build()?;
+///
+/// cb.handle_failure();
+/// ticks.fetch_add(100, Ordering::SeqCst);
+/// assert_eq!(cb.state(), CircuitState::HalfOpen);
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TickClock {
+    ticks: Arc<AtomicU64>,
+    tick: Duration,
+    origin: Instant,
+    latest: Instant,
+}
+
+impl TickClock {
+    /// Creates a clock reading `ticks`, where each tick is worth `tick` of time.
+    pub fn new(ticks: Arc<AtomicU64>, tick: Duration) -> Self {
+        let origin = Instant::now();
+        TickClock {
+            ticks,
+            tick,
+            origin,
+            latest: latest_instant(origin),
+        }
+    }
+
+    /// Returns the current value of the tick counter.
+    pub fn ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Acquire)
+    }
+}
+
+impl Clock for TickClock {
+    fn now(&self) -> Instant {
+        let nanos = self
+            .tick
+            .as_nanos()
+            .saturating_mul(u128::from(self.ticks()));
+        let elapsed = Duration::new(
+            u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX),
+            (nanos % 1_000_000_000) as u32,
+        );
+        self.origin.checked_add(elapsed).unwrap_or(self.latest)
+    }
+}
+
+/// Returns the latest `Instant` that can be represented from `origin`, found by a binary search
+/// over whole seconds, as the limit depends on the platform.
+fn latest_instant(origin: Instant) -> Instant {
+    let (mut low, mut high) = (0, u64::MAX);
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if origin.checked_add(Duration::from_secs(mid)).is_some() {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    origin + Duration::from_secs(low)
+}
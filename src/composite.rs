@@ -0,0 +1,111 @@
+use crate::circuit_breaker::CircuitBreaker;
+use crate::circuit_state::CircuitState;
+use crate::error::CircuitBreakerError;
+
+/// Decides when a [`CompositeBreaker`] is considered open, based on how many of its members are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum AggregationMode {
+    /// The composite is open when any member is open.
+    Any,
+    /// The composite is open only when every member is open.
+    All,
+    /// The composite is open when more than half of the members are open.
+    Majority,
+}
+
+/// Combines several circuit breakers, for example one per downstream of a fan-out call, into a
+/// single aggregated state.
+///
+/// The combined state is Open when the members satisfy the [`AggregationMode`]. Otherwise it is
+/// Half-Open if any member is half-open, and Closed if not.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{AggregationMode, CircuitBreaker, CircuitState, CompositeBreaker};
+/// use std::time::Duration;
+///
+/// let composite = CompositeBreaker::new(
+///     vec![
+///         CircuitBreaker::new(3, Duration::from_secs(60)),
+///         CircuitBreaker::new(3, Duration::from_secs(60)),
+///     ],
+///     AggregationMode::All,
+/// );
+/// assert_eq!(composite.state(), CircuitState::Closed);
+/// ```
+pub struct CompositeBreaker {
+    members: Vec<CircuitBreaker>,
+    mode: AggregationMode,
+}
+
+impl CompositeBreaker {
+    /// Creates a new `CompositeBreaker` over the given members.
+    ///
+    /// # Arguments
+    ///
+    /// * `members` - The circuit breakers to aggregate.
+    /// * `mode` - How the members' states are combined.
+    pub fn new(members: Vec<CircuitBreaker>, mode: AggregationMode) -> Self {
+        CompositeBreaker { members, mode }
+    }
+
+    /// Returns the member circuit breakers.
+    pub fn members(&self) -> &[CircuitBreaker] {
+        &self.members
+    }
+
+    /// Returns the aggregation mode.
+    pub fn mode(&self) -> AggregationMode {
+        self.mode
+    }
+
+    /// Returns the combined state of the members.
+    ///
+    /// Like [`CircuitBreaker::state`], this may transition members from Open to Half-Open.
+    pub fn state(&self) -> CircuitState {
+        let states: Vec<CircuitState> = self.members.iter().map(CircuitBreaker::state).collect();
+        let open = states.iter().filter(|&&s| s == CircuitState::Open).count();
+
+        let is_open = match self.mode {
+            AggregationMode::Any => open > 0,
+            AggregationMode::All => !states.is_empty() && open == states.len(),
+            AggregationMode::Majority => open * 2 > states.len(),
+        };
+
+        if is_open {
+            CircuitState::Open
+        } else if states.contains(&CircuitState::HalfOpen) {
+            CircuitState::HalfOpen
+        } else {
+            CircuitState::Closed
+        }
+    }
+
+    /// Executes the given function through the member at `index`.
+    ///
+    /// If the composite is open, the call is rejected with `CircuitBreakerError::CircuitOpen`
+    /// without consulting the member. Its `retry_after` is the earliest time an open member is
+    /// due to move to Half-Open, which is the soonest the composite's state can change.
+    /// Otherwise it behaves like [`CircuitBreaker::execute`] on that member.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn execute<F, T, E>(&self, index: usize, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        if self.state() == CircuitState::Open {
+            let retry_after = self
+                .members
+                .iter()
+                .filter_map(CircuitBreaker::open_retry_after)
+                .min();
+            return Err(Box::new(CircuitBreakerError::CircuitOpen { retry_after }));
+        }
+        self.members[index].execute(f)
+    }
+}
@@ -0,0 +1,157 @@
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::builder::{CircuitBreakerBuilder, DEFAULT_FAILURE_THRESHOLD, DEFAULT_RESET_TIMEOUT};
+use crate::error::ConfigError;
+use crate::policy::{ClosedResetMode, FailurePolicy};
+
+/// A reusable set of circuit breaker options, consumed by [`CircuitBreaker::with_config`].
+///
+/// Unlike [`CircuitBreakerBuilder`], a config holds only plain values, so it can be cloned and
+/// used to construct any number of breakers that behave identically. Options that attach
+/// behaviour, such as callbacks, clocks and metrics recorders, are set on the builder returned by
+/// `CircuitBreakerBuilder::from(config)`.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitConfig};
+/// use std::time::Duration;
+///
+/// let config = CircuitConfig {
+///     failure_threshold: 3,
+///     reset_timeout: Duration::from_secs(10),
+///     success_threshold: 2,
+///     ..CircuitConfig::default()
+/// };
+///
+/// let payments = CircuitBreaker::with_config(config.clone())?;
+/// let inventory = CircuitBreaker::with_config(config)?;
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+///
+/// [`CircuitBreaker::with_config`]: crate::CircuitBreaker::with_config
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitConfig {
+    /// The number of failures that must occur before the circuit opens.
+    pub failure_threshold: u32,
+    /// The duration after which the circuit moves from Open to Half-Open.
+    pub reset_timeout: Duration,
+    /// The number of successful half-open trials needed to close the circuit.
+    pub success_threshold: u32,
+    /// The policy deciding when the circuit opens.
+    pub failure_policy: FailurePolicy,
+    /// Further policies that also open the circuit, whichever is satisfied first.
+    pub additional_failure_policies: Vec<FailurePolicy>,
+    /// The window after which accumulated failures age out, if any.
+    pub failure_decay: Option<Duration>,
+    /// The number of trial calls admitted while half-open, if limited.
+    pub half_open_max_calls: Option<u32>,
+    /// The number of calls a time window needs before its failure rate can open the circuit.
+    pub minimum_throughput: u32,
+    /// How a success while closed affects the failure counter.
+    pub closed_reset_mode: ClosedResetMode,
+    /// The longest the circuit stays open before moving to Half-Open, if limited.
+    pub max_open_duration: Option<Duration>,
+    /// The number of identical consecutive errors that open the circuit, if enabled.
+    pub repeated_error_threshold: Option<u32>,
+}
+
+impl Default for CircuitConfig {
+    /// The configuration of [`CircuitBreaker::default`](crate::CircuitBreaker::default).
+    fn default() -> Self {
+        CircuitConfig {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            reset_timeout: DEFAULT_RESET_TIMEOUT,
+            success_threshold: 1,
+            failure_policy: FailurePolicy::ConsecutiveFailures,
+            additional_failure_policies: Vec::new(),
+            failure_decay: None,
+            half_open_max_calls: None,
+            minimum_throughput: 0,
+            closed_reset_mode: ClosedResetMode::Keep,
+            max_open_duration: None,
+            repeated_error_threshold: None,
+        }
+    }
+}
+
+impl CircuitConfig {
+    /// Reads a configuration from environment variables whose names start with `prefix`.
+    ///
+    /// The following variables are read, and each one that is unset keeps its default:
+    ///
+    /// * `{prefix}FAILURE_THRESHOLD` - the failure threshold, as an integer.
+    /// * `{prefix}RESET_TIMEOUT_SECS` - the reset timeout, as a whole number of seconds.
+    /// * `{prefix}SUCCESS_THRESHOLD` - the success threshold, as an integer.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Prepended as is to each variable name, for example `"CB_"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::InvalidEnvVar`] if a variable is set to a value that does not
+    /// parse, including one that is not valid Unicode.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitConfig;
+    /// use std::time::Duration;
+    ///
+    /// std::env::set_var("PAYMENTS_CB_RESET_TIMEOUT_SECS", "10");
+    /// let config = CircuitConfig::from_env("PAYMENTS_CB_")?;
+    /// assert_eq!(config.reset_timeout, Duration::from_secs(10));
+    /// # Ok::<(), circuit_breaker::ConfigError>(())
+    /// ```
+    pub fn from_env(prefix: &str) -> Result<Self, ConfigError> {
+        let mut config = CircuitConfig::default();
+        if let Some(threshold) = env_var(prefix, "FAILURE_THRESHOLD")? {
+            config.failure_threshold = threshold;
+        }
+        if let Some(secs) = env_var(prefix, "RESET_TIMEOUT_SECS")? {
+            config.reset_timeout = Duration::from_secs(secs);
+        }
+        if let Some(threshold) = env_var(prefix, "SUCCESS_THRESHOLD")? {
+            config.success_threshold = threshold;
+        }
+        Ok(config)
+    }
+}
+
+/// Reads and parses the variable `{prefix}{name}`, returning `None` if it is unset.
+fn env_var<T: FromStr>(prefix: &str, name: &str) -> Result<Option<T>, ConfigError> {
+    let name = format!("{}{}", prefix, name);
+    let Some(value) = env::var_os(&name) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .and_then(|value| value.trim().parse().ok())
+        .map(Some)
+        .ok_or_else(|| ConfigError::InvalidEnvVar {
+            name,
+            value: value.to_string_lossy().into_owned(),
+        })
+}
+
+impl From<CircuitConfig> for CircuitBreakerBuilder {
+    fn from(config: CircuitConfig) -> Self {
+        let mut builder =
+            CircuitBreakerBuilder::new(config.failure_threshold, config.reset_timeout)
+                .success_threshold(config.success_threshold)
+                .failure_policy(config.failure_policy)
+                .minimum_throughput(config.minimum_throughput)
+                .closed_reset_mode(config.closed_reset_mode);
+        builder
+            .failure_policies
+            .extend(config.additional_failure_policies);
+        builder.failure_decay = config.failure_decay;
+        builder.half_open_max_calls = config.half_open_max_calls;
+        builder.max_open_duration = config.max_open_duration;
+        builder.repeated_error_threshold = config.repeated_error_threshold;
+        builder
+    }
+}
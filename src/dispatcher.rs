@@ -0,0 +1,64 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use crate::circuit_breaker::{Callback, CallbackPanicHook};
+use crate::circuit_state::CircuitState;
+
+/// A callback invocation waiting to run.
+struct Job {
+    callback: Callback,
+    label: Option<String>,
+    to: CircuitState,
+}
+
+/// A background thread that runs state-change callbacks in the order they were dispatched.
+///
+/// The thread exits once the dispatcher is dropped and every queued callback has run.
+pub(crate) struct CallbackDispatcher {
+    sender: Sender<Job>,
+}
+
+impl CallbackDispatcher {
+    pub(crate) fn spawn(on_panic: Option<CallbackPanicHook>) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in receiver {
+                invoke(
+                    &job.callback,
+                    job.label.as_deref(),
+                    job.to,
+                    on_panic.as_ref(),
+                );
+            }
+        });
+        CallbackDispatcher { sender }
+    }
+
+    /// Queues `callback` to run with `label` on the dispatcher thread.
+    pub(crate) fn dispatch(&self, callback: Callback, label: Option<&str>, to: CircuitState) {
+        let job = Job {
+            callback,
+            label: label.map(str::to_owned),
+            to,
+        };
+        // The thread only exits once the sender is dropped, so sending cannot fail.
+        let _ = self.sender.send(job);
+    }
+}
+
+/// Runs the callback for a transition to `to`, passing a panic to `on_panic` instead of
+/// propagating it.
+pub(crate) fn invoke(
+    callback: &Callback,
+    label: Option<&str>,
+    to: CircuitState,
+    on_panic: Option<&CallbackPanicHook>,
+) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(label))) {
+        if let Some(on_panic) = on_panic {
+            // A panicking hook is discarded too, so it cannot unwind through the breaker either.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| on_panic(to, &*payload)));
+        }
+    }
+}
@@ -1,9 +1,89 @@
 use std::error::Error;
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum CircuitBreakerError {
-    CircuitOpen,
+    /// The circuit is open and the call was rejected without running. `retry_after` is the time
+    /// left until the circuit is due to move to Half-Open and admit a trial call, or `None` if it
+    /// is held open with `force_open` and will only close explicitly.
+    CircuitOpen { retry_after: Option<Duration> },
+    /// The operation run by `execute_catch_unwind` panicked. The panic was counted as a failure,
+    /// and this error is returned in its place because the breaker's
+    /// [`PanicBehavior`](crate::PanicBehavior) is `Return`; with `Resume` the panic is re-raised
+    /// instead.
+    OperationPanicked,
+    /// The circuit is half-open and its trial quota is exhausted. Unlike `CircuitOpen`, this is a
+    /// transient rejection: a call made after `retry_after` will be admitted as a new trial.
+    HalfOpenQuotaExceeded { retry_after: Duration },
+    /// `limit` calls were already running through the breaker, so the call was rejected without
+    /// running, whatever the state of the circuit.
+    ConcurrencyLimit { limit: usize },
+}
+
+impl CircuitBreakerError {
+    /// Returns a stable, machine-readable code identifying the kind of error, for mapping it to
+    /// an HTTP status or a gRPC code without matching on the `Display` output.
+    ///
+    /// The codes are `"circuit_open"`, `"operation_panicked"`, `"half_open_quota"` and
+    /// `"concurrency_limit"`, and will not change in future releases.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreakerError;
+    ///
+    /// let error = CircuitBreakerError::CircuitOpen { retry_after: None };
+    /// assert_eq!(error.code(), "circuit_open");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match self {
+            CircuitBreakerError::CircuitOpen { .. } => "circuit_open",
+            CircuitBreakerError::OperationPanicked => "operation_panicked",
+            CircuitBreakerError::HalfOpenQuotaExceeded { .. } => "half_open_quota",
+            CircuitBreakerError::ConcurrencyLimit { .. } => "concurrency_limit",
+        }
+    }
+
+    /// Returns the HTTP status code a server should respond with when a call is rejected with
+    /// this error, for services that put a breaker in front of a downstream dependency.
+    ///
+    /// * `CircuitOpen` maps to `503 Service Unavailable`.
+    /// * `HalfOpenQuotaExceeded` and `ConcurrencyLimit` map to `429 Too Many Requests`.
+    /// * `OperationPanicked` maps to `500 Internal Server Error`.
+    ///
+    /// The breaker has no call timeout of its own, so no variant maps to `504 Gateway Timeout`.
+    /// A timeout enforced by the caller should be reported as a 504 by the caller.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreakerError;
+    ///
+    /// let error = CircuitBreakerError::CircuitOpen { retry_after: None };
+    /// assert_eq!(error.http_status(), 503);
+    /// ```
+    pub fn http_status(&self) -> u16 {
+        match self {
+            CircuitBreakerError::CircuitOpen { .. } => 503,
+            CircuitBreakerError::OperationPanicked => 500,
+            CircuitBreakerError::HalfOpenQuotaExceeded { .. }
+            | CircuitBreakerError::ConcurrencyLimit { .. } => 429,
+        }
+    }
+
+    /// Returns why a call was rejected, or `None` if the error does not reject a call.
+    pub(crate) fn rejection_reason(&self) -> Option<RejectionReason> {
+        match self {
+            CircuitBreakerError::CircuitOpen { .. } => Some(RejectionReason::Open),
+            CircuitBreakerError::OperationPanicked => None,
+            CircuitBreakerError::HalfOpenQuotaExceeded { .. } => {
+                Some(RejectionReason::HalfOpenQuota)
+            }
+            CircuitBreakerError::ConcurrencyLimit { .. } => Some(RejectionReason::ConcurrencyLimit),
+        }
+    }
 }
 
 impl Error for CircuitBreakerError {}
@@ -11,7 +91,138 @@ impl Error for CircuitBreakerError {}
 impl fmt::Display for CircuitBreakerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            CircuitBreakerError::CircuitOpen => write!(f, "Circuit is open"),
+            CircuitBreakerError::CircuitOpen {
+                retry_after: Some(retry_after),
+            } => write!(f, "Circuit is open, retry after {:?}", retry_after),
+            CircuitBreakerError::CircuitOpen { retry_after: None } => write!(f, "Circuit is open"),
+            CircuitBreakerError::OperationPanicked => write!(f, "Operation panicked"),
+            CircuitBreakerError::HalfOpenQuotaExceeded { retry_after } => write!(
+                f,
+                "Half-open trial quota exceeded, retry after {:?}",
+                retry_after
+            ),
+            CircuitBreakerError::ConcurrencyLimit { limit } => {
+                write!(f, "Concurrency limit of {} calls reached", limit)
+            }
+        }
+    }
+}
+
+/// Why the breaker rejected a call without running it, as returned by
+/// [`CircuitBreaker::last_rejection_reason`].
+///
+/// [`CircuitBreaker::last_rejection_reason`]: crate::CircuitBreaker::last_rejection_reason
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RejectionReason {
+    /// The circuit was open, as reported by `CircuitBreakerError::CircuitOpen`.
+    Open,
+    /// The concurrency limit was reached, as reported by
+    /// `CircuitBreakerError::ConcurrencyLimit`.
+    ConcurrencyLimit,
+    /// The half-open trial quota was exhausted, as reported by
+    /// `CircuitBreakerError::HalfOpenQuotaExceeded`.
+    HalfOpenQuota,
+}
+
+/// The error returned by breaker methods that preserve the operation's own error type instead of
+/// boxing it, such as [`CircuitBreaker::execute_ref`] and [`CircuitBreaker::execute_pure`].
+///
+/// [`CircuitBreaker::execute_ref`]: crate::CircuitBreaker::execute_ref
+/// [`CircuitBreaker::execute_pure`]: crate::CircuitBreaker::execute_pure
+#[derive(Debug)]
+pub enum BreakerError<E> {
+    /// The call was rejected by the breaker without running.
+    Open(CircuitBreakerError),
+    /// The operation ran and returned this error.
+    Inner(E),
+}
+
+impl<E: Error> Error for BreakerError<E> {}
+
+impl<E: fmt::Display> fmt::Display for BreakerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakerError::Open(e) => e.fmt(f),
+            BreakerError::Inner(e) => e.fmt(f),
+        }
+    }
+}
+
+/// An invalid circuit breaker configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// An environment variable read by [`CircuitConfig::from_env`] is set but could not be
+    /// parsed.
+    ///
+    /// [`CircuitConfig::from_env`]: crate::CircuitConfig::from_env
+    InvalidEnvVar {
+        /// The name of the variable.
+        name: String,
+        /// The value it holds, lossily converted to UTF-8.
+        value: String,
+    },
+    /// The success threshold is 0, so a half-open circuit could never close.
+    ZeroSuccessThreshold,
+    /// The half-open trial quota is 0, so a half-open circuit would reject every call.
+    ZeroHalfOpenMaxCalls,
+    /// The repeated-error threshold is 0, so no run of identical errors could reach it.
+    ZeroRepeatedErrorThreshold,
+    /// The concurrency limit is 0, so every call would be rejected.
+    ZeroMaxConcurrency,
+    /// A [`FailurePolicy::TimeWindow`] has a zero duration or no buckets.
+    ///
+    /// [`FailurePolicy::TimeWindow`]: crate::FailurePolicy::TimeWindow
+    EmptyTimeWindow,
+    /// A [`FailurePolicy::TimeWindow`] has more buckets than its duration can be split into, so
+    /// each bucket would be shorter than a nanosecond.
+    ///
+    /// [`FailurePolicy::TimeWindow`]: crate::FailurePolicy::TimeWindow
+    TimeWindowTooShort,
+    /// A [`FailurePolicy::SlowCallRate`] has a window of 0 calls.
+    ///
+    /// [`FailurePolicy::SlowCallRate`]: crate::FailurePolicy::SlowCallRate
+    ZeroSlowCallWindow,
+    /// An option holding a fraction is NaN or outside `0.0..=1.0`.
+    InvalidFraction {
+        /// The name of the option, such as `"failure_rate"`.
+        name: &'static str,
+    },
+}
+
+impl Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidEnvVar { name, value } => {
+                write!(
+                    f,
+                    "Invalid value {:?} for environment variable {}",
+                    value, name
+                )
+            }
+            ConfigError::ZeroSuccessThreshold => write!(f, "Success threshold must be at least 1"),
+            ConfigError::ZeroHalfOpenMaxCalls => {
+                write!(f, "Half-open trial quota must be at least 1")
+            }
+            ConfigError::ZeroRepeatedErrorThreshold => {
+                write!(f, "Repeated-error threshold must be at least 1")
+            }
+            ConfigError::ZeroMaxConcurrency => write!(f, "Concurrency limit must be at least 1"),
+            ConfigError::EmptyTimeWindow => {
+                write!(f, "Time window must have a non-zero duration and buckets")
+            }
+            ConfigError::TimeWindowTooShort => {
+                write!(f, "Time window must be at least one nanosecond per bucket")
+            }
+            ConfigError::ZeroSlowCallWindow => {
+                write!(f, "Slow-call rate window must hold at least 1 call")
+            }
+            ConfigError::InvalidFraction { name } => {
+                write!(f, "{} must be between 0.0 and 1.0", name)
+            }
         }
     }
 }
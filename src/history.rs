@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The outcome of a single call executed through the circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outcome {
+    /// When the call completed.
+    pub at: Instant,
+    /// Whether the call succeeded.
+    pub success: bool,
+    /// How long the call took to run.
+    pub latency: Duration,
+}
+
+/// A bounded ring buffer of the most recent call outcomes.
+///
+/// Once `capacity` outcomes are held, each new outcome evicts the oldest one. A capacity of zero
+/// records nothing.
+pub(crate) struct OutcomeHistory {
+    capacity: usize,
+    outcomes: VecDeque<Outcome>,
+}
+
+impl OutcomeHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        OutcomeHistory {
+            capacity,
+            outcomes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn record(&mut self, outcome: Outcome) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.outcomes.len() == self.capacity {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(outcome);
+    }
+
+    /// Returns the recorded outcomes, oldest first.
+    pub(crate) fn to_vec(&self) -> Vec<Outcome> {
+        self.outcomes.iter().copied().collect()
+    }
+}
+
+/// The number of sub-buckets each power of two is divided into, bounding the relative error of a
+/// reported latency to 1/16.
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// A histogram of call latencies in microseconds with log-linear buckets.
+///
+/// Latencies below 16µs are counted exactly. Above that, each power of two is split into 16
+/// equal buckets, so memory use is fixed (under 1,000 counters) while a reported percentile is
+/// within about 6% of the true value, much like an HDR histogram with one significant digit.
+pub(crate) struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl LatencyHistogram {
+    pub(crate) fn new() -> Self {
+        LatencyHistogram {
+            counts: vec![0; Self::index(u64::MAX) + 1],
+            total: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.counts[Self::index(micros)] += 1;
+        self.total += 1;
+    }
+
+    /// Returns the latency below which `p` percent of the recorded calls fall, or `None` if no
+    /// calls have been recorded or `p` is outside `0.0..=100.0`.
+    pub(crate) fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.total == 0 || !(0.0..=100.0).contains(&p) {
+            return None;
+        }
+        let rank = ((p / 100.0 * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(Duration::from_micros(Self::highest_value(index)));
+            }
+        }
+        None
+    }
+
+    fn index(micros: u64) -> usize {
+        if micros < SUB_BUCKETS {
+            return micros as usize;
+        }
+        let exponent = micros.ilog2() - SUB_BUCKET_BITS;
+        let sub_bucket = (micros >> exponent) - SUB_BUCKETS;
+        ((u64::from(exponent) + 1) * SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    /// Returns the largest latency counted in the bucket at `index`.
+    fn highest_value(index: usize) -> u64 {
+        let index = index as u64;
+        if index < SUB_BUCKETS {
+            return index;
+        }
+        let exponent = index / SUB_BUCKETS - 1;
+        let lowest = (SUB_BUCKETS + index % SUB_BUCKETS) << exponent;
+        lowest.saturating_add((1 << exponent) - 1)
+    }
+}
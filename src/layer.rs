@@ -0,0 +1,175 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::circuit_breaker::{Admission, CircuitBreaker, InFlight};
+use crate::policy::OpenBehavior;
+
+/// The error type of a [`CircuitBreakerService`]: either a
+/// [`CircuitBreakerError`](crate::CircuitBreakerError) rejecting the call or the inner service's
+/// own error.
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A [`tower`](https://docs.rs/tower) layer that wraps services in a [`CircuitBreakerService`]
+/// sharing one breaker.
+///
+/// Requires the `tower` feature.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitBreakerLayer};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use tower::ServiceBuilder;
+///
+/// let breaker = Arc::new(CircuitBreaker::new(3, Duration::from_secs(60)));
+/// let service = ServiceBuilder::new()
+///     .layer(CircuitBreakerLayer::new(breaker))
+///     .service_fn(|request: u32| async move { Ok::<_, std::io::Error>(request * 2) });
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreakerLayer {
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CircuitBreakerLayer {
+    /// Creates a new `CircuitBreakerLayer` applying `breaker` to every wrapped service.
+    pub fn new(breaker: Arc<CircuitBreaker>) -> Self {
+        CircuitBreakerLayer { breaker }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreakerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreakerService::new(inner, self.breaker.clone())
+    }
+}
+
+/// A [`tower`](https://docs.rs/tower) service that runs every call of an inner service through a
+/// [`CircuitBreaker`].
+///
+/// `poll_ready` fails with `CircuitBreakerError::CircuitOpen` while the circuit is open, counting
+/// the rejection, and otherwise defers to the inner service. Each response is recorded as a
+/// success, and each error is passed to the breaker's error classifier, exactly as with
+/// [`CircuitBreaker::execute`]. Requires the `tower` feature.
+#[derive(Clone)]
+pub struct CircuitBreakerService<S> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<S> CircuitBreakerService<S> {
+    /// Creates a new `CircuitBreakerService` running `inner` through `breaker`.
+    pub fn new(inner: S, breaker: Arc<CircuitBreaker>) -> Self {
+        CircuitBreakerService { inner, breaker }
+    }
+
+    /// Returns the circuit breaker applied to the inner service.
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+}
+
+impl<S, Request> Service<Request> for CircuitBreakerService<S>
+where
+    S: Service<Request>,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(rejection) = self.breaker.check_open() {
+            return Poll::Ready(Err(Box::new(rejection)));
+        }
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // A cancelled future drops `in_flight`, releasing any half-open trial it holds.
+        let admitted = CircuitBreaker::enter(self.breaker.clone()).and_then(|mut in_flight| {
+            // Waiting for the circuit to leave Open would block the executor, so fail fast.
+            let admission = in_flight
+                .breaker()
+                .acquire_with(None, OpenBehavior::FailFast)?;
+            in_flight.hold_trial(&admission);
+            Ok((admission, in_flight))
+        });
+        let state = match admitted {
+            Ok((admission, in_flight)) => State::Called {
+                future: self.inner.call(request),
+                in_flight,
+                admission,
+            },
+            Err(rejection) => State::Rejected {
+                rejection: Some(Box::new(rejection)),
+            },
+        };
+        ResponseFuture { state }
+    }
+}
+
+pin_project! {
+    /// The future returned by [`CircuitBreakerService`], recording the inner service's outcome
+    /// when it completes.
+    pub struct ResponseFuture<F> {
+        #[pin]
+        state: State<F>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<F> {
+        Called {
+            #[pin]
+            future: F,
+            in_flight: InFlight<Arc<CircuitBreaker>>,
+            admission: Admission,
+        },
+        Rejected {
+            rejection: Option<BoxError>,
+        },
+    }
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<BoxError>,
+{
+    type Output = Result<T, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().state.project() {
+            StateProj::Called {
+                future,
+                in_flight,
+                admission,
+            } => match future.poll(cx) {
+                Poll::Ready(Ok(response)) => {
+                    in_flight.breaker().complete(*admission, true, None);
+                    Poll::Ready(Ok(response))
+                }
+                Poll::Ready(Err(e)) => {
+                    let e = e.into();
+                    in_flight.breaker().fail(*admission, &*e, None);
+                    Poll::Ready(Err(e))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            StateProj::Rejected { rejection } => Poll::Ready(Err(rejection
+                .take()
+                .expect("ResponseFuture polled after completion"))),
+        }
+    }
+}
@@ -1,8 +1,83 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
 mod circuit_breaker;
 mod circuit_state;
+#[cfg(feature = "std")]
+pub mod classifiers;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
+mod composite;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod dispatcher;
+#[cfg(feature = "std")]
 mod error;
+#[cfg(feature = "std")]
+mod history;
+#[cfg(feature = "tower")]
+mod layer;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "reqwest")]
+mod middleware;
+#[cfg(feature = "std")]
+mod policy;
+#[cfg(feature = "std")]
+mod prober;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "async")]
+mod signal;
+mod state_machine;
+#[cfg(feature = "std")]
+mod store;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+mod typed;
 
-pub use circuit_breaker::CircuitBreaker;
-pub use circuit_state::CircuitState;
-pub use error::CircuitBreakerError;
-
+#[cfg(feature = "std")]
+pub use builder::{CircuitBreakerBuilder, DEFAULT_FAILURE_THRESHOLD, DEFAULT_RESET_TIMEOUT};
+#[cfg(feature = "std")]
+pub use circuit_breaker::{CallPermit, CircuitBreaker, ExecOutcome, TypedCircuitBreaker};
+pub use circuit_state::{CircuitState, ParseStateError};
+#[cfg(feature = "std")]
+pub use circuit_state::{Health, StateChange, StateDurations};
+#[cfg(feature = "std")]
+pub use clock::{Clock, SystemClock, TickClock};
+#[cfg(feature = "std")]
+pub use composite::{AggregationMode, CompositeBreaker};
+#[cfg(feature = "std")]
+pub use config::CircuitConfig;
+#[cfg(feature = "std")]
+pub use error::{BreakerError, CircuitBreakerError, ConfigError, RejectionReason};
+#[cfg(feature = "std")]
+pub use history::Outcome;
+#[cfg(feature = "tower")]
+pub use layer::{CircuitBreakerLayer, CircuitBreakerService, ResponseFuture};
+#[cfg(feature = "std")]
+pub use metrics::{
+    export_prometheus, CircuitMetrics, MetricsDelta, MetricsRecorder, NoopMetricsRecorder,
+};
+#[cfg(feature = "reqwest")]
+pub use middleware::{CircuitBreakerMiddleware, HttpStatusError};
+#[cfg(feature = "std")]
+pub use policy::{
+    ClosedResetMode, FailureAction, FailurePolicy, FailureReason, OpenBehavior, PanicBehavior,
+};
+#[cfg(feature = "std")]
+pub use registry::CircuitBreakerRegistry;
+#[cfg(feature = "std")]
+pub use sharded::ShardedCircuitBreaker;
+pub use state_machine::{SharedStateMachine, StateLock, StateMachine, Tick};
+#[cfg(feature = "std")]
+pub use store::{InMemoryStateStore, StateStore, StoredState};
+#[cfg(feature = "std")]
+pub use typed::Untyped;
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::circuit_state::CircuitState;
+use crate::policy::FailureReason;
+
+/// A snapshot of a circuit breaker's counters, returned by [`CircuitBreaker::metrics`].
+///
+/// [`CircuitBreaker::metrics`]: crate::CircuitBreaker::metrics
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitMetrics {
+    /// The state of the circuit when the snapshot was taken.
+    pub state: CircuitState,
+    /// The current value of the failure counter used for the threshold.
+    pub failures: u32,
+    /// The total number of successful calls.
+    pub total_successes: u64,
+    /// The total number of failed calls.
+    pub total_failures: u64,
+    /// The total number of calls rejected without being executed.
+    pub rejected_calls: u64,
+    /// The number of times the circuit has opened.
+    pub trip_count: u64,
+    /// The number of successes recorded since the last failure.
+    pub consecutive_successes: u64,
+    /// The failures tagged with a reason by `execute_with_reason`, counted per reason. Failures
+    /// recorded without a reason are only included in `total_failures`.
+    pub failure_reasons: BTreeMap<FailureReason, u64>,
+}
+
+impl CircuitMetrics {
+    /// Returns the change in counters between an earlier snapshot `other` and this one.
+    ///
+    /// Counts are saturating, so diffing against a later snapshot yields zeros rather than
+    /// wrapping. Dividing the deltas by the time between the snapshots gives call rates.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The earlier snapshot to compare against.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use circuit_breaker::CircuitBreaker;
+    /// use std::time::Duration;
+    ///
+    /// let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// let before = cb.metrics();
+    /// cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+    ///
+    /// let delta = cb.metrics().diff(&before);
+    /// assert_eq!(delta.successes, 1);
+    /// assert!(!delta.state_changed);
+    /// ```
+    pub fn diff(&self, other: &CircuitMetrics) -> MetricsDelta {
+        MetricsDelta {
+            successes: self.total_successes.saturating_sub(other.total_successes),
+            failures: self.total_failures.saturating_sub(other.total_failures),
+            rejected_calls: self.rejected_calls.saturating_sub(other.rejected_calls),
+            trips: self.trip_count.saturating_sub(other.trip_count),
+            state_changed: self.state != other.state,
+        }
+    }
+}
+
+/// The difference between two [`CircuitMetrics`] snapshots, returned by [`CircuitMetrics::diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricsDelta {
+    /// The number of successful calls between the snapshots.
+    pub successes: u64,
+    /// The number of failed calls between the snapshots.
+    pub failures: u64,
+    /// The number of rejected calls between the snapshots.
+    pub rejected_calls: u64,
+    /// The number of times the circuit opened between the snapshots.
+    pub trips: u64,
+    /// Whether the state differs between the snapshots. A circuit that opened and closed again
+    /// in between is reported as unchanged.
+    pub state_changed: bool,
+}
+
+/// Receives events from a circuit breaker so they can be forwarded to a metrics backend such as
+/// Prometheus or StatsD.
+///
+/// All methods have empty default implementations, so a recorder only needs to implement the
+/// events it cares about. Recorders are always invoked without the breaker's internal lock held.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitState, MetricsRecorder};
+/// use std::time::Duration;
+///
+/// struct LoggingRecorder;
+///
+/// impl MetricsRecorder for LoggingRecorder {
+///     fn record_state_change(&self, from: CircuitState, to: CircuitState) {
+///         println!("circuit moved from {} to {}", from, to);
+///     }
+/// }
+///
+/// let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+///     .metrics_recorder(Box::new(LoggingRecorder))
+///     .build()?;
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+pub trait MetricsRecorder: Send + Sync {
+    /// Called when a call through the breaker succeeds.
+    fn record_success(&self) {}
+
+    /// Called when a call through the breaker fails.
+    fn record_failure(&self) {}
+
+    /// Called when a call is rejected without being executed.
+    fn record_rejected(&self) {}
+
+    /// Called when the circuit transitions from one state to another.
+    fn record_state_change(&self, _from: CircuitState, _to: CircuitState) {}
+}
+
+/// A `MetricsRecorder` that ignores every event. This is the default recorder.
+#[derive(Debug, Default)]
+pub struct NoopMetricsRecorder;
+
+impl MetricsRecorder for NoopMetricsRecorder {}
+
+/// Allows a recorder to be shared, so the caller can keep a handle to it after passing a clone to
+/// the builder.
+impl<R: MetricsRecorder + ?Sized> MetricsRecorder for Arc<R> {
+    fn record_success(&self) {
+        (**self).record_success();
+    }
+
+    fn record_failure(&self) {
+        (**self).record_failure();
+    }
+
+    fn record_rejected(&self) {
+        (**self).record_rejected();
+    }
+
+    fn record_state_change(&self, from: CircuitState, to: CircuitState) {
+        (**self).record_state_change(from, to);
+    }
+}
+
+/// Reads the value of a Prometheus series from a snapshot.
+type SeriesValue = fn(&CircuitMetrics) -> u64;
+
+/// The per-breaker series rendered by [`export_prometheus`] after the state: the metric name,
+/// its type, its help text and how to read it from a snapshot.
+const PROMETHEUS_SERIES: [(&str, &str, &str, SeriesValue); 5] = [
+    (
+        "circuit_breaker_failure_count",
+        "gauge",
+        "The current value of the failure counter used for the threshold.",
+        |metrics| u64::from(metrics.failures),
+    ),
+    (
+        "circuit_breaker_successes_total",
+        "counter",
+        "The total number of successful calls.",
+        |metrics| metrics.total_successes,
+    ),
+    (
+        "circuit_breaker_failures_total",
+        "counter",
+        "The total number of failed calls.",
+        |metrics| metrics.total_failures,
+    ),
+    (
+        "circuit_breaker_rejected_calls_total",
+        "counter",
+        "The total number of calls rejected without being executed.",
+        |metrics| metrics.rejected_calls,
+    ),
+    (
+        "circuit_breaker_trips_total",
+        "counter",
+        "The number of times the circuit has opened.",
+        |metrics| metrics.trip_count,
+    ),
+];
+
+/// Renders the state and counters of named circuit breakers in the Prometheus text exposition
+/// format, for serving from a scrape endpoint.
+///
+/// Every series carries a `name` label with the breaker's name. The state is exported as
+/// `circuit_breaker_state`, with one series per `state` label (`closed`, `open` or `half_open`)
+/// set to `1` for the current state and `0` for the others. The failure counter compared
+/// against the threshold follows as the gauge `circuit_breaker_failure_count`, and the counters
+/// from [`CircuitBreaker::metrics`] as `circuit_breaker_successes_total`,
+/// `circuit_breaker_failures_total`, `circuit_breaker_rejected_calls_total` and
+/// `circuit_breaker_trips_total`. Breakers whose counters are reset with
+/// [`CircuitBreaker::drain_metrics`] should not be exported, as Prometheus counters must never
+/// decrease.
+///
+/// This does not depend on a Prometheus client library or register with its registry: the
+/// returned text is meant to be served as is, or appended to a registry's own output.
+///
+/// # Arguments
+///
+/// * `breakers` - The breakers to export, each paired with its name.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{export_prometheus, CircuitBreaker};
+/// use std::time::Duration;
+///
+/// let payments = CircuitBreaker::new(3, Duration::from_secs(60));
+/// let search = CircuitBreaker::new(5, Duration::from_secs(30));
+/// search.handle_failure();
+///
+/// let text = export_prometheus([("payments", &payments), ("search", &search)]);
+/// assert!(text.contains("circuit_breaker_failure_count{name=\"search\"} 1\n"));
+/// ```
+pub fn export_prometheus<'a, I>(breakers: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a CircuitBreaker)>,
+{
+    let snapshots: Vec<_> = breakers
+        .into_iter()
+        .map(|(name, breaker)| (escape_label(name), breaker.metrics()))
+        .collect();
+
+    // Writing to a `String` cannot fail.
+    let mut text = String::new();
+    text.push_str("# HELP circuit_breaker_state Whether the circuit is in the given state.\n");
+    text.push_str("# TYPE circuit_breaker_state gauge\n");
+    for (name, metrics) in &snapshots {
+        for (state, label) in [
+            (CircuitState::Closed, "closed"),
+            (CircuitState::Open, "open"),
+            (CircuitState::HalfOpen, "half_open"),
+        ] {
+            let _ = writeln!(
+                text,
+                "circuit_breaker_state{{name=\"{}\",state=\"{}\"}} {}",
+                name,
+                label,
+                u8::from(metrics.state == state)
+            );
+        }
+    }
+    for (metric, kind, help, value) in PROMETHEUS_SERIES {
+        let _ = writeln!(text, "# HELP {} {}", metric, help);
+        let _ = writeln!(text, "# TYPE {} {}", metric, kind);
+        for (name, metrics) in &snapshots {
+            let _ = writeln!(text, "{}{{name=\"{}\"}} {}", metric, name, value(metrics));
+        }
+    }
+    text
+}
+
+/// Escapes a label value for the Prometheus text exposition format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
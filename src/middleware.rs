@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::policy::OpenBehavior;
+
+/// A [`reqwest-middleware`](https://docs.rs/reqwest-middleware) middleware that runs outbound
+/// HTTP requests through a [`CircuitBreaker`].
+///
+/// A request made while the circuit is open is rejected without being sent, with a
+/// `reqwest_middleware::Error::Middleware` wrapping the `CircuitBreakerError`. Otherwise the
+/// outcome of the request is recorded:
+///
+/// * A `5xx` response is passed to the error classifier as an [`HttpStatusError`], and counts
+///   as a failure unless the classifier ignores it. The response itself is still returned.
+/// * A `4xx` response describes a problem with the request rather than the server, so it is
+///   recorded as neither a success nor a failure. A half-open trial it was made as is released
+///   for the next request.
+/// * Any other response is a success.
+/// * A transport error, such as a refused connection, is passed to the error classifier.
+///
+/// Requires the `reqwest` feature.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitBreakerMiddleware};
+/// use reqwest_middleware::ClientBuilder;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let breaker = Arc::new(CircuitBreaker::new(5, Duration::from_secs(30)));
+/// let client = ClientBuilder::new(reqwest::Client::new())
+///     .with(CircuitBreakerMiddleware::new(breaker))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreakerMiddleware {
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CircuitBreakerMiddleware {
+    /// Creates a new `CircuitBreakerMiddleware` applying `breaker` to every request.
+    pub fn new(breaker: Arc<CircuitBreaker>) -> Self {
+        CircuitBreakerMiddleware { breaker }
+    }
+
+    /// Returns the circuit breaker applied to requests.
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+}
+
+#[async_trait]
+impl Middleware for CircuitBreakerMiddleware {
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let (_in_flight, admission) = self
+            .breaker
+            .admit_with(None, OpenBehavior::FailFast)
+            .map_err(reqwest_middleware::Error::middleware)?;
+
+        match next.run(request, extensions).await {
+            Ok(response) => {
+                let status = response.status();
+                if status.is_server_error() {
+                    self.breaker
+                        .fail(admission, &HttpStatusError { status }, None);
+                } else if !status.is_client_error() {
+                    self.breaker.complete(admission, true, None);
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                self.breaker.fail(admission, &e, None);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A `5xx` response, as passed to the error classifier by [`CircuitBreakerMiddleware`].
+///
+/// Requires the `reqwest` feature.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, HttpStatusError};
+/// use reqwest::StatusCode;
+/// use std::time::Duration;
+///
+/// // Count every server error except 501 Not Implemented.
+/// let cb = CircuitBreaker::builder(5, Duration::from_secs(30))
+///     .error_classifier(|e| {
+///         e.downcast_ref::<HttpStatusError>()
+///             .map_or(true, |e| e.status != StatusCode::NOT_IMPLEMENTED)
+///     })
+///     .build()?;
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpStatusError {
+    /// The status of the response.
+    pub status: StatusCode,
+}
+
+impl Error for HttpStatusError {}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Server responded with {}", self.status)
+    }
+}
@@ -0,0 +1,292 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Decides when accumulated failures open the circuit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum FailurePolicy {
+    /// Opens the circuit once the failure counter reaches the failure threshold. This is the
+    /// default policy.
+    ConsecutiveFailures,
+    /// Opens the circuit once the failure rate over a rolling time window reaches `failure_rate`.
+    ///
+    /// The window is divided into `buckets` equally sized buckets, which are rolled forward as
+    /// time passes so that outcomes older than `duration` no longer count.
+    TimeWindow {
+        /// The length of the rolling window.
+        duration: Duration,
+        /// The number of buckets the window is divided into.
+        buckets: usize,
+        /// The failure rate, between 0.0 and 1.0, at which the circuit opens.
+        ///
+        /// The rate is only evaluated once the window holds at least the builder's
+        /// `minimum_throughput` calls.
+        failure_rate: f64,
+    },
+    /// Opens the circuit once the share of slow calls among the last `window` calls reaches
+    /// `rate`, whether or not those calls failed.
+    ///
+    /// A call is slow when it takes longer than `threshold`. Only calls whose duration the
+    /// breaker measures, those made through `execute` and its variants or a `CallPermit`, are
+    /// recorded; `handle_success` and `handle_failure` are not. The rate is only evaluated once
+    /// `window` calls have been recorded, and is evaluated after every call while the circuit
+    /// is closed.
+    SlowCallRate {
+        /// The duration above which a call counts as slow.
+        threshold: Duration,
+        /// The share of slow calls, between 0.0 and 1.0, at which the circuit opens.
+        rate: f64,
+        /// The number of most recent calls the rate is measured over.
+        window: usize,
+    },
+}
+
+/// How a success made while the circuit is closed affects the failure counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ClosedResetMode {
+    /// Successes leave the counter alone, so failures accumulate until the threshold is reached
+    /// or they age out through `failure_decay`. This is the default.
+    #[default]
+    Keep,
+    /// A success resets the counter to zero, so only an unbroken run of failures opens the
+    /// circuit.
+    Reset,
+    /// A success decrements the counter by one, so sporadic successes slow down, but do not hide,
+    /// accumulating failures.
+    Decrement,
+}
+
+/// What a call made while the circuit is open does, as set with
+/// `CircuitBreakerBuilder::open_behavior`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OpenBehavior {
+    /// The call is rejected immediately with `CircuitOpen`. This is the default.
+    #[default]
+    FailFast,
+    /// The call blocks for up to `max` in case the circuit leaves Open in the meantime, either
+    /// because the reset timeout elapses or because another thread closes it. It is then
+    /// admitted as usual, or rejected with `CircuitOpen` if the circuit is still open.
+    WaitForReset {
+        /// The longest a call waits, measured by the breaker's clock.
+        max: Duration,
+    },
+}
+
+/// What `execute_catch_unwind` does with a panic in the executed closure, once it has been
+/// recorded as a failure, as set with `CircuitBreakerBuilder::on_panic`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum PanicBehavior {
+    /// The panic is returned as `CircuitBreakerError::OperationPanicked`. This is the default.
+    #[default]
+    Return,
+    /// The panic resumes unwinding through the caller with its original payload.
+    Resume,
+}
+
+/// What a failure classifier decides an error does to the circuit.
+///
+/// Returned by the classifier set with `CircuitBreakerBuilder::failure_classifier`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FailureAction {
+    /// Count the error as one failure.
+    Count,
+    /// Return the error to the caller without recording it as a failure or a success.
+    Ignore,
+    /// Count the error as the given number of failures, both in the failure counter and in a
+    /// `TimeWindow` policy's failure rate.
+    CountWeighted(u32),
+    /// Count the error as a failure and open the circuit immediately, whatever the threshold.
+    ForceOpen,
+}
+
+/// Why an error counted as a failure, as tagged by the mapping passed to
+/// `CircuitBreaker::execute_with_reason`.
+///
+/// Failures are broken down by reason in `CircuitMetrics::failure_reasons`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FailureReason(Cow<'static, str>);
+
+impl FailureReason {
+    /// Creates a reason from a static string or an owned `String`.
+    pub fn new(reason: impl Into<Cow<'static, str>>) -> Self {
+        FailureReason(reason.into())
+    }
+
+    /// Returns the reason as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&'static str> for FailureReason {
+    fn from(reason: &'static str) -> Self {
+        FailureReason::new(reason)
+    }
+}
+
+impl From<String> for FailureReason {
+    fn from(reason: String) -> Self {
+        FailureReason::new(reason)
+    }
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Tracks which of the most recent calls were slow, for [`FailurePolicy::SlowCallRate`].
+pub(crate) struct SlowCallWindow {
+    threshold: Duration,
+    rate: f64,
+    capacity: usize,
+    calls: VecDeque<bool>,
+    slow: usize,
+}
+
+impl SlowCallWindow {
+    pub(crate) fn new(threshold: Duration, rate: f64, capacity: usize) -> Self {
+        SlowCallWindow {
+            threshold,
+            rate,
+            capacity,
+            calls: VecDeque::with_capacity(capacity),
+            slow: 0,
+        }
+    }
+
+    /// Records a call that took `latency`, evicting the oldest call once the window is full.
+    pub(crate) fn record(&mut self, latency: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.calls.len() == self.capacity && self.calls.pop_front() == Some(true) {
+            self.slow -= 1;
+        }
+        let slow = latency > self.threshold;
+        self.calls.push_back(slow);
+        self.slow += usize::from(slow);
+    }
+
+    /// Returns whether the window is full and its share of slow calls has reached the rate.
+    pub(crate) fn rate_reached(&self) -> bool {
+        self.capacity > 0
+            && self.calls.len() == self.capacity
+            && self.slow as f64 / self.capacity as f64 >= self.rate
+    }
+
+    /// Forgets every recorded call.
+    pub(crate) fn clear(&mut self) {
+        self.calls.clear();
+        self.slow = 0;
+    }
+}
+
+/// Counts successes and failures in time buckets covering a rolling window.
+///
+/// Buckets are advanced lazily whenever the counter is touched, so no background work is needed.
+pub(crate) struct TimeWindowCounter {
+    bucket_width: Duration,
+    buckets: Vec<Bucket>,
+    current: usize,
+    current_start: Option<Instant>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    successes: u32,
+    failures: u32,
+}
+
+impl TimeWindowCounter {
+    pub(crate) fn new(duration: Duration, buckets: usize) -> Self {
+        let buckets = buckets.max(1);
+        let bucket_width = (duration / buckets as u32).max(Duration::from_nanos(1));
+        TimeWindowCounter {
+            bucket_width,
+            buckets: vec![Bucket::default(); buckets],
+            current: 0,
+            current_start: None,
+        }
+    }
+
+    /// Records `count` calls made at `now` with the same outcome, as a weighted failure is.
+    pub(crate) fn record(&mut self, now: Instant, success: bool, count: u32) {
+        self.advance(now);
+        let bucket = &mut self.buckets[self.current];
+        if success {
+            bucket.successes = bucket.successes.saturating_add(count);
+        } else {
+            bucket.failures = bucket.failures.saturating_add(count);
+        }
+    }
+
+    /// Returns the failure rate over the live buckets, or 0.0 if no calls were recorded.
+    pub(crate) fn failure_rate(&mut self, now: Instant) -> f64 {
+        self.advance(now);
+        let (successes, failures) = self.totals();
+        let total = successes + failures;
+        if total == 0 {
+            0.0
+        } else {
+            failures as f64 / total as f64
+        }
+    }
+
+    /// Returns the number of calls recorded in the live buckets.
+    pub(crate) fn calls(&mut self, now: Instant) -> u64 {
+        self.advance(now);
+        let (successes, failures) = self.totals();
+        successes + failures
+    }
+
+    /// Clears every bucket.
+    pub(crate) fn clear(&mut self) {
+        self.buckets.fill(Bucket::default());
+        self.current_start = None;
+    }
+
+    fn totals(&self) -> (u64, u64) {
+        self.buckets.iter().fold((0, 0), |(s, f), bucket| {
+            (
+                s + u64::from(bucket.successes),
+                f + u64::from(bucket.failures),
+            )
+        })
+    }
+
+    /// Rolls the window forward to `now`, clearing buckets that have expired.
+    fn advance(&mut self, now: Instant) {
+        let start = match self.current_start {
+            Some(start) => start,
+            None => {
+                self.current_start = Some(now);
+                return;
+            }
+        };
+
+        let steps = now.saturating_duration_since(start).as_nanos() / self.bucket_width.as_nanos();
+        if steps == 0 {
+            return;
+        }
+
+        let len = self.buckets.len();
+        for _ in 0..steps.min(len as u128) {
+            self.current = (self.current + 1) % len;
+            self.buckets[self.current] = Bucket::default();
+        }
+
+        self.current_start = if steps < len as u128 {
+            Some(start + self.bucket_width * steps as u32)
+        } else {
+            Some(now)
+        };
+    }
+}
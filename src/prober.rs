@@ -0,0 +1,72 @@
+use std::sync::{Arc, Condvar, Mutex, PoisonError, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::circuit_breaker::TypedCircuitBreaker;
+use crate::circuit_state::CircuitState;
+
+/// A background thread that runs a health probe while the circuit is half-open.
+pub(crate) struct Prober {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Prober {
+    /// Spawns the probing thread. It holds only a weak reference to the breaker, so it exits once
+    /// the breaker is dropped.
+    pub(crate) fn spawn<P, E>(
+        breaker: Weak<TypedCircuitBreaker<E>>,
+        interval: Duration,
+        probe: P,
+    ) -> Self
+    where
+        P: Fn() -> bool + Send + Sync + 'static,
+        E: 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+
+        let handle = thread::spawn(move || loop {
+            let (stopped, condvar) = &*thread_stop;
+            let guard = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+            let (guard, _) = condvar
+                .wait_timeout_while(guard, interval, |stopped| !*stopped)
+                .unwrap_or_else(PoisonError::into_inner);
+            if *guard {
+                return;
+            }
+            drop(guard);
+
+            let Some(breaker) = breaker.upgrade() else {
+                return;
+            };
+            if breaker.state() == CircuitState::HalfOpen {
+                if probe() {
+                    breaker.handle_success();
+                } else {
+                    breaker.handle_failure();
+                }
+            }
+        });
+
+        Prober {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Signals the thread to stop and waits for it to exit.
+    pub(crate) fn stop(mut self) {
+        let (stopped, condvar) = &*self.stop;
+        *stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        condvar.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            // The last reference to the breaker may be released by the prober thread itself, in
+            // which case it is already on its way out and must not join itself.
+            if handle.thread().id() != thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::circuit_breaker::CircuitBreaker;
+#[cfg(feature = "async")]
+use crate::metrics;
+
+/// A set of circuit breakers looked up by name, for applications guarding several dependencies.
+///
+/// Breakers are shared as `Arc`s, so a breaker obtained from the registry keeps working after
+/// it has been removed or replaced. With the `async` feature, the registry can render all of its
+/// breakers for a Prometheus scrape with [`export_prometheus`](Self::export_prometheus).
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitBreakerRegistry, CircuitState};
+/// use std::time::Duration;
+///
+/// let registry = CircuitBreakerRegistry::new();
+/// registry.register("payments", CircuitBreaker::new(1, Duration::from_secs(60)));
+///
+/// let payments = registry.get("payments").unwrap();
+/// payments.handle_failure();
+/// assert_eq!(registry.get("payments").unwrap().state(), CircuitState::Open);
+/// assert!(registry.get("search").is_none());
+/// ```
+#[derive(Default)]
+pub struct CircuitBreakerRegistry {
+    breakers: RwLock<BTreeMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl CircuitBreakerRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        CircuitBreakerRegistry::default()
+    }
+
+    /// Adds `breaker` under `name`, replacing any breaker already registered under it, and
+    /// returns the shared breaker.
+    pub fn register(
+        &self,
+        name: impl Into<String>,
+        breaker: CircuitBreaker,
+    ) -> Arc<CircuitBreaker> {
+        let breaker = Arc::new(breaker);
+        self.breakers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.into(), breaker.clone());
+        breaker
+    }
+
+    /// Returns the breaker registered under `name`, if any.
+    pub fn get(&self, name: &str) -> Option<Arc<CircuitBreaker>> {
+        self.breakers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+    }
+
+    /// Removes the breaker registered under `name` and returns it, if any.
+    pub fn remove(&self, name: &str) -> Option<Arc<CircuitBreaker>> {
+        self.breakers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name)
+    }
+
+    /// Returns the names of the registered breakers, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.breakers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Renders the state and counters of every registered breaker in the Prometheus text
+    /// exposition format, labelled with the names they are registered under.
+    ///
+    /// The metric names and help text are those of the free function
+    /// [`export_prometheus`](crate::export_prometheus). The breakers are snapshotted without
+    /// blocking on anything but their own locks, so the future completes on its first poll and
+    /// can be awaited directly in a scrape handler. Requires the `async` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use circuit_breaker::{CircuitBreaker, CircuitBreakerRegistry};
+    /// # use std::time::Duration;
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let registry = CircuitBreakerRegistry::new();
+    /// registry.register("payments", CircuitBreaker::new(3, Duration::from_secs(60)));
+    ///
+    /// let body = registry.export_prometheus().await;
+    /// assert!(body.contains("circuit_breaker_state{name=\"payments\",state=\"closed\"} 1\n"));
+    /// # });
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn export_prometheus(&self) -> String {
+        let breakers: Vec<_> = self
+            .breakers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, breaker)| (name.clone(), breaker.clone()))
+            .collect();
+        metrics::export_prometheus(
+            breakers
+                .iter()
+                .map(|(name, breaker)| (name.as_str(), &**breaker)),
+        )
+    }
+}
@@ -0,0 +1,210 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::circuit_state::CircuitState;
+use crate::policy::FailureAction;
+
+/// A circuit breaker for high-contention workloads, counting outcomes in per-thread shards that
+/// are periodically rolled up into a shared [`CircuitBreaker`].
+///
+/// While the circuit is closed, calls do not record their outcome under the shared breaker's
+/// lock: successes and failures are added to the calling thread's shard instead. A shard is
+/// flushed into the shared breaker, which drives the actual state, once it holds
+/// `flush_threshold` failures or `flush_interval` has passed since its last flush. Every call
+/// reads the shared breaker's state first, which takes no lock with the `arc-swap` feature, and
+/// counts towards its concurrency limit. When the circuit is not closed, or the shared breaker is
+/// still warming up, calls go straight through the shared breaker, so rejections and half-open
+/// trials behave as usual.
+///
+/// Because outcomes reach the shared breaker in batches, it may open slightly later than an
+/// unsharded breaker would. Within a batch, successes are recorded before failures, so policies
+/// that depend on the order of outcomes, such as
+/// [`FailurePolicy::ConsecutiveFailures`](crate::FailurePolicy::ConsecutiveFailures) with a
+/// closed reset mode other than `Keep`, only see it approximately. Calls made on the fast path
+/// are not timed, so slow-call policies only see calls made through the shared breaker.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitState, ShardedCircuitBreaker};
+/// use std::time::Duration;
+///
+/// let cb = ShardedCircuitBreaker::new(
+///     CircuitBreaker::new(3, Duration::from_secs(60)),
+///     8,
+///     2,
+///     Duration::from_millis(100),
+/// );
+/// let result = cb.execute(|| Ok::<_, std::io::Error>("Operation successful"));
+/// assert!(result.is_ok());
+/// assert_eq!(cb.state(), CircuitState::Closed);
+/// ```
+pub struct ShardedCircuitBreaker {
+    inner: CircuitBreaker,
+    shards: Vec<Shard>,
+    flush_threshold: u32,
+    flush_interval: Duration,
+    epoch: Instant,
+}
+
+/// A shard's pending outcomes, padded to its own cache line to avoid false sharing.
+#[repr(align(64))]
+struct Shard {
+    successes: AtomicU32,
+    /// The combined weight of the pending failures, and how many calls failed.
+    failures: AtomicU32,
+    failed_calls: AtomicU32,
+    last_flush: AtomicU64,
+}
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+impl ShardedCircuitBreaker {
+    /// Creates a new `ShardedCircuitBreaker` rolling outcomes up into `inner`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The shared circuit breaker that drives the state.
+    /// * `shards` - The number of shards. Threads are spread across them round-robin.
+    /// * `flush_threshold` - The number of failures a shard holds before it is flushed.
+    /// * `flush_interval` - The longest a shard holds a failure before it is flushed.
+    pub fn new(
+        inner: CircuitBreaker,
+        shards: usize,
+        flush_threshold: u32,
+        flush_interval: Duration,
+    ) -> Self {
+        ShardedCircuitBreaker {
+            epoch: inner.clock.now(),
+            inner,
+            shards: (0..shards.max(1))
+                .map(|_| Shard {
+                    successes: AtomicU32::new(0),
+                    failures: AtomicU32::new(0),
+                    failed_calls: AtomicU32::new(0),
+                    last_flush: AtomicU64::new(0),
+                })
+                .collect(),
+            flush_threshold: flush_threshold.max(1),
+            flush_interval,
+        }
+    }
+
+    /// Returns the shared circuit breaker.
+    pub fn inner(&self) -> &CircuitBreaker {
+        &self.inner
+    }
+
+    /// Returns the state of the shared circuit breaker.
+    ///
+    /// Like [`CircuitBreaker::state`], this may transition the circuit from Open to Half-Open.
+    pub fn state(&self) -> CircuitState {
+        self.inner.state()
+    }
+
+    /// Executes the given function within the circuit breaker.
+    ///
+    /// While the circuit is closed, this runs `f` without recording its outcome under the shared
+    /// breaker's lock, counting it in the calling thread's shard instead. The call still counts
+    /// towards the shared breaker's concurrency limit. Otherwise it behaves like
+    /// [`CircuitBreaker::execute`] on the shared breaker.
+    pub fn execute<F, T, E>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: std::error::Error + 'static,
+    {
+        if self.inner.peek_state() != CircuitState::Closed
+            || self.inner.warming_up(self.inner.clock.now())
+        {
+            return self.inner.execute(f);
+        }
+
+        let _in_flight = CircuitBreaker::enter(&self.inner)?;
+        match f() {
+            Ok(result) => {
+                self.record(true, 0, false);
+                Ok(result)
+            }
+            Err(e) => {
+                let (weight, force_open) = match self.inner.classify(&e) {
+                    FailureAction::Ignore => return Err(Box::new(e)),
+                    FailureAction::Count => (1, false),
+                    FailureAction::CountWeighted(weight) => (weight, false),
+                    FailureAction::ForceOpen => (1, true),
+                };
+                self.record(false, weight, force_open);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Flushes every shard's pending outcomes into the shared circuit breaker.
+    pub fn flush(&self) {
+        for shard in &self.shards {
+            self.flush_shard(shard, false);
+        }
+    }
+
+    /// Counts a call's outcome in the calling thread's shard: a success, or a failure of
+    /// `weight`. The shard is flushed once its failures reach the threshold, when the flush
+    /// interval has passed, or at once with `force_open`.
+    fn record(&self, success: bool, weight: u32, force_open: bool) {
+        let shard = &self.shards[self.shard_index()];
+        if success {
+            shard.successes.fetch_add(1, Ordering::AcqRel);
+        } else {
+            shard.failed_calls.fetch_add(1, Ordering::AcqRel);
+        }
+        let pending = shard
+            .failures
+            .fetch_add(weight, Ordering::AcqRel)
+            .saturating_add(weight);
+
+        let now = self.elapsed_nanos();
+        let due = now.saturating_sub(shard.last_flush.load(Ordering::Acquire))
+            >= self.flush_interval.as_nanos() as u64;
+        if force_open || pending >= self.flush_threshold || due {
+            self.flush_shard(shard, force_open);
+        }
+    }
+
+    fn flush_shard(&self, shard: &Shard, force_open: bool) {
+        shard
+            .last_flush
+            .store(self.elapsed_nanos(), Ordering::Release);
+        let successes = shard.successes.swap(0, Ordering::AcqRel);
+        self.inner.record_successes(None, false, successes);
+        let pending = shard.failures.swap(0, Ordering::AcqRel);
+        let failed_calls = shard.failed_calls.swap(0, Ordering::AcqRel);
+        if failed_calls > 0 || force_open {
+            self.inner
+                .record_failures(pending, failed_calls, force_open);
+        }
+    }
+
+    fn shard_index(&self) -> usize {
+        let index = SHARD_INDEX.with(|index| match index.get() {
+            Some(index) => index,
+            None => {
+                let next = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+                index.set(Some(next));
+                next
+            }
+        });
+        index % self.shards.len()
+    }
+
+    fn elapsed_nanos(&self) -> u64 {
+        self.inner
+            .clock
+            .now()
+            .saturating_duration_since(self.epoch)
+            .as_nanos() as u64
+    }
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// A one-shot signal registered in the breaker's state and fired on a transition.
+#[derive(Default)]
+pub(crate) struct Signal {
+    state: Mutex<SignalState>,
+}
+
+#[derive(Default)]
+struct SignalState {
+    fired: bool,
+    waker: Option<Waker>,
+}
+
+impl Signal {
+    /// Marks the signal as fired and wakes the task awaiting it, if any.
+    pub(crate) fn fire(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.fired = true;
+        let waker = state.waker.take();
+        drop(state);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// A future that completes once its [`Signal`] has fired.
+pub(crate) struct SignalFuture {
+    signal: Arc<Signal>,
+}
+
+impl SignalFuture {
+    pub(crate) fn new(signal: Arc<Signal>) -> Self {
+        SignalFuture { signal }
+    }
+}
+
+impl Future for SignalFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self
+            .signal
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if state.fired {
+            return Poll::Ready(());
+        }
+        match state.waker {
+            Some(ref waker) if waker.will_wake(cx.waker()) => {}
+            _ => state.waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,305 @@
+use core::fmt::Debug;
+
+use crate::circuit_state::CircuitState;
+
+/// A point in time as the [`StateMachine`] sees it.
+///
+/// It is implemented for `u64` tick counts, such as milliseconds from a hardware timer, and with
+/// the `std` feature for `std::time::Instant`, which is what `CircuitBreaker` uses.
+pub trait Tick: Copy + Debug {
+    /// The time between two ticks, in which the reset timeout is expressed.
+    type Span: Copy + PartialOrd + Debug;
+
+    /// Returns the time elapsed from `earlier` to `self`, or zero if `earlier` is later.
+    fn since(self, earlier: Self) -> Self::Span;
+}
+
+impl Tick for u64 {
+    type Span = u64;
+
+    fn since(self, earlier: Self) -> u64 {
+        self.saturating_sub(earlier)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Tick for std::time::Instant {
+    type Span = std::time::Duration;
+
+    fn since(self, earlier: Self) -> std::time::Duration {
+        self.saturating_duration_since(earlier)
+    }
+}
+
+/// The core circuit breaker state machine, driven by caller-supplied ticks.
+///
+/// `StateMachine` does not depend on `std`: time is any [`Tick`], by default an opaque,
+/// monotonically increasing `u64` tick count (for example milliseconds from a hardware timer),
+/// and it does no locking of its own. Callers that share it between contexts wrap it in a
+/// [`SharedStateMachine`] with whatever lock their platform provides. It is available without
+/// the default `std` feature, for embedded use.
+///
+/// `CircuitBreaker` keeps its state, failure counter and last failure time in a
+/// `StateMachine<Instant>` and leaves the reset timeout, the failure counter and the threshold
+/// check to it, adding its other features (failure policies, half-open quotas, callbacks and
+/// so on) on top. Driven the same way, the two move through the same states.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitState, StateMachine};
+///
+/// let mut sm = StateMachine::new(2, 100);
+/// sm.record_failure(0);
+/// sm.record_failure(1);
+/// assert_eq!(sm.state(), CircuitState::Open);
+///
+/// assert!(!sm.try_acquire(50));
+/// assert!(sm.try_acquire(101));
+/// assert_eq!(sm.state(), CircuitState::HalfOpen);
+///
+/// sm.record_success();
+/// assert_eq!(sm.state(), CircuitState::Closed);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StateMachine<T: Tick = u64> {
+    failure_threshold: u32,
+    reset_timeout: T::Span,
+    state: CircuitState,
+    failures: u32,
+    last_failure: Option<T>,
+}
+
+impl StateMachine {
+    /// Creates a new `StateMachine` counting time in `u64` ticks, in the Closed state.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure_threshold` - The number of failures that must occur before the circuit opens.
+    ///   A threshold of `0` means the circuit never opens automatically.
+    /// * `reset_timeout` - The number of ticks after the last failure at which the circuit moves
+    ///   from Open to Half-Open.
+    pub const fn new(failure_threshold: u32, reset_timeout: u64) -> Self {
+        Self::with_tick(failure_threshold, reset_timeout)
+    }
+}
+
+impl<T: Tick> StateMachine<T> {
+    /// Creates a new `StateMachine` counting time in ticks of type `T`, in the Closed state.
+    ///
+    /// This is [`new`](StateMachine::new) for any [`Tick`], such as `std::time::Instant` with
+    /// a `Duration` reset timeout.
+    pub const fn with_tick(failure_threshold: u32, reset_timeout: T::Span) -> Self {
+        StateMachine {
+            failure_threshold,
+            reset_timeout,
+            state: CircuitState::Closed,
+            failures: 0,
+            last_failure: None,
+        }
+    }
+
+    /// Returns the current state without performing any transition.
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Returns the current failure count.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Returns the tick at which the last failure was recorded, if any.
+    pub fn last_failure(&self) -> Option<T> {
+        self.last_failure
+    }
+
+    /// Returns the failure threshold.
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    /// Changes the failure threshold. The current failure count is kept and compared against
+    /// the new threshold on the next failure.
+    pub fn set_failure_threshold(&mut self, failure_threshold: u32) {
+        self.failure_threshold = failure_threshold;
+    }
+
+    /// Returns the reset timeout.
+    pub fn reset_timeout(&self) -> T::Span {
+        self.reset_timeout
+    }
+
+    /// Changes the reset timeout, which applies from the next check.
+    pub fn set_reset_timeout(&mut self, reset_timeout: T::Span) {
+        self.reset_timeout = reset_timeout;
+    }
+
+    /// Returns whether an Open circuit is due to move to Half-Open at tick `now`: the reset
+    /// timeout has elapsed since the last failure. An Open circuit without a recorded failure
+    /// never is.
+    pub fn reset_due(&self, now: T) -> bool {
+        self.state == CircuitState::Open
+            && self
+                .last_failure
+                .is_some_and(|last_failure| now.since(last_failure) >= self.reset_timeout)
+    }
+
+    /// Returns whether `failures` failures reach `threshold`, a threshold of `0` never being
+    /// reached.
+    pub fn threshold_reached(&self, threshold: u32) -> bool {
+        threshold > 0 && self.failures >= threshold
+    }
+
+    /// Returns the state at tick `now`, moving from Open to Half-Open if the reset timeout has
+    /// elapsed.
+    pub fn poll(&mut self, now: T) -> CircuitState {
+        if self.reset_due(now) {
+            self.state = CircuitState::HalfOpen;
+        }
+        self.state
+    }
+
+    /// Returns whether a call may proceed at tick `now`.
+    pub fn try_acquire(&mut self, now: T) -> bool {
+        self.poll(now) != CircuitState::Open
+    }
+
+    /// Records a failure at tick `now`, opening the circuit once the threshold is reached. A
+    /// failed half-open trial re-opens the circuit whatever the failure count.
+    ///
+    /// Failures recorded while the circuit is already open do not grow the failure counter.
+    pub fn record_failure(&mut self, now: T) {
+        let trial = self.state == CircuitState::HalfOpen;
+        self.count_failure(1, now);
+        if self.state != CircuitState::Open
+            && (trial || self.threshold_reached(self.failure_threshold))
+        {
+            self.state = CircuitState::Open;
+        }
+    }
+
+    /// Records a success. A half-open circuit closes and its failure counter is cleared; a
+    /// closed circuit keeps its count, so failures accumulate until the threshold is reached.
+    pub fn record_success(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            self.failures = 0;
+            self.state = CircuitState::Closed;
+        }
+    }
+
+    /// Records the time of a failure at tick `now` and adds `weight` to the failure counter,
+    /// unless the circuit is already open.
+    pub(crate) fn count_failure(&mut self, weight: u32, now: T) {
+        self.last_failure = Some(now);
+        if self.state != CircuitState::Open {
+            self.failures = self.failures.saturating_add(weight);
+        }
+    }
+
+    /// Moves the circuit to `to` without any check.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_state(&mut self, to: CircuitState) {
+        self.state = to;
+    }
+
+    /// Replaces the failure count.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_failures(&mut self, failures: u32) {
+        self.failures = failures;
+    }
+
+    /// Replaces the time of the last failure. Without one, an Open circuit stays open.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_last_failure(&mut self, last_failure: Option<T>) {
+        self.last_failure = last_failure;
+    }
+}
+
+/// A lock guarding a [`StateMachine`], supplied by the caller so that [`SharedStateMachine`]
+/// works with whatever mutual exclusion the platform provides, such as a critical section on a
+/// single-core microcontroller or a spin lock.
+///
+/// With the `std` feature, it is implemented for `std::sync::Mutex<StateMachine>`.
+pub trait StateLock {
+    /// Runs `f` with exclusive access to the state machine and returns its result.
+    fn with<R>(&self, f: impl FnOnce(&mut StateMachine) -> R) -> R;
+}
+
+#[cfg(feature = "std")]
+impl StateLock for std::sync::Mutex<StateMachine> {
+    fn with<R>(&self, f: impl FnOnce(&mut StateMachine) -> R) -> R {
+        f(&mut self
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner))
+    }
+}
+
+/// A [`StateMachine`] shared through a caller-supplied [`StateLock`], so it can be used through a
+/// shared reference, for example from a `static` accessed by several interrupt handlers.
+///
+/// Each method takes the lock once, so a check and the transition it performs happen together.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use circuit_breaker::{CircuitState, SharedStateMachine, StateMachine};
+/// use std::sync::Mutex;
+///
+/// static BREAKER: SharedStateMachine<Mutex<StateMachine>> =
+///     SharedStateMachine::new(Mutex::new(StateMachine::new(1, 100)));
+///
+/// BREAKER.record_failure(0);
+/// assert!(!BREAKER.try_acquire(50));
+/// assert!(BREAKER.try_acquire(100));
+/// assert_eq!(BREAKER.state(), CircuitState::HalfOpen);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SharedStateMachine<L> {
+    lock: L,
+}
+
+impl<L: StateLock> SharedStateMachine<L> {
+    /// Creates a new `SharedStateMachine` around the state machine guarded by `lock`.
+    pub const fn new(lock: L) -> Self {
+        SharedStateMachine { lock }
+    }
+
+    /// Returns the current state without performing any transition.
+    pub fn state(&self) -> CircuitState {
+        self.lock.with(|machine| machine.state())
+    }
+
+    /// Returns the current failure count.
+    pub fn failures(&self) -> u32 {
+        self.lock.with(|machine| machine.failures())
+    }
+
+    /// Returns the state at tick `now`, moving from Open to Half-Open if the reset timeout has
+    /// elapsed.
+    pub fn poll(&self, now: u64) -> CircuitState {
+        self.lock.with(|machine| machine.poll(now))
+    }
+
+    /// Returns whether a call may proceed at tick `now`.
+    pub fn try_acquire(&self, now: u64) -> bool {
+        self.lock.with(|machine| machine.try_acquire(now))
+    }
+
+    /// Records a failure at tick `now`, opening the circuit once the threshold is reached.
+    pub fn record_failure(&self, now: u64) {
+        self.lock.with(|machine| machine.record_failure(now));
+    }
+
+    /// Records a success, closing the circuit if it was half-open.
+    pub fn record_success(&self) {
+        self.lock.with(StateMachine::record_success);
+    }
+
+    /// Returns the lock guarding the state machine.
+    pub fn lock(&self) -> &L {
+        &self.lock
+    }
+}
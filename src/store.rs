@@ -0,0 +1,185 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::circuit_state::CircuitState;
+
+/// The part of a circuit breaker's state that a [`StateStore`] shares between breakers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoredState {
+    /// The state of the circuit.
+    pub state: CircuitState,
+    /// The failure counter compared against the failure threshold.
+    pub failures: u32,
+    /// When the last failure was recorded, which the reset timeout is measured from.
+    pub last_failure_time: Option<Instant>,
+}
+
+impl Default for StoredState {
+    /// A closed circuit with no recorded failures.
+    fn default() -> Self {
+        StoredState {
+            state: CircuitState::Closed,
+            failures: 0,
+            last_failure_time: None,
+        }
+    }
+}
+
+/// The first line of a persisted state, identifying the format.
+const PERSIST_HEADER: &str = "circuit_breaker state v1";
+
+impl StoredState {
+    /// Writes the state in a line-based text format, for `CircuitBreaker::persist_to`. The last
+    /// failure time is written as milliseconds since the Unix epoch, assuming `now` is the
+    /// current instant.
+    pub(crate) fn write_to(&self, w: &mut dyn Write, now: Instant) -> io::Result<()> {
+        writeln!(w, "{}", PERSIST_HEADER)?;
+        writeln!(w, "state={}", self.state)?;
+        writeln!(w, "failures={}", self.failures)?;
+        match self.last_failure_time {
+            Some(at) => {
+                let age = now.saturating_duration_since(at);
+                let at = SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH);
+                let millis = at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                writeln!(w, "last_failure_ms={}", millis)?;
+            }
+            None => writeln!(w, "last_failure_ms=none")?,
+        }
+        w.flush()
+    }
+
+    /// Reads a state written by [`write_to`](Self::write_to), translating the last failure time
+    /// back to an instant relative to `now`.
+    ///
+    /// Input that ends before every field has been read fails with
+    /// `io::ErrorKind::UnexpectedEof`; any other malformed input fails with
+    /// `io::ErrorKind::InvalidData`.
+    pub(crate) fn read_from(r: &mut dyn Read, now: Instant) -> io::Result<StoredState> {
+        let mut lines = BufReader::new(r).lines();
+        let mut next = |what: &str| -> io::Result<String> {
+            lines.next().transpose()?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("persisted circuit breaker state ends before its {}", what),
+                )
+            })
+        };
+        let header = next("header")?;
+        if header != PERSIST_HEADER {
+            return Err(corrupt(format!("unrecognized header {:?}", header)));
+        }
+
+        let mut field = |key: &str| -> io::Result<String> {
+            let line = next(key)?;
+            match line.split_once('=') {
+                Some((name, value)) if name == key => Ok(value.to_string()),
+                _ => Err(corrupt(format!("expected {}, found {:?}", key, line))),
+            }
+        };
+        let state = field("state")?;
+        let state: CircuitState = state
+            .parse()
+            .map_err(|_| corrupt(format!("invalid state {:?}", state)))?;
+        let failures = field("failures")?;
+        let failures = failures
+            .parse()
+            .map_err(|_| corrupt(format!("invalid failure count {:?}", failures)))?;
+        let last_failure_time = match field("last_failure_ms")?.as_str() {
+            "none" => None,
+            millis => {
+                let millis: u64 = millis
+                    .parse()
+                    .map_err(|_| corrupt(format!("invalid last failure time {:?}", millis)))?;
+                let at = UNIX_EPOCH + Duration::from_millis(millis);
+                let age = SystemTime::now().duration_since(at).unwrap_or_default();
+                // An instant older than the clock can represent, typically one from before a
+                // reboot, is taken as now, so the reset timeout starts afresh.
+                Some(now.checked_sub(age).unwrap_or(now))
+            }
+        };
+
+        Ok(StoredState {
+            state,
+            failures,
+            last_failure_time,
+        })
+    }
+}
+
+/// Returns the error reported for malformed persisted state.
+fn corrupt(detail: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt circuit breaker state: {}", detail),
+    )
+}
+
+/// A backend holding circuit state, so that several breakers can share it.
+///
+/// A breaker configured with a store through [`CircuitBreakerBuilder::state_store`] loads the
+/// [`StoredState`] whenever it inspects or updates its state and stores it back if it changed.
+/// The store's contents are authoritative: a breaker picks up trips, resets and failures recorded
+/// by any other breaker sharing the store.
+///
+/// Load and store are separate operations, so two breakers updating the state at the same moment
+/// may overwrite each other's change. Callbacks and subscribers only observe transitions made by
+/// their own breaker.
+///
+/// `Instant` is only meaningful within one process. A store shared between processes, for
+/// example in Redis, must translate it to and from a wall-clock representation.
+///
+/// [`CircuitBreakerBuilder::state_store`]: crate::CircuitBreakerBuilder::state_store
+pub trait StateStore: Send + Sync {
+    /// Returns the current shared state.
+    fn load(&self) -> StoredState;
+
+    /// Replaces the shared state.
+    fn store(&self, state: StoredState);
+}
+
+/// A [`StateStore`] keeping the state in memory, for sharing it between breakers in one process.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{CircuitBreaker, CircuitState, InMemoryStateStore};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let store = Arc::new(InMemoryStateStore::new());
+/// let a = CircuitBreaker::builder(1, Duration::from_secs(60))
+///     .state_store(store.clone())
+///     .build()?;
+/// let b = CircuitBreaker::builder(1, Duration::from_secs(60))
+///     .state_store(store)
+///     .build()?;
+///
+/// a.handle_failure();
+/// assert_eq!(b.state(), CircuitState::Open);
+/// # Ok::<(), circuit_breaker::ConfigError>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct InMemoryStateStore {
+    state: Mutex<StoredState>,
+}
+
+impl InMemoryStateStore {
+    /// Creates a store holding a closed circuit.
+    pub fn new() -> Self {
+        InMemoryStateStore::default()
+    }
+}
+
+impl StateStore for InMemoryStateStore {
+    fn load(&self) -> StoredState {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn store(&self, state: StoredState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+}
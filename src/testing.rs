@@ -0,0 +1,102 @@
+//! Helpers for testing code that uses a circuit breaker, without sleeping.
+//!
+//! [`FakeClock`] only moves when told to, [`fast_builder`] configures a breaker that opens on
+//! the first failure and recovers after [`FAST_RESET_TIMEOUT`] of fake time, and
+//! [`assert_state!`](crate::assert_state) checks the state a breaker reports.
+//!
+//! Requires the `testing` feature, which is meant to be enabled for dev-dependencies only.
+//!
+//! # Example
+//!
+//! ```
+//! use circuit_breaker::assert_state;
+//! use circuit_breaker::testing::{fast_builder, FakeClock, FAST_RESET_TIMEOUT};
+//!
+//! let clock = FakeClock::new();
+//! let cb = fast_builder(&clock).build()?;
+//!
+//! cb.handle_failure();
+//! assert_state!(cb, Open);
+//! clock.advance(FAST_RESET_TIMEOUT);
+//! assert_state!(cb, HalfOpen);
+//! # Ok::<(), circuit_breaker::ConfigError>(())
+//! ```
+
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::builder::CircuitBreakerBuilder;
+use crate::clock::Clock;
+
+/// The reset timeout of a breaker built with [`fast_builder`].
+pub const FAST_RESET_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A [`Clock`] that stands still until [`advance`](Self::advance) is called.
+#[derive(Debug)]
+pub struct FakeClock {
+    start: Instant,
+    offset: Mutex<Duration>,
+}
+
+impl FakeClock {
+    /// Creates a fake clock, shared so it can be handed to a breaker and advanced by the test.
+    pub fn new() -> Arc<Self> {
+        Arc::new(FakeClock {
+            start: Instant::now(),
+            offset: Mutex::new(Duration::ZERO),
+        })
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.offset.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+
+    /// Returns how far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+}
+
+/// Returns a builder for a breaker driven by `clock` that opens on the first failure and moves
+/// to Half-Open once the clock has been advanced by [`FAST_RESET_TIMEOUT`].
+///
+/// Every other option keeps its default and can be changed on the returned builder.
+pub fn fast_builder(clock: &Arc<FakeClock>) -> CircuitBreakerBuilder {
+    CircuitBreakerBuilder::new(1, FAST_RESET_TIMEOUT).clock(clock.clone())
+}
+
+/// Asserts that a circuit breaker reports the given state, naming a [`CircuitState`] variant.
+///
+/// The state is read with `CircuitBreaker::state`, so an open circuit whose reset timeout has
+/// elapsed moves to Half-Open, just as it would for the next call. A custom message can follow,
+/// as with `assert_eq!`. Requires the `testing` feature.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{assert_state, CircuitBreaker};
+/// use std::time::Duration;
+///
+/// let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+/// assert_state!(cb, Closed);
+/// cb.handle_failure();
+/// assert_state!(cb, Open, "a single failure should open the circuit");
+/// ```
+///
+/// [`CircuitState`]: crate::CircuitState
+#[macro_export]
+macro_rules! assert_state {
+    ($breaker:expr, $state:ident $(,)?) => {
+        assert_eq!($breaker.state(), $crate::CircuitState::$state)
+    };
+    ($breaker:expr, $state:ident, $($arg:tt)+) => {
+        assert_eq!($breaker.state(), $crate::CircuitState::$state, $($arg)+)
+    };
+}
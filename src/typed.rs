@@ -0,0 +1,110 @@
+use std::error::Error;
+
+use crate::circuit_breaker::TypedCircuitBreaker;
+use crate::error::BreakerError;
+use crate::policy::FailureAction;
+
+/// Decides what an error of type `E` does to the circuit.
+pub(crate) type TypedClassifier<E> = Box<dyn Fn(&E) -> FailureAction + Send + Sync>;
+
+/// The error type parameter of the type-erased [`CircuitBreaker`](crate::CircuitBreaker), whose
+/// `execute` methods accept operations with any error type.
+///
+/// It has no values and only selects the type-erased methods. It is a type of its own rather than
+/// `Box<dyn Error>` so that those methods can never overlap the ones of a typed breaker.
+pub enum Untyped {}
+
+/// A breaker for operations that share one concrete error type `E`, returning errors unboxed
+/// and classifying them with a strongly typed classifier.
+///
+/// [`execute`](Self::execute) returns the operation's error unchanged in
+/// [`BreakerError::Inner`] and rejections in [`BreakerError::Open`], without allocating. Errors
+/// are classified by the classifier set with [`error_classifier`](Self::error_classifier) or
+/// [`failure_classifier`](Self::failure_classifier), which receives `&E` directly instead of a
+/// `&dyn Error` to downcast; without one, every error counts as a failure. The classifier set
+/// with `CircuitBreakerBuilder::error_classifier` and the `trip_on` types are not consulted.
+///
+/// # Example
+///
+/// ```
+/// use circuit_breaker::{BreakerError, CircuitBreakerError, TypedCircuitBreaker};
+/// use std::time::Duration;
+///
+/// #[derive(Debug)]
+/// enum DbError {
+///     Timeout,
+///     NotFound,
+/// }
+/// # impl std::fmt::Display for DbError {
+/// #     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// #         write!(f, "{:?}", self)
+/// #     }
+/// # }
+/// # impl std::error::Error for DbError {}
+///
+/// let cb = TypedCircuitBreaker::new(1, Duration::from_secs(60))
+///     .error_classifier(|e: &DbError| matches!(e, DbError::Timeout));
+///
+/// let _ = cb.execute(|| Err::<(), _>(DbError::NotFound));
+/// let _ = cb.execute(|| Err::<(), _>(DbError::Timeout));
+/// match cb.execute(|| Ok(())) {
+///     Err(BreakerError::Open(CircuitBreakerError::CircuitOpen { .. })) => {}
+///     other => panic!("expected the circuit to be open, got {:?}", other),
+/// }
+/// ```
+impl<E: Error> TypedCircuitBreaker<E> {
+    /// Sets a classifier deciding which errors count as failures. Errors for which it returns
+    /// `false` are returned to the caller but ignored by the breaker.
+    pub fn error_classifier<F>(self, classifier: F) -> Self
+    where
+        F: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        self.failure_classifier(move |e| {
+            if classifier(e) {
+                FailureAction::Count
+            } else {
+                FailureAction::Ignore
+            }
+        })
+    }
+
+    /// Sets a classifier deciding what each error does to the circuit, as
+    /// `CircuitBreakerBuilder::failure_classifier` does for untyped errors.
+    pub fn failure_classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&E) -> FailureAction + Send + Sync + 'static,
+    {
+        self.typed_classifier = Some(Box::new(classifier));
+        self
+    }
+
+    /// Executes the given function within the circuit breaker.
+    ///
+    /// Returns the function's value, the function's error in [`BreakerError::Inner`], or the
+    /// rejection in [`BreakerError::Open`] if the call was not admitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A function that returns a `Result` with the breaker's error type.
+    pub fn execute<F, T>(&self, f: F) -> Result<T, BreakerError<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let (_in_flight, admission) = self.admit(None).map_err(BreakerError::Open)?;
+
+        match f() {
+            Ok(result) => {
+                self.complete(admission, true, None);
+                Ok(result)
+            }
+            Err(e) => {
+                let action = self
+                    .typed_classifier
+                    .as_ref()
+                    .map_or(FailureAction::Count, |classifier| classifier(&e));
+                self.fail_as(admission, action, &e, None);
+                Err(BreakerError::Inner(e))
+            }
+        }
+    }
+}
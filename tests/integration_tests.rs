@@ -1,10 +1,14 @@
 #[cfg(test)]
 mod tests {
-    use circuit_breaker::{CircuitBreaker, CircuitState, CircuitBreakerError};
-    use std::sync::Arc;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use circuit_breaker::{
+        AggregationMode, BreakerError, CircuitBreaker, CircuitBreakerBuilder, CircuitBreakerError, CircuitConfig, CircuitState, ConfigError, RejectionReason,
+        Clock, ClosedResetMode, CompositeBreaker, FailureAction, FailurePolicy, FailureReason, MetricsRecorder, ShardedCircuitBreaker, StateMachine, StateStore,
+        Health, OpenBehavior, PanicBehavior, StoredState, TickClock, TypedCircuitBreaker, CircuitBreakerRegistry,
+    };
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
   
     #[test]
@@ -72,6 +76,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::io_other_error)]
     fn test_circuit_breaker_execute() {
         let cb = CircuitBreaker::new(2, Duration::from_millis(100));
 
@@ -83,7 +88,7 @@ mod tests {
 
         let result = cb.execute(|| Ok::<_, std::io::Error>(42));
         assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
-                         Some(CircuitBreakerError::CircuitOpen)));
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
 
         thread::sleep(Duration::from_millis(150));
 
@@ -92,4 +97,3711 @@ mod tests {
 
         assert_eq!(cb.state(), CircuitState::Closed);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_circuit_breaker_config_getters() {
+        let cb = CircuitBreaker::new(3, Duration::from_millis(100));
+
+        assert_eq!(cb.failure_threshold(), 3);
+        assert_eq!(cb.reset_timeout(), Duration::from_millis(100));
+    }
+
+    struct PanickingStore {
+        state: Mutex<StoredState>,
+        armed: AtomicBool,
+    }
+
+    impl StateStore for PanickingStore {
+        fn load(&self) -> StoredState {
+            *self.state.lock().unwrap()
+        }
+
+        fn store(&self, state: StoredState) {
+            if self.armed.swap(false, Ordering::SeqCst) {
+                panic!("store panicked");
+            }
+            *self.state.lock().unwrap() = state;
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_recovers_from_poisoned_lock() {
+        let store = Arc::new(PanickingStore {
+            state: Mutex::new(StoredState::default()),
+            armed: AtomicBool::new(true),
+        });
+        let cb = Arc::new(CircuitBreaker::builder(1, Duration::from_millis(100))
+            .state_store(store)
+            .build().unwrap());
+
+        // The store is written while the state lock is held, so panicking there poisons it.
+        let cb_clone = cb.clone();
+        let result = thread::spawn(move || cb_clone.handle_failure()).join();
+        assert!(result.is_err());
+
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        thread::sleep(Duration::from_millis(150));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_survives_panicking_callback() {
+        let cb = CircuitBreaker::new(1, Duration::from_millis(100));
+        cb.set_on_open(|| panic!("callback panicked"));
+
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        thread::sleep(Duration::from_millis(150));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_callback_panic_hook() {
+        let build = |async_callbacks| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let sender = Mutex::new(sender);
+            let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+                .async_callbacks(async_callbacks)
+                .on_open(|| panic!("alert failed"))
+                .on_callback_panic(move |to, payload| {
+                    let message = payload.downcast_ref::<&str>().copied();
+                    sender.lock().unwrap().send((to, message.map(str::to_owned))).unwrap();
+                })
+                .build().unwrap();
+            (cb, receiver)
+        };
+
+        for async_callbacks in [false, true] {
+            let (cb, panics) = build(async_callbacks);
+            cb.handle_failure();
+            assert_eq!(cb.state(), CircuitState::Open);
+            let (to, message) = panics.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(to, CircuitState::Open);
+            assert_eq!(message.as_deref(), Some("alert failed"));
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_panicking_operation_trips() {
+        let cb = CircuitBreaker::new(2, Duration::from_millis(100));
+
+        for _ in 0..2 {
+            let result = cb.execute_catch_unwind(|| -> Result<(), std::io::Error> {
+                panic!("operation panicked");
+            });
+            assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                             Some(CircuitBreakerError::OperationPanicked)));
+        }
+
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let result = cb.execute_catch_unwind(|| Ok::<_, std::io::Error>(42));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+
+        thread::sleep(Duration::from_millis(150));
+
+        let result = cb.execute_catch_unwind(|| Ok::<_, std::io::Error>(42));
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_panicking_operation_resumes() {
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .on_panic(PanicBehavior::Resume)
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                cb.execute_catch_unwind(|| -> Result<(), std::io::Error> {
+                    panic!("operation panicked");
+                })
+            }))
+            .unwrap_err();
+            // The original payload is re-raised once the failure has been recorded.
+            assert_eq!(payload.downcast_ref::<&str>(), Some(&"operation panicked"));
+        }
+
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.metrics().total_failures, 2);
+        assert_eq!(cb.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker_label_reaches_callbacks() {
+        let cb = CircuitBreaker::new(2, Duration::from_millis(100));
+
+        let labels = Arc::new(Mutex::new(Vec::new()));
+        let labels_clone = labels.clone();
+        cb.set_on_open_labeled(move |label| {
+            labels_clone.lock().unwrap().push(label.map(str::to_owned));
+        });
+
+        let _ = cb.execute_labeled("fetch_user", || Err::<(), _>(std::io::Error::other("error")));
+        let _ = cb.execute_labeled("fetch_orders", || Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(*labels.lock().unwrap(), vec![Some("fetch_orders".to_owned())]);
+
+        // Transitions caused by unlabeled calls report no label.
+        let cb = CircuitBreaker::new(1, Duration::from_millis(100));
+        let labels_clone = labels.clone();
+        cb.set_on_open_labeled(move |label| {
+            labels_clone.lock().unwrap().push(label.map(str::to_owned));
+        });
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(labels.lock().unwrap().last(), Some(&None));
+    }
+
+    #[test]
+    fn test_circuit_breaker_failure_decay() {
+        let cb = CircuitBreaker::builder(2, Duration::from_millis(100))
+            .failure_decay(Duration::from_millis(50))
+            .build().unwrap();
+
+        cb.handle_failure();
+        thread::sleep(Duration::from_millis(80));
+
+        // The first failure has aged out, so this one starts a fresh count.
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_is_call_permitted() {
+        let cb = CircuitBreaker::new(1, Duration::from_millis(100));
+        assert!(cb.is_call_permitted());
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        cb.handle_failure();
+        assert!(!cb.is_call_permitted());
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        thread::sleep(Duration::from_millis(150));
+
+        // Probing past the reset timeout performs the half-open transition.
+        assert!(cb.is_call_permitted());
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert!(cb.is_call_permitted());
+    }
+
+    #[test]
+    fn test_circuit_breaker_is_call_permitted_checks_limits() {
+        // An exhausted half-open quota is reported without using up a trial.
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .half_open_max_calls(1)
+            .success_threshold(2)
+            .build().unwrap();
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        assert!(cb.is_call_permitted());
+        assert!(cb.is_call_permitted());
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert!(!cb.is_call_permitted());
+
+        // So is a single-flight trial in flight.
+        let cb = half_open_single_flight(&MockClock::new());
+        assert!(cb.is_call_permitted());
+        let permit = cb.try_acquire().unwrap();
+        assert!(!cb.is_call_permitted());
+        permit.report_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // And the concurrency limit.
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .max_concurrency(1)
+            .build().unwrap();
+        let permit = cb.try_acquire().unwrap();
+        assert!(!cb.is_call_permitted());
+        drop(permit);
+        assert!(cb.is_call_permitted());
+        assert_eq!(cb.metrics().rejected_calls, 0);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_circuit_breaker_is_call_permitted_matches_sampling() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .half_open_sampling(0.5)
+            .success_threshold(u32::MAX)
+            .rng_seed(7)
+            .build().unwrap();
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+
+        // Each answer predicts the sampling decision the next call makes.
+        for _ in 0..32 {
+            let permitted = cb.is_call_permitted();
+            assert_eq!(cb.is_call_permitted(), permitted);
+            assert_eq!(cb.execute(|| Ok::<_, std::io::Error>(())).is_ok(), permitted);
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_mut() {
+        let cb = CircuitBreaker::new(2, Duration::from_millis(100));
+
+        let mut calls = 0;
+        let mut operation = || {
+            calls += 1;
+            if calls > 1 {
+                Err(std::io::Error::other("error"))
+            } else {
+                Ok(calls)
+            }
+        };
+
+        assert_eq!(cb.execute_mut(&mut operation).unwrap(), 1);
+        assert!(cb.execute_mut(&mut operation).is_err());
+        assert!(cb.execute_mut(&mut operation).is_err());
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // The closure is not invoked while the circuit is open.
+        assert!(cb.execute_mut(&mut operation).is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_with_retry() {
+        let cb = CircuitBreaker::new(10, Duration::from_millis(100));
+
+        let mut calls = 0;
+        let result = cb.execute_with_retry(3, Duration::from_millis(1), || {
+            calls += 1;
+            Err::<(), _>(std::io::Error::other("error"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 4);
+
+        let mut calls = 0;
+        let result = cb.execute_with_retry(3, Duration::from_millis(1), || {
+            calls += 1;
+            if calls < 3 {
+                Err(std::io::Error::other("error"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_circuit_breaker_retry_stops_when_circuit_opens() {
+        let cb = CircuitBreaker::new(2, Duration::from_millis(100));
+
+        let mut calls = 0;
+        let result = cb.execute_with_retry(5, Duration::from_millis(1), || {
+            calls += 1;
+            Err::<(), _>(std::io::Error::other("error"))
+        });
+        assert!(result.unwrap_err().downcast_ref::<std::io::Error>().is_some());
+        assert_eq!(calls, 2);
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[derive(Default)]
+    struct CountingRecorder {
+        successes: AtomicUsize,
+        failures: AtomicUsize,
+        rejected: AtomicUsize,
+        state_changes: Mutex<Vec<(CircuitState, CircuitState)>>,
+    }
+
+    impl MetricsRecorder for CountingRecorder {
+        fn record_success(&self) {
+            self.successes.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn record_failure(&self) {
+            self.failures.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn record_rejected(&self) {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn record_state_change(&self, from: CircuitState, to: CircuitState) {
+            self.state_changes.lock().unwrap().push((from, to));
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_metrics_recorder() {
+        let recorder = Arc::new(CountingRecorder::default());
+        let cb = CircuitBreaker::builder(2, Duration::from_millis(100))
+            .metrics_recorder(Box::new(recorder.clone()))
+            .build().unwrap();
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+
+        thread::sleep(Duration::from_millis(150));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+
+        assert_eq!(recorder.successes.load(Ordering::SeqCst), 2);
+        assert_eq!(recorder.failures.load(Ordering::SeqCst), 2);
+        assert_eq!(recorder.rejected.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *recorder.state_changes.lock().unwrap(),
+            vec![
+                (CircuitState::Closed, CircuitState::Open),
+                (CircuitState::Open, CircuitState::HalfOpen),
+                (CircuitState::HalfOpen, CircuitState::Closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_circuit_breaker_subscribe() {
+        let cb = CircuitBreaker::new(1, Duration::from_millis(100));
+
+        let first = cb.subscribe();
+        let second = cb.subscribe();
+        let dropped = cb.subscribe();
+        drop(dropped);
+
+        cb.handle_failure();
+
+        let change = first.try_recv().unwrap();
+        assert_eq!(change.from, CircuitState::Closed);
+        assert_eq!(change.to, CircuitState::Open);
+        assert_eq!(second.try_recv().unwrap(), change);
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+        let change = first.try_recv().unwrap();
+        assert_eq!((change.from, change.to), (CircuitState::Open, CircuitState::HalfOpen));
+        assert!(first.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_cached() {
+        let cb = CircuitBreaker::new(1, Duration::from_millis(100));
+
+        // Nothing is cached yet, so an open circuit still returns the error.
+        cb.handle_failure();
+        let result = cb.execute_cached(|| Ok::<_, std::io::Error>(1));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(cb.execute_cached(|| Ok::<_, std::io::Error>(42)).unwrap(), 42);
+
+        // Failures are not cached.
+        assert!(cb.execute_cached(|| Err::<i32, _>(std::io::Error::other("error"))).is_err());
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let result = cb.execute_cached(|| Ok::<_, std::io::Error>(7));
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_cached_only_while_open() {
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .max_concurrency(1)
+            .build()
+            .unwrap();
+        assert_eq!(cb.execute_cached(|| Ok::<_, std::io::Error>(42)).unwrap(), 42);
+
+        // A call over the concurrency limit is rejected even though a value is cached.
+        let permit = cb.try_acquire().unwrap();
+        let result = cb.execute_cached(|| Ok::<_, std::io::Error>(7));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::ConcurrencyLimit { limit: 1 })));
+        permit.report_success();
+    }
+
+    #[test]
+    fn test_circuit_breaker_open_without_failure_time_rejects() {
+        let cb = CircuitBreaker::new(3, Duration::from_millis(50));
+
+        // A forced-open circuit has no recorded failure time.
+        cb.force_open();
+        thread::sleep(Duration::from_millis(100));
+
+        let mut called = false;
+        let result = cb.execute(|| {
+            called = true;
+            Ok::<_, std::io::Error>(())
+        });
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert!(!called);
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert!(!cb.is_call_permitted());
+
+        cb.force_close();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_breaker_default_config() {
+        let cb = CircuitBreaker::default();
+        assert_eq!(cb.failure_threshold(), 5);
+        assert_eq!(cb.reset_timeout(), Duration::from_secs(30));
+
+        let cb = CircuitBreakerBuilder::default().build().unwrap();
+        assert_eq!(cb.failure_threshold(), 5);
+        assert_eq!(cb.reset_timeout(), Duration::from_secs(30));
+
+        let cb = CircuitBreakerBuilder::default().failure_threshold(2).build().unwrap();
+        assert_eq!(cb.failure_threshold(), 2);
+        assert_eq!(cb.reset_timeout(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_composite_breaker_aggregation_modes() {
+        let members = || {
+            let open = CircuitBreaker::new(1, Duration::from_secs(60));
+            open.handle_failure();
+            let half_open = CircuitBreaker::new(1, Duration::from_millis(10));
+            half_open.handle_failure();
+            let closed = CircuitBreaker::new(1, Duration::from_secs(60));
+            vec![open, half_open, closed]
+        };
+
+        let any = CompositeBreaker::new(members(), AggregationMode::Any);
+        let all = CompositeBreaker::new(members(), AggregationMode::All);
+        let majority = CompositeBreaker::new(members(), AggregationMode::Majority);
+        thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(any.state(), CircuitState::Open);
+        assert_eq!(all.state(), CircuitState::HalfOpen);
+        assert_eq!(majority.state(), CircuitState::HalfOpen);
+
+        // Open a second member: a majority is now open, but not all.
+        all.members()[1].handle_failure();
+        majority.members()[1].handle_failure();
+        assert_eq!(all.state(), CircuitState::Closed);
+        assert_eq!(majority.state(), CircuitState::Open);
+
+        all.members()[2].handle_failure();
+        assert_eq!(all.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_composite_breaker_execute() {
+        let composite = CompositeBreaker::new(
+            vec![
+                CircuitBreaker::new(1, Duration::from_secs(60)),
+                CircuitBreaker::new(1, Duration::from_secs(60)),
+            ],
+            AggregationMode::All,
+        );
+
+        let _ = composite.execute(0, || Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(composite.members()[0].state(), CircuitState::Open);
+        assert_eq!(composite.execute(1, || Ok::<_, std::io::Error>(42)).unwrap(), 42);
+
+        let _ = composite.execute(1, || Err::<(), _>(std::io::Error::other("error")));
+        let result = composite.execute(1, || Ok::<_, std::io::Error>(42));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+    }
+
+    struct MockClock {
+        start: Instant,
+        offset: Mutex<Duration>,
+    }
+
+    impl MockClock {
+        fn new() -> Arc<Self> {
+            Arc::new(MockClock { start: Instant::now(), offset: Mutex::new(Duration::ZERO) })
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.offset.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.start + *self.offset.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_time_window_policy() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(clock.clone())
+            .failure_policy(FailurePolicy::TimeWindow {
+                duration: Duration::from_secs(10),
+                buckets: 5,
+                failure_rate: 0.6,
+            })
+            .build().unwrap();
+
+        for _ in 0..3 {
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        }
+
+        clock.advance(Duration::from_secs(4));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+
+        // 2 failures out of 5 calls.
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        clock.advance(Duration::from_secs(5));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+
+        // 3 failures out of 6 calls, the successes are still inside the window.
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // The bucket holding the successes has now expired, leaving only failures.
+        clock.advance(Duration::from_secs(2));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_time_window_counts_weighted_failures() {
+        let cb = CircuitBreaker::builder(100, Duration::from_secs(60))
+            .failure_policy(FailurePolicy::TimeWindow {
+                duration: Duration::from_secs(10),
+                buckets: 5,
+                failure_rate: 0.6,
+            })
+            .build().unwrap();
+
+        for _ in 0..4 {
+            cb.handle_success();
+        }
+        cb.handle_failure_weighted(5);
+        // 5 failures out of 9 calls.
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // 6 failures out of 10 calls, not 2 out of 6.
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_time_window_stale_buckets_expire() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(clock.clone())
+            .failure_policy(FailurePolicy::TimeWindow {
+                duration: Duration::from_secs(10),
+                buckets: 5,
+                failure_rate: 0.6,
+            })
+            .build().unwrap();
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // Without expiry this would be 2 failures out of 4 calls. Far beyond the window the
+        // earlier calls no longer count, so the failure is a 100% rate.
+        clock.advance(Duration::from_secs(60));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_mock_clock_reset_timeout() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(clock.clone())
+            .build().unwrap();
+
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(cb.state(), CircuitState::Open);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn test_circuit_breaker_error_classifier_outcome() {
+        let cb = CircuitBreaker::builder(2, Duration::from_millis(100))
+            .error_classifier(|e| {
+                e.downcast_ref::<std::io::Error>()
+                    .is_none_or(|e| e.kind() != std::io::ErrorKind::NotFound)
+            })
+            .build().unwrap();
+
+        let outcome = cb.execute_with_outcome(|| {
+            Err::<(), _>(std::io::Error::from(std::io::ErrorKind::NotFound))
+        });
+        assert!(outcome.result.is_err());
+        assert!(!outcome.counted);
+
+        let outcome = cb.execute_with_outcome(|| Ok::<_, std::io::Error>(42));
+        assert_eq!(outcome.result.unwrap(), 42);
+        assert!(!outcome.counted);
+
+        let outcome = cb.execute_with_outcome(|| Err::<(), _>(std::io::Error::other("error")));
+        assert!(outcome.counted);
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::from(std::io::ErrorKind::NotFound)));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        let outcome = cb.execute_with_outcome(|| Err::<(), _>(std::io::Error::other("error")));
+        assert!(outcome.counted);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // Rejected calls are not counted either.
+        let outcome = cb.execute_with_outcome(|| Ok::<_, std::io::Error>(42));
+        assert!(outcome.result.is_err());
+        assert!(!outcome.counted);
+    }
+
+    #[test]
+    fn test_circuit_breaker_zero_threshold_never_trips() {
+        let cb = CircuitBreaker::new(0, Duration::from_millis(100));
+
+        for _ in 0..100 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+            cb.handle_failure();
+        }
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        cb.force_open();
+        assert_eq!(cb.state(), CircuitState::Open);
+        cb.force_close();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_circuit_breaker_half_open_sampling() {
+        let clock = MockClock::new();
+        // Ignored errors neither close nor re-open the circuit, keeping it half-open.
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(1))
+            .clock(clock.clone())
+            .error_classifier(|_| false)
+            .half_open_sampling(0.3)
+            .rng_seed(7)
+            .build().unwrap();
+
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+        let mut admitted = 0;
+        for _ in 0..10_000 {
+            let _ = cb.execute(|| {
+                admitted += 1;
+                Err::<(), _>(std::io::Error::other("ignored"))
+            });
+        }
+
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        // Seeded so the test cannot flake; the sample is only checked to be close to 30%, since
+        // the generator's output is not portable across platforms or rand versions.
+        assert!((2_700..3_300).contains(&admitted), "admitted {} calls", admitted);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_circuit_breaker_rng_seed() {
+        let clock = MockClock::new();
+        let sample = |seed| {
+            let cb = CircuitBreaker::builder(1, Duration::from_secs(1))
+                .clock(clock.clone())
+                .error_classifier(|_| false)
+                .half_open_sampling(0.5)
+                .rng_seed(seed)
+                .build().unwrap();
+            cb.force_half_open();
+            (0..200)
+                .map(|_| cb.execute(|| Err::<(), _>(std::io::Error::other("ignored"))).unwrap_err().is::<std::io::Error>())
+                .collect::<Vec<_>>()
+        };
+
+        let admitted = sample(7);
+        assert_eq!(admitted, sample(7));
+        assert_ne!(admitted, sample(8));
+        assert!(admitted.contains(&true) && admitted.contains(&false));
+    }
+
+    #[test]
+    fn test_state_machine_driven_by_ticks() {
+        let mut sm = StateMachine::new(3, 100);
+        assert_eq!(sm.state(), CircuitState::Closed);
+
+        sm.record_failure(10);
+        sm.record_failure(20);
+        assert!(sm.try_acquire(25));
+        sm.record_failure(30);
+        assert_eq!(sm.state(), CircuitState::Open);
+        assert_eq!(sm.failures(), 3);
+
+        // Ticks, not wall-clock time, decide when the reset timeout has elapsed.
+        assert!(!sm.try_acquire(129));
+        assert_eq!(sm.poll(129), CircuitState::Open);
+        assert!(sm.try_acquire(130));
+        assert_eq!(sm.state(), CircuitState::HalfOpen);
+
+        sm.record_failure(140);
+        assert_eq!(sm.state(), CircuitState::Open);
+        assert_eq!(sm.poll(240), CircuitState::HalfOpen);
+
+        sm.record_success();
+        assert_eq!(sm.state(), CircuitState::Closed);
+        assert_eq!(sm.failures(), 0);
+    }
+
+    #[test]
+    fn test_state_machine_agrees_with_circuit_breaker() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(3, Duration::from_millis(100))
+            .clock(clock.clone())
+            .build().unwrap();
+        let mut sm = StateMachine::new(3, 100);
+
+        // (tick in milliseconds, whether the call succeeds)
+        let calls = [
+            (0, false), (10, true), (20, false), (30, false), (50, true), (129, true),
+            (130, false), (200, true), (229, false), (230, false), (240, true), (250, false),
+            (260, false), (270, true), (300, false), (400, true), (410, false), (420, true),
+            (430, false), (440, false), (540, true),
+        ];
+        let mut elapsed = 0;
+        for (tick, success) in calls {
+            clock.advance(Duration::from_millis(tick - elapsed));
+            elapsed = tick;
+
+            let admitted = sm.try_acquire(tick);
+            let result = cb.execute(|| if success { Ok(()) } else { Err(std::io::Error::other("down")) });
+            let rejected = result.as_ref().is_err_and(|e| e.is::<CircuitBreakerError>());
+            assert_eq!(admitted, !rejected, "at tick {}", tick);
+            if admitted {
+                if success {
+                    sm.record_success();
+                } else {
+                    sm.record_failure(tick);
+                }
+            }
+            assert_eq!(cb.state(), sm.state(), "at tick {}", tick);
+            assert_eq!(cb.current_failures(), sm.failures(), "at tick {}", tick);
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_weighted_failures() {
+        let cb = CircuitBreaker::new(3, Duration::from_millis(100));
+        cb.handle_failure_weighted(3);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let cb = CircuitBreaker::new(3, Duration::from_millis(100));
+        cb.handle_failure_weighted(2);
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_prober_closes_circuit() {
+        let cb = Arc::new(CircuitBreaker::new(1, Duration::from_millis(50)));
+        cb.handle_failure();
+
+        let probes = Arc::new(AtomicUsize::new(0));
+        let probes_clone = probes.clone();
+        cb.start_prober(Duration::from_millis(10), move || {
+            // Fail the first probe, then report the dependency as healthy.
+            probes_clone.fetch_add(1, Ordering::SeqCst) > 0
+        });
+
+        thread::sleep(Duration::from_millis(300));
+        cb.stop_prober();
+
+        assert!(probes.load(Ordering::SeqCst) >= 2);
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_prober_exits_on_drop() {
+        let cb = Arc::new(CircuitBreaker::new(1, Duration::from_millis(50)));
+
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        cb.start_prober(Duration::from_millis(10), move || {
+            let _ = &sender;
+            true
+        });
+        drop(cb);
+
+        // The probe closure, and with it the sender, is dropped once the thread exits.
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(1)),
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn test_circuit_breaker_tick_clock() {
+        let ticks = Arc::new(AtomicU64::new(0));
+        let clock = Arc::new(TickClock::new(ticks.clone(), Duration::from_millis(1)));
+        let cb = CircuitBreaker::builder(2, Duration::from_millis(500))
+            .clock(clock.clone())
+            .build().unwrap();
+
+        cb.handle_failure();
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        ticks.store(499, Ordering::SeqCst);
+        assert_eq!(clock.ticks(), 499);
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert!(matches!(
+            cb.try_acquire().err(),
+            Some(CircuitBreakerError::CircuitOpen { retry_after: Some(retry_after) }) if retry_after == Duration::from_millis(1)
+        ));
+
+        ticks.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // Coarser ticks scale the timeout down to fewer ticks.
+        let ticks = Arc::new(AtomicU64::new(0));
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(Arc::new(TickClock::new(ticks.clone(), Duration::from_secs(10))))
+            .build().unwrap();
+        cb.handle_failure();
+        ticks.store(5, Ordering::SeqCst);
+        assert_eq!(cb.state(), CircuitState::Open);
+        ticks.store(6, Ordering::SeqCst);
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+        // A tick count beyond what an Instant can hold saturates instead of panicking.
+        let ticks = Arc::new(AtomicU64::new(u64::MAX - 1));
+        let clock = TickClock::new(ticks.clone(), Duration::from_secs(u64::MAX));
+        let latest = clock.now();
+        ticks.store(u64::MAX, Ordering::SeqCst);
+        assert_eq!(clock.now(), latest);
+        ticks.store(0, Ordering::SeqCst);
+        assert!(clock.now() < latest);
+    }
+
+    #[test]
+    fn test_circuit_breaker_set_reset_timeout() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(clock.clone())
+            .build().unwrap();
+
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        cb.set_reset_timeout(Duration::from_secs(5));
+        assert_eq!(cb.reset_timeout(), Duration::from_secs(5));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn test_circuit_breaker_set_failure_threshold() {
+        let cb = CircuitBreaker::new(5, Duration::from_secs(60));
+        for _ in 0..3 {
+            cb.handle_failure();
+        }
+
+        // Lowering the threshold below the failure count waits for the next failure.
+        cb.set_failure_threshold(2);
+        assert_eq!(cb.failure_threshold(), 2);
+        assert_eq!(cb.state(), CircuitState::Closed);
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // Raising it delays the next trip.
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+        cb.set_failure_threshold(4);
+        for _ in 0..3 {
+            cb.handle_failure();
+        }
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_rejected_calls_metric() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(1))
+            .clock(clock.clone())
+            .build().unwrap();
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.metrics().rejected_calls, 0);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        for _ in 0..3 {
+            let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+        }
+        assert_eq!(cb.metrics().rejected_calls, 3);
+
+        // The call admitted by the Open to Half-Open transition is not a rejection.
+        clock.advance(Duration::from_secs(1));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+
+        let metrics = cb.metrics();
+        assert_eq!(metrics.state, CircuitState::Closed);
+        assert_eq!(metrics.rejected_calls, 3);
+        assert_eq!(metrics.total_successes, 2);
+        assert_eq!(metrics.total_failures, 2);
+        assert_eq!(metrics.failures, 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker_initial_state_open() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(3, Duration::from_secs(1))
+            .clock(clock.clone())
+            .initial_state(CircuitState::Open)
+            .build().unwrap();
+
+        assert_eq!(cb.state(), CircuitState::Open);
+        let executed = AtomicBool::new(false);
+        let result = cb.execute(|| {
+            executed.store(true, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(())
+        });
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+            Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert!(!executed.load(Ordering::SeqCst));
+
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_initial_state_half_open() {
+        let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+            .initial_state(CircuitState::HalfOpen)
+            .build().unwrap();
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_quota_exceeded() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .half_open_max_calls(2)
+            // Ignored errors keep the circuit half-open, so the trials do not resolve it.
+            .error_classifier(|_| false)
+            .initial_state(CircuitState::HalfOpen)
+            .build().unwrap();
+
+        for _ in 0..2 {
+            let result = cb.execute(|| Err::<(), _>(std::io::Error::other("ignored")));
+            assert!(result.unwrap_err().downcast_ref::<std::io::Error>().is_some());
+        }
+
+        clock.advance(Duration::from_secs(4));
+        let result = cb.execute(|| Ok::<_, std::io::Error>(()));
+        match result.unwrap_err().downcast_ref::<CircuitBreakerError>() {
+            Some(CircuitBreakerError::HalfOpenQuotaExceeded { retry_after }) => {
+                assert_eq!(*retry_after, Duration::from_secs(6));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(cb.metrics().rejected_calls, 1);
+
+        clock.advance(Duration::from_secs(6));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_recent_outcomes() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(10, Duration::from_secs(60))
+            .clock(clock.clone())
+            .outcome_history(3)
+            .build().unwrap();
+
+        let pattern = [true, false, true, true, false];
+        for (i, &success) in pattern.iter().enumerate() {
+            let _ = cb.execute(|| {
+                clock.advance(Duration::from_millis(i as u64 + 1));
+                if success {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::other("error"))
+                }
+            });
+        }
+
+        let outcomes = cb.recent_outcomes();
+        assert_eq!(outcomes.len(), 3);
+        let successes: Vec<bool> = outcomes.iter().map(|o| o.success).collect();
+        assert_eq!(successes, vec![true, true, false]);
+        let latencies: Vec<Duration> = outcomes.iter().map(|o| o.latency).collect();
+        assert_eq!(latencies, vec![
+            Duration::from_millis(3),
+            Duration::from_millis(4),
+            Duration::from_millis(5),
+        ]);
+        assert!(outcomes.windows(2).all(|pair| pair[0].at < pair[1].at));
+    }
+
+    #[test]
+    fn test_circuit_breaker_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CircuitBreaker>();
+    }
+
+    #[test]
+    fn test_circuit_breaker_concurrent_stress() {
+        const THREADS: usize = 16;
+        const CALLS: usize = 500;
+        const THRESHOLD: u32 = 5;
+
+        let cb = Arc::new(CircuitBreaker::new(THRESHOLD, Duration::from_millis(1)));
+        let changes = cb.subscribe();
+        let max_failures = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let cb = Arc::clone(&cb);
+                let max_failures = Arc::clone(&max_failures);
+                thread::spawn(move || {
+                    for i in 0..CALLS {
+                        let _ = cb.execute(|| {
+                            if (t + i) % 3 == 0 {
+                                Err(std::io::Error::other("error"))
+                            } else {
+                                Ok(())
+                            }
+                        });
+                        max_failures.fetch_max(cb.metrics().failures as usize, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Failures are only counted for admitted calls, so at most one in-flight call per
+        // thread can land on top of the threshold.
+        assert!(max_failures.load(Ordering::SeqCst) <= THRESHOLD as usize + THREADS);
+
+        let metrics = cb.metrics();
+        assert_eq!(
+            metrics.total_successes + metrics.total_failures + metrics.rejected_calls,
+            (THREADS * CALLS) as u64
+        );
+
+        // Transitions are published under the lock, so they form an unbroken chain.
+        let mut previous = CircuitState::Closed;
+        for change in changes.try_iter() {
+            assert_eq!(change.from, previous);
+            assert_ne!(change.from, change.to);
+            previous = change.to;
+        }
+        assert_eq!(previous, metrics.state);
+    }
+
+    #[test]
+    fn test_circuit_breaker_minimum_throughput() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(clock.clone())
+            .failure_policy(FailurePolicy::TimeWindow {
+                duration: Duration::from_secs(10),
+                buckets: 10,
+                failure_rate: 0.5,
+            })
+            .minimum_throughput(10)
+            .build().unwrap();
+
+        // A 1/1 failure rate is ignored below the minimum throughput.
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        for _ in 0..4 {
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        }
+        for _ in 0..4 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        }
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // The tenth call brings the window to the minimum throughput at a 6/10 failure rate.
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_wait_until_closed() {
+        let cb = Arc::new(CircuitBreaker::new(1, Duration::from_secs(60)));
+        cb.handle_failure();
+        assert!(!cb.wait_until_closed(Some(Duration::from_millis(20))));
+
+        let waiter = {
+            let cb = Arc::clone(&cb);
+            thread::spawn(move || cb.wait_until_closed(Some(Duration::from_secs(10))))
+        };
+        thread::sleep(Duration::from_millis(50));
+        cb.force_close();
+
+        assert!(waiter.join().unwrap());
+        assert!(cb.wait_until_closed(Some(Duration::ZERO)));
+    }
+
+    #[test]
+    fn test_circuit_breaker_builder_callbacks() {
+        let opened = Arc::new(AtomicUsize::new(0));
+        let closed = Arc::new(AtomicUsize::new(0));
+        let clock = MockClock::new();
+        let cb = {
+            let opened = Arc::clone(&opened);
+            let closed = Arc::clone(&closed);
+            CircuitBreaker::builder(1, Duration::from_secs(1))
+                .clock(clock.clone())
+                .on_open(move || {
+                    opened.fetch_add(1, Ordering::SeqCst);
+                })
+                .on_close(move || {
+                    closed.fetch_add(1, Ordering::SeqCst);
+                })
+                .build().unwrap()
+        };
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::from_secs(1));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(closed.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_or() {
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+
+        assert_eq!(cb.execute_or(0, || Ok::<_, std::io::Error>(42)).unwrap(), 42);
+
+        let result = cb.execute_or(0, || Err::<i32, _>(std::io::Error::other("error")));
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::Other);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let executed = AtomicBool::new(false);
+        let result = cb.execute_or(-1, || {
+            executed.store(true, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(42)
+        });
+        assert_eq!(result.unwrap(), -1);
+        assert!(!executed.load(Ordering::SeqCst));
+        assert_eq!(cb.metrics().rejected_calls, 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_repeated_error_threshold() {
+        let cb = CircuitBreaker::builder(10, Duration::from_secs(60))
+            .repeated_error_threshold(3)
+            .build().unwrap();
+
+        // Varied errors only count toward the normal threshold.
+        for message in ["timeout", "refused", "timeout", "reset"] {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other(message)));
+        }
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        for _ in 0..2 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("disk full")));
+        }
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("disk full")));
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.metrics().failures, 7);
+    }
+
+    #[test]
+    fn test_circuit_breaker_state_durations() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(5))
+            .clock(clock.clone())
+            .build().unwrap();
+
+        clock.advance(Duration::from_secs(10));
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(7));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        clock.advance(Duration::from_secs(2));
+        cb.handle_success();
+        clock.advance(Duration::from_secs(3));
+
+        let durations = cb.state_durations();
+        assert_eq!(durations.closed, Duration::from_secs(13));
+        assert_eq!(durations.open, Duration::from_secs(7));
+        assert_eq!(durations.half_open, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_circuit_breaker_max_open_duration() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(3600))
+            .clock(clock.clone())
+            .max_open_duration(Duration::from_secs(30))
+            .build().unwrap();
+
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(29));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // No traffic arrives, but the ceiling still moves the circuit to Half-Open.
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+        cb.force_open();
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_boxed() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        let result = cb.execute_boxed(|| -> Result<i32, Box<dyn std::error::Error>> {
+            let value: i32 = "42".parse()?;
+            Ok(value)
+        });
+        assert_eq!(result.unwrap(), 42);
+
+        let result = cb.execute_boxed(|| -> Result<i32, Box<dyn std::error::Error>> {
+            Err(Box::new(std::io::Error::other("error")))
+        });
+        assert!(result.unwrap_err().downcast_ref::<std::io::Error>().is_some());
+
+        let result = cb.execute_boxed(|| Err::<(), _>("plain message"));
+        assert_eq!(result.unwrap_err().to_string(), "plain message");
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let result = cb.execute_boxed(|| Ok::<_, Box<dyn std::error::Error>>(()));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+    }
+
+    #[test]
+    fn test_circuit_breaker_open_error_retry_after() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .build().unwrap();
+        cb.handle_failure();
+
+        let retry_after = |cb: &CircuitBreaker| {
+            match cb.execute(|| Ok::<_, std::io::Error>(())).unwrap_err()
+                .downcast_ref::<CircuitBreakerError>() {
+                Some(CircuitBreakerError::CircuitOpen { retry_after }) => *retry_after,
+                other => panic!("unexpected error: {:?}", other),
+            }
+        };
+
+        assert_eq!(retry_after(&cb), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(retry_after(&cb), Some(Duration::from_secs(6)));
+        clock.advance(Duration::from_secs(5));
+        let remaining = retry_after(&cb).unwrap();
+        assert!(remaining > Duration::ZERO && remaining <= Duration::from_secs(1));
+
+        let error = CircuitBreakerError::CircuitOpen { retry_after: Some(Duration::from_secs(1)) };
+        assert_eq!(error.to_string(), "Circuit is open, retry after 1s");
+
+        cb.force_open();
+        assert_eq!(retry_after(&cb), None);
+    }
+
+    struct MockStore {
+        state: Mutex<StoredState>,
+        stores: AtomicUsize,
+    }
+
+    impl StateStore for MockStore {
+        fn load(&self) -> StoredState {
+            *self.state.lock().unwrap()
+        }
+
+        fn store(&self, state: StoredState) {
+            self.stores.fetch_add(1, Ordering::SeqCst);
+            *self.state.lock().unwrap() = state;
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_shared_state_store() {
+        let store = Arc::new(MockStore {
+            state: Mutex::new(Default::default()),
+            stores: AtomicUsize::new(0),
+        });
+        let a = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .state_store(store.clone())
+            .build().unwrap();
+        let b = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .state_store(store.clone())
+            .build().unwrap();
+
+        // Failures recorded by either breaker count toward the shared threshold.
+        let _ = a.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(b.metrics().failures, 1);
+        let _ = b.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(a.state(), CircuitState::Open);
+
+        let result = a.execute(|| Ok::<_, std::io::Error>(()));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+
+        // Reads that change nothing do not write back to the store.
+        let stores = store.stores.load(Ordering::SeqCst);
+        let _ = b.state();
+        let _ = b.metrics();
+        assert_eq!(store.stores.load(Ordering::SeqCst), stores);
+
+        b.force_close();
+        assert_eq!(a.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_peek_state_and_current_failures() {
+        let clock = MockClock::new();
+        let transitions = Arc::new(AtomicUsize::new(0));
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(1))
+            .clock(clock.clone())
+            .build().unwrap();
+        {
+            let transitions = Arc::clone(&transitions);
+            cb.set_on_half_open(move || {
+                transitions.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        cb.handle_failure();
+        assert_eq!(cb.current_failures(), 1);
+        cb.handle_failure();
+        assert_eq!(cb.current_failures(), 2);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+        assert_eq!(transitions.load(Ordering::SeqCst), 0);
+
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(cb.peek_state(), CircuitState::HalfOpen);
+        assert_eq!(transitions.load(Ordering::SeqCst), 1);
+        assert_eq!(cb.current_failures(), 2);
+    }
+
+    #[test]
+    fn test_circuit_breaker_failure_classifier_actions() {
+        let cb = CircuitBreaker::builder(5, Duration::from_secs(60))
+            .failure_classifier(|e| match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+                Some(std::io::ErrorKind::PermissionDenied) => FailureAction::ForceOpen,
+                Some(std::io::ErrorKind::NotFound) => FailureAction::Ignore,
+                Some(std::io::ErrorKind::TimedOut) => FailureAction::CountWeighted(2),
+                _ => FailureAction::Count,
+            })
+            .build().unwrap();
+
+        let outcome = cb.execute_with_outcome(|| {
+            Err::<(), _>(std::io::Error::from(std::io::ErrorKind::NotFound))
+        });
+        assert!(!outcome.counted);
+        assert_eq!(cb.current_failures(), 0);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::from(std::io::ErrorKind::TimedOut)));
+        assert_eq!(cb.current_failures(), 2);
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.current_failures(), 3);
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        let outcome = cb.execute_with_outcome(|| {
+            Err::<(), _>(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        });
+        assert!(outcome.counted);
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_classifiers_io_transient_only() {
+        use std::io::{Error, ErrorKind};
+        let classify = circuit_breaker::classifiers::io_transient_only();
+
+        for kind in [ErrorKind::ConnectionRefused, ErrorKind::ConnectionReset, ErrorKind::TimedOut] {
+            assert!(classify(&Error::from(kind)), "{:?} should count", kind);
+        }
+        for kind in [ErrorKind::NotFound, ErrorKind::PermissionDenied, ErrorKind::InvalidInput] {
+            assert!(!classify(&Error::from(kind)), "{:?} should be ignored", kind);
+        }
+        assert!(classify(&std::fmt::Error));
+
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .error_classifier(circuit_breaker::classifiers::io_transient_only())
+            .build().unwrap();
+        let _ = cb.execute(|| Err::<(), _>(Error::from(ErrorKind::NotFound)));
+        assert_eq!(cb.state(), CircuitState::Closed);
+        let _ = cb.execute(|| Err::<(), _>(Error::from(ErrorKind::ConnectionRefused)));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_failures_do_not_grow_while_open() {
+        let opened = Arc::new(AtomicUsize::new(0));
+        let cb = {
+            let opened = Arc::clone(&opened);
+            CircuitBreaker::builder(3, Duration::from_secs(60))
+                .on_open(move || {
+                    opened.fetch_add(1, Ordering::SeqCst);
+                })
+                .build().unwrap()
+        };
+
+        cb.handle_failure_weighted(2);
+        cb.handle_failure_weighted(u32::MAX);
+        assert_eq!(cb.current_failures(), u32::MAX);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        for _ in 0..100_000 {
+            cb.handle_failure_weighted(u32::MAX);
+        }
+        assert_eq!(cb.current_failures(), u32::MAX);
+        assert_eq!(cb.metrics().total_failures, 100_002);
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+        let mut machine = StateMachine::new(2, 10);
+        for tick in 0..1000 {
+            machine.record_failure(tick);
+        }
+        assert_eq!(machine.failures(), 2);
+    }
+
+    #[test]
+    fn test_sharded_circuit_breaker_aggregates_failures() {
+        let cb = Arc::new(ShardedCircuitBreaker::new(
+            CircuitBreaker::new(8, Duration::from_secs(60)),
+            4,
+            2,
+            Duration::from_secs(60),
+        ));
+
+        // Each thread records one failure, below the per-shard flush threshold.
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cb = Arc::clone(&cb);
+                thread::spawn(move || {
+                    let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cb.inner().current_failures(), 0);
+
+        cb.flush();
+        assert_eq!(cb.inner().current_failures(), 4);
+        assert_eq!(cb.inner().metrics().total_failures, 4);
+
+        // Failures from a single thread roll up every two failures until the shared breaker trips.
+        for _ in 0..4 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        }
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let result = cb.execute(|| Ok::<_, std::io::Error>(()));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+    }
+
+    #[test]
+    fn test_sharded_circuit_breaker_uses_shared_breaker() {
+        let cb = ShardedCircuitBreaker::new(
+            CircuitBreaker::builder(8, Duration::from_secs(60))
+                .max_concurrency(1)
+                .build().unwrap(),
+            1,
+            2,
+            Duration::from_secs(60),
+        );
+
+        // Successes are batched into the shared breaker alongside failures.
+        for _ in 0..3 {
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        }
+        assert_eq!(cb.inner().metrics().total_successes, 0);
+        cb.flush();
+        assert_eq!(cb.inner().metrics().total_successes, 3);
+
+        // Calls made while closed still count towards the shared concurrency limit.
+        let nested = cb.execute(|| Ok::<_, std::io::Error>(cb.execute(|| Ok::<_, std::io::Error>(())))).unwrap();
+        assert!(matches!(nested.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::ConcurrencyLimit { limit: 1 })));
+        assert_eq!(cb.inner().metrics().rejected_calls, 1);
+
+        // Opening the shared breaker directly takes effect on the next call.
+        cb.inner().force_open();
+        let result = cb.execute(|| Ok::<_, std::io::Error>(()));
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert_eq!(cb.inner().metrics().rejected_calls, 2);
+    }
+
+    #[test]
+    fn test_circuit_breaker_try_acquire_permits() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        cb.try_acquire().unwrap().report_failure();
+        assert_eq!(cb.current_failures(), 1);
+        drop(cb.try_acquire().unwrap());
+        assert_eq!(cb.metrics().total_successes, 1);
+        cb.try_acquire().unwrap().report_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        assert!(matches!(cb.try_acquire().err(), Some(CircuitBreakerError::CircuitOpen { .. })));
+
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .unreported_permit_is_failure(true)
+            .build().unwrap();
+        let permit = cb.try_acquire().unwrap();
+        assert_eq!(permit.state(), CircuitState::Closed);
+        drop(permit);
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_probe_gates_admission() {
+        let clock = MockClock::new();
+        let healthy = Arc::new(AtomicBool::new(false));
+        let probes = Arc::new(AtomicUsize::new(0));
+        let cb = {
+            let healthy = Arc::clone(&healthy);
+            let probes = Arc::clone(&probes);
+            CircuitBreaker::builder(1, Duration::from_secs(1))
+                .clock(clock.clone())
+                .half_open_probe(move || {
+                    probes.fetch_add(1, Ordering::SeqCst);
+                    healthy.load(Ordering::SeqCst)
+                })
+                .build().unwrap()
+        };
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(probes.load(Ordering::SeqCst), 0);
+
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(1));
+        let executed = AtomicBool::new(false);
+        let result = cb.execute(|| {
+            executed.store(true, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(())
+        });
+        assert!(matches!(result.unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert!(!executed.load(Ordering::SeqCst));
+        assert_eq!(probes.load(Ordering::SeqCst), 1);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        healthy.store(true, Ordering::SeqCst);
+        clock.advance(Duration::from_secs(1));
+        let result = cb.execute(|| {
+            executed.store(true, Ordering::SeqCst);
+            Err::<(), _>(std::io::Error::other("error"))
+        });
+        assert!(result.is_err());
+        assert!(executed.load(Ordering::SeqCst));
+        assert_eq!(probes.load(Ordering::SeqCst), 2);
+        // The probe closed the circuit, so the real call ran and its failure tripped it again.
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_closed_reset_modes() {
+        // Two failures for every success: a steadily failing dependency.
+        let run = |mode: ClosedResetMode| {
+            let cb = CircuitBreaker::builder(5, Duration::from_secs(60))
+                .closed_reset_mode(mode)
+                .build().unwrap();
+            for i in 0..12 {
+                if i % 3 == 2 {
+                    let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+                } else {
+                    let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+                }
+            }
+            (cb.state(), cb.current_failures())
+        };
+
+        assert_eq!(run(ClosedResetMode::Reset), (CircuitState::Closed, 0));
+        assert_eq!(run(ClosedResetMode::Decrement), (CircuitState::Open, 5));
+        assert_eq!(run(ClosedResetMode::Keep), (CircuitState::Open, 5));
+
+        let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+            .closed_reset_mode(ClosedResetMode::Decrement)
+            .build().unwrap();
+        for _ in 0..2 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        }
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+        assert_eq!(cb.current_failures(), 1);
+    }
+
+    struct Service<'a> {
+        fetch: Box<dyn Fn() -> Result<u32, Box<dyn std::error::Error>> + 'a>,
+    }
+
+    #[test]
+    fn test_circuit_breaker_guard() {
+        let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+        let calls = AtomicUsize::new(0);
+        let service = Service {
+            fetch: Box::new(cb.guard(|| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<u32, _>(std::io::Error::other("error"))
+            })),
+        };
+
+        for _ in 0..3 {
+            assert!((service.fetch)().unwrap_err().downcast_ref::<std::io::Error>().is_some());
+        }
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        assert!(matches!((service.fetch)().unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[derive(Debug)]
+    struct BorrowedError<'a> {
+        input: &'a str,
+    }
+
+    impl std::fmt::Display for BorrowedError<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "cannot parse {:?}", self.input)
+        }
+    }
+
+    impl std::error::Error for BorrowedError<'_> {}
+
+    #[test]
+    fn test_circuit_breaker_execute_ref() {
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        let buffer = String::from("name:circuit");
+
+        fn parse(input: &str) -> Result<&str, BorrowedError<'_>> {
+            input.strip_prefix("name:").ok_or(BorrowedError { input })
+        }
+
+        let name = cb.execute_ref(|| parse(&buffer)).unwrap();
+        assert_eq!(name, "circuit");
+
+        let bad = String::from("garbage");
+        match cb.execute_ref(|| parse(&bad)) {
+            Err(BreakerError::Inner(e)) => assert_eq!(e.input, "garbage"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        assert!(matches!(cb.execute_ref(|| parse(&buffer)),
+                         Err(BreakerError::Open(CircuitBreakerError::CircuitOpen { .. }))));
+    }
+
+    #[derive(Debug)]
+    enum UpstreamError {
+        Timeout,
+        Unavailable(u16),
+        BadRequest,
+    }
+
+    impl std::fmt::Display for UpstreamError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl std::error::Error for UpstreamError {}
+
+    #[test]
+    fn test_circuit_breaker_execute_with_reason() {
+        let cb = CircuitBreaker::new(10, Duration::from_secs(60));
+        let map = |e: &UpstreamError| match e {
+            UpstreamError::Timeout => Some(FailureReason::from("timeout")),
+            UpstreamError::Unavailable(status) => Some(FailureReason::from(format!("status_{}", status))),
+            UpstreamError::BadRequest => None,
+        };
+
+        let errors = [
+            UpstreamError::Timeout,
+            UpstreamError::Unavailable(503),
+            UpstreamError::Timeout,
+            UpstreamError::BadRequest,
+            UpstreamError::Unavailable(502),
+            UpstreamError::Timeout,
+        ];
+        for error in errors {
+            match cb.execute_with_reason(|| Err::<(), _>(error), map) {
+                Err(BreakerError::Inner(_)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        assert_eq!(cb.execute_with_reason(|| Ok::<_, UpstreamError>(1), map).unwrap(), 1);
+
+        let metrics = cb.metrics();
+        assert_eq!(metrics.total_failures, 5);
+        assert_eq!(metrics.total_successes, 1);
+        let reasons: Vec<_> = metrics.failure_reasons.iter().map(|(reason, count)| (reason.as_str(), *count)).collect();
+        assert_eq!(reasons, vec![("status_502", 1), ("status_503", 1), ("timeout", 3)]);
+
+        // Failures recorded without a reason only show up in the totals.
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.metrics().total_failures, 6);
+        assert_eq!(cb.metrics().failure_reasons.values().sum::<u64>(), 5);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_pure() {
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .error_classifier(|e| !e.is::<std::fmt::Error>())
+            .build()
+            .unwrap();
+
+        assert_eq!(cb.execute_pure(|| Ok::<_, std::io::Error>(7)).unwrap(), 7);
+
+        assert!(matches!(cb.execute_pure(|| Err::<(), _>(std::fmt::Error)),
+                         Err(BreakerError::Inner(std::fmt::Error))));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        match cb.execute_pure(|| Err::<(), _>(std::io::Error::other("boom"))) {
+            Err(BreakerError::Inner(e)) => assert_eq!(e.to_string(), "boom"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        assert!(matches!(cb.execute_pure(|| Ok::<_, std::io::Error>(7)),
+                         Err(BreakerError::Open(CircuitBreakerError::CircuitOpen { .. }))));
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_bypass() {
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .max_concurrency(1)
+            .build()
+            .unwrap();
+        cb.force_open();
+
+        let mut ran = false;
+        let result = cb.execute_bypass(|| {
+            ran = true;
+            Ok::<_, std::io::Error>(7)
+        });
+        assert!(ran);
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.metrics().total_successes, 1);
+        assert_eq!(cb.metrics().rejected_calls, 0);
+
+        let error = cb.execute_bypass(|| Err::<(), _>(std::io::Error::other("down"))).unwrap_err();
+        assert_eq!(error.to_string(), "down");
+        assert_eq!(cb.metrics().total_failures, 1);
+
+        // A bypassing call ignores the concurrency limit and can trip a closed circuit.
+        cb.force_close();
+        let permit = cb.try_acquire().unwrap();
+        for _ in 0..2 {
+            let _ = cb.execute_bypass(|| Err::<(), _>(std::io::Error::other("down")));
+        }
+        assert_eq!(cb.state(), CircuitState::Open);
+        permit.report_success();
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_bypass_leaves_trial_alone() {
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .half_open_single_flight(true)
+            .build()
+            .unwrap();
+        cb.force_half_open();
+        let trial = cb.try_acquire().unwrap();
+
+        // A bypassed failure is counted but neither re-opens the circuit nor ends the trial.
+        let _ = cb.execute_bypass(|| Err::<(), _>(std::io::Error::other("down")));
+        cb.execute_best_effort(|| Err::<(), _>(std::io::Error::other("close failed")));
+        assert_eq!(cb.metrics().total_failures, 2);
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert!(!cb.try_admit_trial());
+
+        // The trial still decides the outcome.
+        trial.report_success();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_best_effort() {
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .max_concurrency(1)
+            .build()
+            .unwrap();
+
+        cb.execute_best_effort(|| Ok::<_, std::io::Error>(()));
+        assert_eq!(cb.metrics().total_successes, 1);
+
+        let permit = cb.try_acquire().unwrap();
+        cb.execute_best_effort(|| Err::<(), _>(std::io::Error::other("close failed")));
+        cb.execute_best_effort(|| Err::<(), _>(std::io::Error::other("close failed")));
+        assert_eq!(cb.metrics().total_failures, 2);
+        assert_eq!(cb.state(), CircuitState::Open);
+        permit.report_success();
+
+        // Teardown still runs while the circuit is open, and is never counted as rejected.
+        let mut ran = false;
+        cb.execute_best_effort(|| {
+            ran = true;
+            Ok::<_, std::io::Error>(())
+        });
+        assert!(ran);
+        assert_eq!(cb.metrics().total_successes, 3);
+        assert_eq!(cb.metrics().rejected_calls, 0);
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_typed_circuit_breaker() {
+        let cb: TypedCircuitBreaker<UpstreamError> = TypedCircuitBreaker::new(2, Duration::from_secs(60))
+            .failure_classifier(|e| match e {
+                UpstreamError::BadRequest => FailureAction::Ignore,
+                UpstreamError::Unavailable(503) => FailureAction::ForceOpen,
+                _ => FailureAction::Count,
+            });
+
+        assert_eq!(cb.execute(|| Ok::<_, UpstreamError>(7)).unwrap(), 7);
+
+        assert!(matches!(cb.execute(|| Err::<(), _>(UpstreamError::BadRequest)),
+                         Err(BreakerError::Inner(UpstreamError::BadRequest))));
+        assert_eq!(cb.metrics().total_failures, 0);
+
+        let _ = cb.execute(|| Err::<(), _>(UpstreamError::Timeout));
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.metrics().total_failures, 1);
+
+        assert!(matches!(cb.execute(|| Err::<(), _>(UpstreamError::Unavailable(503))),
+                         Err(BreakerError::Inner(UpstreamError::Unavailable(503)))));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        assert!(matches!(cb.execute(|| Ok::<_, UpstreamError>(7)),
+                         Err(BreakerError::Open(CircuitBreakerError::CircuitOpen { .. }))));
+
+        // Without a classifier every error counts.
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .build_typed::<UpstreamError>()
+            .unwrap();
+        let _ = cb.execute(|| Err::<(), _>(UpstreamError::BadRequest));
+        assert_eq!(cb.state(), CircuitState::Open);
+        match cb.execute(|| Ok::<_, UpstreamError>(7)) {
+            Err(BreakerError::Open(CircuitBreakerError::CircuitOpen { .. })) => {}
+            other => panic!("expected the circuit to be open, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_async_callbacks() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let cb = {
+            let open_sender = Mutex::new(sender.clone());
+            let close_sender = Mutex::new(sender);
+            CircuitBreaker::builder(1, Duration::ZERO)
+                .async_callbacks(true)
+                .on_open(move || {
+                    thread::sleep(Duration::from_millis(300));
+                    open_sender.lock().unwrap().send(("open", thread::current().id())).unwrap();
+                })
+                .on_close(move || {
+                    close_sender.lock().unwrap().send(("close", thread::current().id())).unwrap();
+                })
+                .build().unwrap()
+        };
+
+        let start = Instant::now();
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // The slow open callback still runs before the close callback queued after it.
+        let (first, first_thread) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        let (second, _) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((first, second), ("open", "close"));
+        assert_ne!(first_thread, thread::current().id());
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_decider() {
+        #[derive(Debug)]
+        struct Readiness {
+            ready: bool,
+        }
+
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .build().unwrap();
+        let is_ready = |readiness: &Readiness| readiness.ready;
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+
+        // A successful but not-ready trial is returned and counted, but keeps the circuit half-open.
+        let readiness = cb
+            .execute_with_decider(|| Ok::<_, std::io::Error>(Readiness { ready: false }), is_ready)
+            .unwrap();
+        assert!(!readiness.ready);
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(cb.metrics().total_successes, 1);
+
+        // A failed trial still re-opens the circuit, and a ready one closes it.
+        let _ = cb.execute_with_decider(|| Err::<Readiness, _>(std::io::Error::other("down")), is_ready);
+        assert_eq!(cb.state(), CircuitState::Open);
+        clock.advance(Duration::from_secs(10));
+        cb.execute_with_decider(|| Ok::<_, std::io::Error>(Readiness { ready: true }), is_ready)
+            .unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // The decider only applies to half-open trials.
+        cb.execute_with_decider(|| Ok::<_, std::io::Error>(Readiness { ready: false }), is_ready)
+            .unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // Plain execute does not require 'static values and never consults a decider.
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        let buffer = String::from("ready=false");
+        let value = cb.execute(|| Ok::<_, std::io::Error>(&buffer[6..])).unwrap();
+        assert_eq!(value, "false");
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_health() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(4, Duration::from_secs(10))
+            .clock(clock.clone())
+            .degraded_failure_fraction(0.75)
+            .build().unwrap();
+        assert_eq!(cb.health(), Health::Healthy);
+
+        cb.handle_failure();
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Healthy);
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Degraded);
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Unhealthy);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(cb.health(), Health::Degraded);
+        cb.handle_success();
+        assert_eq!(cb.health(), Health::Healthy);
+
+        // With the default fraction of 0.5, half the threshold is degraded.
+        let cb = CircuitBreaker::new(4, Duration::from_secs(10));
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Healthy);
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Degraded);
+
+        // A zero fraction degrades on the first failure, but not before.
+        let cb = CircuitBreaker::builder(4, Duration::from_secs(10))
+            .degraded_failure_fraction(0.0)
+            .build().unwrap();
+        assert_eq!(cb.health(), Health::Healthy);
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Degraded);
+
+        // Without automatic tripping, a closed circuit stays healthy.
+        let cb = CircuitBreaker::new(0, Duration::from_secs(10));
+        cb.handle_failure();
+        assert_eq!(cb.health(), Health::Healthy);
+        cb.force_open();
+        assert_eq!(cb.health(), Health::Unhealthy);
+
+        assert_eq!(
+            CircuitBreaker::builder(4, Duration::from_secs(10)).degraded_failure_fraction(1.5).build().err(),
+            Some(ConfigError::InvalidFraction { name: "degraded_failure_fraction" })
+        );
+    }
+
+    #[test]
+    fn test_circuit_breaker_drain_metrics() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        let _ = cb.execute_with_reason(|| Err::<(), _>(std::io::Error::other("error")), |_| Some(FailureReason::from("io")));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+
+        let drained = cb.drain_metrics();
+        assert_eq!(drained.state, CircuitState::Open);
+        assert_eq!(drained.total_successes, 1);
+        assert_eq!(drained.total_failures, 2);
+        assert_eq!(drained.rejected_calls, 1);
+        assert_eq!(drained.trip_count, 1);
+        assert_eq!(drained.failure_reasons.get(&FailureReason::from("io")), Some(&1));
+
+        // The cumulative counters start fresh, while the state is unaffected.
+        let fresh = cb.metrics();
+        assert_eq!(fresh.state, CircuitState::Open);
+        assert_eq!(fresh.failures, drained.failures);
+        assert_eq!((fresh.total_successes, fresh.total_failures, fresh.rejected_calls, fresh.trip_count), (0, 0, 0, 0));
+        assert!(fresh.failure_reasons.is_empty());
+
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+        assert_eq!(cb.drain_metrics().rejected_calls, 1);
+        assert_eq!(cb.metrics().rejected_calls, 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker_persist_round_trip() {
+        let clock = MockClock::new();
+        let build = || {
+            CircuitBreaker::builder(3, Duration::from_secs(60))
+                .clock(clock.clone())
+                .build()
+                .unwrap()
+        };
+
+        let cb = build();
+        for _ in 0..3 {
+            cb.handle_failure();
+        }
+        clock.advance(Duration::from_secs(20));
+        let mut saved = Vec::new();
+        cb.persist_to(&mut saved).unwrap();
+
+        let restored = build();
+        let opened = Arc::new(AtomicUsize::new(0));
+        let counter = opened.clone();
+        restored.set_on_open(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        restored.restore_from(&mut saved.as_slice()).unwrap();
+        assert_eq!(restored.state(), CircuitState::Open);
+        assert_eq!(restored.current_failures(), 3);
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+        assert_eq!(restored.trip_count(), 0);
+
+        // The reset timeout keeps running from the original failure.
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(restored.state(), CircuitState::Open);
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(restored.state(), CircuitState::HalfOpen);
+
+        // A circuit held open by hand stays held open.
+        cb.force_open();
+        let mut saved = Vec::new();
+        cb.persist_to(&mut saved).unwrap();
+        let restored = build();
+        restored.restore_from(&mut saved.as_slice()).unwrap();
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(restored.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_restore_corrupt_input() {
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        let mut saved = Vec::new();
+        cb.persist_to(&mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+
+        let truncated: String = saved.lines().take(2).map(|line| format!("{}\n", line)).collect();
+        let error = cb.restore_from(&mut truncated.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let corrupted = saved.replace("state=Closed", "state=Ajar");
+        let error = cb.restore_from(&mut corrupted.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Ajar"));
+
+        let error = cb.restore_from(&mut &b"\xff\xfe garbage"[..]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let error = cb.restore_from(&mut saved.replace("failures=0", "failures=-1").as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        cb.restore_from(&mut saved.replace("state=Closed", "state=Open").as_bytes()).unwrap();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_extend_timeout_on_rejection() {
+        let clock = MockClock::new();
+        let build = |extend| {
+            CircuitBreaker::builder(1, Duration::from_secs(10))
+                .clock(clock.clone())
+                .extend_timeout_on_rejection(extend)
+                .build()
+                .unwrap()
+        };
+        let fixed = build(false);
+        let extended = build(true);
+        fixed.handle_failure();
+        extended.handle_failure();
+
+        clock.advance(Duration::from_secs(6));
+        for (cb, remaining) in [(&fixed, 4), (&extended, 10)] {
+            let error = cb.execute(|| Ok::<_, std::io::Error>(())).unwrap_err();
+            assert!(matches!(error.downcast_ref::<CircuitBreakerError>(),
+                             Some(CircuitBreakerError::CircuitOpen { retry_after: Some(retry_after) })
+                                 if *retry_after == Duration::from_secs(remaining)));
+        }
+
+        // The original timeout has elapsed, but the rejection restarted the extended one.
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(fixed.state(), CircuitState::HalfOpen);
+        assert_eq!(extended.state(), CircuitState::Open);
+
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(extended.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn test_circuit_breaker_record_outcome() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let changes = cb.subscribe();
+
+        cb.record_outcome(false);
+        cb.record_outcome(true);
+        cb.record_outcome(false);
+        cb.record_outcome(false);
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+
+        // Before the reset timeout, outcomes leave the circuit open.
+        clock.advance(Duration::from_secs(5));
+        cb.record_outcome(true);
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+
+        // After it, a failed check is a failed trial and re-opens the circuit.
+        clock.advance(Duration::from_secs(10));
+        cb.record_outcome(false);
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+
+        // A successful check closes it.
+        clock.advance(Duration::from_secs(10));
+        cb.record_outcome(true);
+        assert_eq!(cb.peek_state(), CircuitState::Closed);
+
+        let transitions: Vec<_> = changes.try_iter().map(|change| (change.from, change.to)).collect();
+        assert_eq!(transitions, [
+            (CircuitState::Closed, CircuitState::Open),
+            (CircuitState::Open, CircuitState::HalfOpen),
+            (CircuitState::HalfOpen, CircuitState::Open),
+            (CircuitState::Open, CircuitState::HalfOpen),
+            (CircuitState::HalfOpen, CircuitState::Closed),
+        ]);
+        assert_eq!(cb.metrics().total_failures, 4);
+        assert_eq!(cb.metrics().total_successes, 3);
+    }
+
+    #[test]
+    fn test_circuit_breaker_probation() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(3, Duration::from_secs(5))
+            .clock(clock.clone())
+            .probation(Duration::from_secs(60), 1)
+            .build()
+            .unwrap();
+        let recover = || {
+            for _ in 0..3 {
+                cb.handle_failure();
+            }
+            assert_eq!(cb.state(), CircuitState::Open);
+            clock.advance(Duration::from_secs(5));
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+            assert_eq!(cb.state(), CircuitState::Closed);
+        };
+
+        // Probation does not apply before the circuit has ever recovered.
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.handle_failure();
+        cb.handle_failure();
+
+        // A single failure during probation re-opens the circuit.
+        clock.advance(Duration::from_secs(5));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        clock.advance(Duration::from_secs(59));
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // The same failure after probation does not.
+        clock.advance(Duration::from_secs(5));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        clock.advance(Duration::from_secs(60));
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.handle_failure();
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // Closing the circuit by hand ends probation.
+        cb.force_close();
+        recover();
+        cb.force_close();
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    /// A clock that moves forward by `step` every time it is read.
+    struct SteppingClock {
+        now: Mutex<Instant>,
+        step: Duration,
+    }
+
+    impl SteppingClock {
+        fn new(step: Duration) -> Arc<Self> {
+            Arc::new(SteppingClock { now: Mutex::new(Instant::now()), step })
+        }
+    }
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            let mut now = self.now.lock().unwrap();
+            let current = *now;
+            *now += self.step;
+            current
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_open_behavior_fail_fast() {
+        // The clock never moves, so a call that waited would never return.
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .clock(MockClock::new())
+            .open_behavior(OpenBehavior::FailFast)
+            .build()
+            .unwrap();
+        cb.handle_failure();
+
+        let error = cb.execute(|| Ok::<_, std::io::Error>(())).unwrap_err();
+        assert!(matches!(error.downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert_eq!(cb.metrics().rejected_calls, 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_open_behavior_wait_for_reset() {
+        let build = |reset_timeout, max, clock: Arc<dyn Clock>| {
+            CircuitBreaker::builder(1, reset_timeout)
+                .clock(clock)
+                .open_behavior(OpenBehavior::WaitForReset { max })
+                .build()
+                .unwrap()
+        };
+
+        // The reset timeout elapses on the breaker's clock during the wait, so the call is
+        // admitted as a trial.
+        let cb = build(Duration::from_secs(10), Duration::from_secs(60), SteppingClock::new(Duration::from_secs(1)));
+        cb.handle_failure();
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.metrics().rejected_calls, 0);
+
+        // The reset timeout outlasts the wait, so the call is rejected once, at the end of it.
+        let cb = build(Duration::from_secs(3600), Duration::from_secs(5), SteppingClock::new(Duration::from_secs(1)));
+        cb.handle_failure();
+        let error = cb.execute(|| Ok::<_, std::io::Error>(())).unwrap_err();
+        assert!(matches!(error.downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert_eq!(cb.metrics().rejected_calls, 1);
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+
+        // Closing the circuit from another thread ends the wait, though the clock never moves.
+        let cb = Arc::new(build(Duration::from_secs(3600), Duration::from_secs(3600), MockClock::new()));
+        cb.force_open();
+        let closer = {
+            let cb = cb.clone();
+            thread::spawn(move || {
+                while cb.in_flight() == 0 {
+                    thread::yield_now();
+                }
+                cb.force_close();
+            })
+        };
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        closer.join().unwrap();
+        assert_eq!(cb.metrics().rejected_calls, 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker_warmup() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .clock(clock.clone())
+            .warmup(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        for _ in 0..5 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("cold")));
+        }
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.current_failures(), 0);
+        assert_eq!(cb.metrics().total_failures, 5);
+
+        clock.advance(Duration::from_secs(10));
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        cb.handle_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_manual_trials() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .half_open_max_calls(1)
+            .build()
+            .unwrap();
+
+        assert!(!cb.try_admit_trial());
+        cb.handle_failure();
+        assert!(!cb.try_admit_trial());
+        assert_eq!(cb.metrics().rejected_calls, 0);
+
+        clock.advance(Duration::from_secs(10));
+        assert!(cb.try_admit_trial());
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert!(!cb.try_admit_trial());
+        cb.report_trial(false);
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        clock.advance(Duration::from_secs(10));
+        assert!(cb.try_admit_trial());
+        cb.report_trial(true);
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert!(!cb.try_admit_trial());
+    }
+
+    #[test]
+    fn test_circuit_breaker_manual_trials_respect_concurrency_limit() {
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .max_concurrency(1)
+            .half_open_single_flight(true)
+            .build()
+            .unwrap();
+        cb.force_half_open();
+
+        // A reserved trial counts as in flight until it is reported.
+        assert!(cb.try_admit_trial());
+        assert_eq!(cb.in_flight(), 1);
+        assert!(matches!(cb.try_acquire(), Err(CircuitBreakerError::ConcurrencyLimit { limit: 1 })));
+        cb.report_trial(false);
+        assert_eq!(cb.in_flight(), 0);
+
+        // A call holding the only slot keeps a trial from being reserved.
+        cb.force_half_open();
+        let permit = cb.try_acquire().unwrap();
+        assert!(!cb.try_admit_trial());
+        assert_eq!(cb.metrics().rejected_calls, 2);
+        permit.report_success();
+
+        // A single-flight trial already in flight refuses at once rather than waiting for it.
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .half_open_single_flight(true)
+            .build()
+            .unwrap();
+        cb.force_half_open();
+        assert!(cb.try_admit_trial());
+        let started = Instant::now();
+        assert!(!cb.try_admit_trial());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        cb.report_trial(true);
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.in_flight(), 0);
+
+        // Reporting without a reservation is ignored.
+        cb.handle_failure();
+        assert!(!cb.report_trial(true));
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.metrics().total_successes, 1);
+        assert_eq!(cb.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_circuit_state_from_str() {
+        for state in [CircuitState::Closed, CircuitState::Open, CircuitState::HalfOpen] {
+            assert_eq!(state.to_string().parse::<CircuitState>(), Ok(state));
+        }
+        assert_eq!("closed".parse::<CircuitState>(), Ok(CircuitState::Closed));
+        assert_eq!("OPEN".parse::<CircuitState>(), Ok(CircuitState::Open));
+        assert_eq!("halfOpen".parse::<CircuitState>(), Ok(CircuitState::HalfOpen));
+
+        for garbage in ["", "half-open", "Opened", "ajar"] {
+            let error = garbage.parse::<CircuitState>().unwrap_err();
+            assert_eq!(error.to_string(), "Invalid circuit state, expected Closed, Open or HalfOpen");
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_transition_guard() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let may_close = Arc::new(AtomicBool::new(false));
+        let closes = Arc::new(AtomicUsize::new(0));
+        {
+            let may_close = may_close.clone();
+            cb.set_transition_guard(move |_, to| to != CircuitState::Closed || may_close.load(Ordering::SeqCst));
+            let closes = closes.clone();
+            cb.set_on_close(move || {
+                closes.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        cb.force_close();
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(closes.load(Ordering::SeqCst), 0);
+
+        // A failed trial still re-opens the circuit.
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("down")));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        may_close.store(true, Ordering::SeqCst);
+        clock.advance(Duration::from_secs(10));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(closes.load(Ordering::SeqCst), 1);
+
+        // Vetoing the move to Half-Open keeps rejecting calls after the reset timeout.
+        cb.set_transition_guard(|from, to| !(from == CircuitState::Open && to == CircuitState::HalfOpen));
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert!(cb.execute(|| Ok::<_, std::io::Error>(())).is_err());
+        assert!(!cb.try_admit_trial());
+    }
+
+    #[test]
+    fn test_circuit_breaker_slow_call_rate_policy() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+            .clock(clock.clone())
+            .or_failure_policy(FailurePolicy::SlowCallRate {
+                threshold: Duration::from_millis(100),
+                rate: 0.5,
+                window: 4,
+            })
+            .build()
+            .unwrap();
+        let call = |latency| {
+            cb.execute(|| {
+                clock.advance(latency);
+                Ok::<_, std::io::Error>(())
+            })
+        };
+
+        // Two slow calls out of three do not count until the window is full.
+        call(Duration::from_millis(500)).unwrap();
+        call(Duration::from_millis(10)).unwrap();
+        call(Duration::from_millis(500)).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // The fourth call fills the window with half of it slow, without any failure.
+        call(Duration::from_millis(10)).unwrap();
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.metrics().total_failures, 0);
+
+        // Closing starts an empty window, and fast calls only leave it as slow ones arrive.
+        cb.force_close();
+        for _ in 0..4 {
+            call(Duration::from_millis(10)).unwrap();
+        }
+        call(Duration::from_millis(500)).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        call(Duration::from_millis(500)).unwrap();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        assert_eq!(
+            CircuitBreaker::builder(3, Duration::from_secs(60))
+                .failure_policy(FailurePolicy::SlowCallRate { threshold: Duration::ZERO, rate: 1.5, window: 4 })
+                .build()
+                .err(),
+            Some(ConfigError::InvalidFraction { name: "slow_call_rate" })
+        );
+        assert_eq!(
+            CircuitBreaker::builder(3, Duration::from_secs(60))
+                .failure_policy(FailurePolicy::SlowCallRate { threshold: Duration::ZERO, rate: 0.5, window: 0 })
+                .build()
+                .err(),
+            Some(ConfigError::ZeroSlowCallWindow)
+        );
+        assert_eq!(ConfigError::EmptyTimeWindow.to_string(), "Time window must have a non-zero duration and buckets");
+    }
+
+    #[test]
+    fn test_circuit_breaker_guard_iter() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+        let taken = std::cell::Cell::new(0);
+        let calls = std::cell::Cell::new(0);
+        let items = (0..10).inspect(|_| taken.set(taken.get() + 1));
+
+        let mut results = cb.guard_iter(items, |item| {
+            calls.set(calls.get() + 1);
+            if item == 0 { Ok(item) } else { Err(std::io::Error::other("down")) }
+        });
+        assert_eq!(calls.get(), 0);
+
+        assert_eq!(results.next().unwrap().unwrap(), 0);
+        assert_eq!((taken.get(), calls.get()), (1, 1));
+
+        // The second failure opens the circuit, so the remaining items are never taken.
+        assert_eq!(results.by_ref().count(), 2);
+        assert!(results.next().is_none());
+        assert_eq!((taken.get(), calls.get()), (3, 3));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // An already open circuit yields one rejection and stops.
+        let results: Vec<_> = cb.guard_iter(0..10, Ok::<_, std::io::Error>).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0].as_ref().unwrap_err().downcast_ref::<CircuitBreakerError>(),
+                         Some(CircuitBreakerError::CircuitOpen { .. })));
+    }
+
+    #[test]
+    fn test_circuit_breaker_consecutive_successes() {
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(60))
+            .error_classifier(|e| !e.is::<std::fmt::Error>())
+            .build()
+            .unwrap();
+        assert_eq!(cb.consecutive_successes(), 0);
+
+        for _ in 0..3 {
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        }
+        cb.handle_success();
+        assert_eq!(cb.consecutive_successes(), 4);
+        assert_eq!(cb.metrics().consecutive_successes, 4);
+
+        // Ignored errors are not failures.
+        let _ = cb.execute(|| Err::<(), _>(std::fmt::Error));
+        assert_eq!(cb.consecutive_successes(), 4);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("down")));
+        assert_eq!(cb.consecutive_successes(), 0);
+        assert_eq!(cb.metrics().consecutive_successes, 0);
+
+        cb.handle_success();
+        cb.handle_failure_weighted(2);
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.consecutive_successes(), 0);
+
+        // A late failure reported while open still breaks the run.
+        cb.handle_success();
+        assert_eq!(cb.consecutive_successes(), 1);
+        cb.handle_failure();
+        assert_eq!(cb.consecutive_successes(), 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker_last_rejection_reason() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .max_concurrency(1)
+            .build()
+            .unwrap();
+        assert_eq!(cb.last_rejection_reason(), None);
+
+        let permit = cb.try_acquire().unwrap();
+        assert!(cb.execute(|| Ok::<_, std::io::Error>(())).is_err());
+        assert_eq!(cb.last_rejection_reason(), Some(RejectionReason::ConcurrencyLimit));
+        permit.report_failure();
+
+        assert!(cb.execute(|| Ok::<_, std::io::Error>(())).is_err());
+        assert_eq!(cb.last_rejection_reason(), Some(RejectionReason::Open));
+
+        // The reason outlives the state it was recorded in.
+        clock.advance(Duration::from_secs(10));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.last_rejection_reason(), Some(RejectionReason::Open));
+
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .half_open_max_calls(1)
+            .build()
+            .unwrap();
+        cb.force_half_open();
+        let permit = cb.try_acquire().unwrap();
+        assert!(cb.try_acquire().is_err());
+        assert_eq!(cb.last_rejection_reason(), Some(RejectionReason::HalfOpenQuota));
+        permit.report_success();
+    }
+
+    #[test]
+    fn test_circuit_breaker_state_since_resets_on_transition() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(5))
+            .clock(clock.clone())
+            .build().unwrap();
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state_since(), Duration::from_secs(10));
+
+        cb.handle_failure();
+        assert_eq!(cb.state_since(), Duration::ZERO);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(cb.state_since(), Duration::from_secs(3));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(cb.state_since(), Duration::ZERO);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cb.state_since(), Duration::from_secs(1));
+
+        cb.handle_success();
+        assert_eq!(cb.state_since(), Duration::ZERO);
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(cb.state_since(), Duration::from_secs(4));
+
+        // A transition to the current state does not restart the clock.
+        cb.force_close();
+        assert_eq!(cb.state_since(), Duration::from_secs(4));
+        cb.force_open();
+        clock.advance(Duration::from_secs(2));
+        cb.force_open();
+        assert_eq!(cb.state_since(), Duration::from_secs(2));
+        let durations = cb.state_durations();
+        assert_eq!(durations.closed, Duration::from_secs(14));
+        assert_eq!(durations.open, Duration::from_secs(7));
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_cancellable() {
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        let cancel = AtomicBool::new(false);
+
+        // Cancelled from another thread while the operation runs.
+        let result = thread::scope(|scope| {
+            let canceller = scope.spawn(|| cancel.store(true, Ordering::SeqCst));
+            cb.execute_cancellable(&cancel, || {
+                while !cancel.load(Ordering::SeqCst) {
+                    thread::yield_now();
+                }
+                canceller.join().unwrap();
+                Err::<(), _>(std::io::Error::from(std::io::ErrorKind::Interrupted))
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(cb.metrics().total_failures, 0);
+
+        // A cancelled call that succeeds still counts as a success.
+        assert_eq!(cb.execute_cancellable(&cancel, || Ok::<_, std::io::Error>(7)).unwrap(), 7);
+        assert_eq!(cb.metrics().total_successes, 1);
+
+        cancel.store(false, Ordering::SeqCst);
+        let _ = cb.execute_cancellable(&cancel, || Err::<(), _>(std::io::Error::other("down")));
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert!(cb.execute_cancellable(&cancel, || Ok::<_, std::io::Error>(())).is_err());
+    }
+
+    #[test]
+    fn test_circuit_breaker_trip_count() {
+        let build = |count_forced_trips| {
+            CircuitBreaker::builder(2, Duration::from_secs(10))
+                .clock(MockClock::new())
+                .count_forced_trips(count_forced_trips)
+                .build()
+                .unwrap()
+        };
+        let fail = |cb: &CircuitBreaker| {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        };
+
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert_eq!(cb.trip_count(), 0);
+
+        fail(&cb);
+        fail(&cb);
+        assert_eq!(cb.trip_count(), 1);
+        // Rejections while open do not trip the circuit again.
+        fail(&cb);
+        assert_eq!(cb.trip_count(), 1);
+
+        // A failed half-open trial re-opens the circuit, which counts as a trip.
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        fail(&cb);
+        assert_eq!(cb.state(), CircuitState::Open);
+        assert_eq!(cb.trip_count(), 2);
+
+        clock.advance(Duration::from_secs(10));
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        fail(&cb);
+        fail(&cb);
+        assert_eq!(cb.trip_count(), 3);
+        assert_eq!(cb.metrics().trip_count, 3);
+
+        let uncounted = build(false);
+        uncounted.force_open();
+        assert_eq!(uncounted.trip_count(), 0);
+
+        let counted = build(true);
+        counted.force_open();
+        counted.force_open();
+        assert_eq!(counted.trip_count(), 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_metrics_diff() {
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+        let before = cb.metrics();
+        assert_eq!(before, cb.metrics());
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        for _ in 0..2 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        }
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+        let after = cb.metrics();
+        assert_ne!(before, after);
+
+        let delta = after.diff(&before);
+        assert_eq!(delta.successes, 1);
+        assert_eq!(delta.failures, 2);
+        assert_eq!(delta.rejected_calls, 1);
+        assert_eq!(delta.trips, 1);
+        assert!(delta.state_changed);
+
+        let reversed = before.diff(&after);
+        assert_eq!((reversed.successes, reversed.failures, reversed.rejected_calls), (0, 0, 0));
+        assert!(!after.diff(&after.clone()).state_changed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_trip_on() {
+        let cb = CircuitBreaker::new(5, Duration::from_secs(60));
+        cb.trip_on::<std::fmt::Error>();
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        let _ = cb.execute(|| Err::<(), _>(std::fmt::Error));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_with_config() {
+        let config = CircuitConfig {
+            failure_threshold: 2,
+            reset_timeout: Duration::ZERO,
+            success_threshold: 2,
+            ..CircuitConfig::default()
+        };
+        let breakers = [
+            CircuitBreaker::with_config(config.clone()).unwrap(),
+            CircuitBreaker::with_config(config).unwrap(),
+        ];
+
+        for cb in &breakers {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+            assert_eq!(cb.state(), CircuitState::Closed);
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+            assert_eq!(cb.state(), CircuitState::HalfOpen);
+
+            // The first successful trial is not enough to close the circuit.
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+            assert_eq!(cb.state(), CircuitState::HalfOpen);
+            cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+            assert_eq!(cb.state(), CircuitState::Closed);
+        }
+        assert_eq!(breakers[0].metrics(), breakers[1].metrics());
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_failure_reopens() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(2, Duration::from_secs(10))
+            .clock(clock.clone())
+            .failure_decay(Duration::from_secs(5))
+            .build().unwrap();
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        // The failures have decayed by the time the circuit is half-open, yet a single failing
+        // trial still re-opens it and restarts the reset timer.
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(cb.state(), CircuitState::Open);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn test_circuit_breaker_from_env() {
+        std::env::set_var("TEST_FROM_ENV_CB_FAILURE_THRESHOLD", "7");
+        std::env::set_var("TEST_FROM_ENV_CB_RESET_TIMEOUT_SECS", "12");
+        let cb = CircuitBreaker::from_env("TEST_FROM_ENV_CB_").unwrap();
+        assert_eq!(cb.failure_threshold(), 7);
+        assert_eq!(cb.reset_timeout(), Duration::from_secs(12));
+
+        let config = CircuitConfig::from_env("TEST_FROM_ENV_UNSET_CB_").unwrap();
+        assert_eq!(config, CircuitConfig::default());
+
+        std::env::set_var("TEST_FROM_ENV_BAD_CB_RESET_TIMEOUT_SECS", "ten");
+        let error = CircuitBreaker::from_env("TEST_FROM_ENV_BAD_CB_").err().unwrap();
+        assert_eq!(error, ConfigError::InvalidEnvVar {
+            name: "TEST_FROM_ENV_BAD_CB_RESET_TIMEOUT_SECS".to_string(),
+            value: "ten".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_circuit_breaker_builder_validation() {
+        let window = |duration, buckets, failure_rate| FailurePolicy::TimeWindow {
+            duration,
+            buckets,
+            failure_rate,
+        };
+        let builder = || CircuitBreaker::builder(3, Duration::from_secs(60));
+
+        assert_eq!(builder().success_threshold(0).build().err(), Some(ConfigError::ZeroSuccessThreshold));
+        assert_eq!(builder().half_open_max_calls(0).build().err(), Some(ConfigError::ZeroHalfOpenMaxCalls));
+        assert_eq!(builder().repeated_error_threshold(0).build().err(), Some(ConfigError::ZeroRepeatedErrorThreshold));
+        assert_eq!(builder().max_concurrency(0).build().err(), Some(ConfigError::ZeroMaxConcurrency));
+        assert_eq!(
+            builder().failure_policy(window(Duration::ZERO, 10, 0.5)).build().err(),
+            Some(ConfigError::EmptyTimeWindow)
+        );
+        assert_eq!(
+            builder().or_failure_policy(window(Duration::from_secs(10), 0, 0.5)).build().err(),
+            Some(ConfigError::EmptyTimeWindow)
+        );
+        assert_eq!(
+            builder().failure_policy(window(Duration::from_nanos(5), 6, 0.5)).build().err(),
+            Some(ConfigError::TimeWindowTooShort)
+        );
+        assert!(builder().failure_policy(window(Duration::from_nanos(6), 6, 0.5)).build().is_ok());
+        for failure_rate in [-0.1, 1.5, f64::NAN] {
+            assert_eq!(
+                builder().failure_policy(window(Duration::from_secs(10), 10, failure_rate)).build().err(),
+                Some(ConfigError::InvalidFraction { name: "failure_rate" })
+            );
+        }
+
+        // A zero failure threshold is valid: it disables automatic tripping.
+        assert!(CircuitBreaker::builder(0, Duration::from_secs(60)).build().is_ok());
+
+        let config = CircuitConfig {
+            success_threshold: 0,
+            ..CircuitConfig::default()
+        };
+        assert_eq!(CircuitBreaker::with_config(config).err(), Some(ConfigError::ZeroSuccessThreshold));
+    }
+
+    #[test]
+    fn test_circuit_breaker_latency_percentile() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(100, Duration::from_secs(60))
+            .clock(clock.clone())
+            .latency_histogram(true)
+            .build().unwrap();
+        assert_eq!(cb.latency_percentile(50.0), None);
+
+        // Calls taking 1ms, 2ms, ..., 100ms.
+        for millis in 1..=100 {
+            cb.execute(|| {
+                clock.advance(Duration::from_millis(millis));
+                Ok::<_, std::io::Error>(())
+            })
+            .unwrap();
+        }
+
+        let within = |p: f64, expected: Duration| {
+            let actual = cb.latency_percentile(p).unwrap();
+            assert!(actual >= expected && actual <= expected.mul_f64(1.07), "p{}: {:?}", p, actual);
+        };
+        within(50.0, Duration::from_millis(50));
+        within(99.0, Duration::from_millis(99));
+        within(100.0, Duration::from_millis(100));
+        assert_eq!(cb.latency_percentile(101.0), None);
+
+        let disabled = CircuitBreaker::new(3, Duration::from_secs(60));
+        disabled.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_eq!(disabled.latency_percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_circuit_breaker_clear_failures() {
+        let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+        for _ in 0..2 {
+            let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        }
+        cb.clear_failures();
+        assert_eq!(cb.current_failures(), 0);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        cb.force_open();
+        cb.clear_failures();
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_execute_with_predicate() {
+        #[derive(Debug, PartialEq)]
+        enum Status {
+            Ok,
+            ServerError,
+        }
+
+        let cb = CircuitBreaker::new(2, Duration::from_secs(60));
+        let is_success = |status: &Status| *status != Status::ServerError;
+
+        assert_eq!(cb.execute_with_predicate(|| Status::Ok, is_success).unwrap(), Status::Ok);
+        assert_eq!(cb.execute_with_predicate(|| Status::ServerError, is_success).unwrap(), Status::ServerError);
+        assert_eq!(cb.current_failures(), 1);
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        cb.execute_with_predicate(|| Status::ServerError, is_success).unwrap();
+        assert_eq!(cb.state(), CircuitState::Open);
+
+        let mut ran = false;
+        let result = cb.execute_with_predicate(|| { ran = true; Status::Ok }, is_success);
+        assert!(matches!(result, Err(CircuitBreakerError::CircuitOpen { .. })));
+        assert!(!ran);
+    }
+
+    #[test]
+    fn test_circuit_breaker_callback_min_interval() {
+        let clock = MockClock::new();
+        let opened = Arc::new(AtomicUsize::new(0));
+        let closed = Arc::new(AtomicUsize::new(0));
+        let cb = {
+            let opened = opened.clone();
+            let closed = closed.clone();
+            CircuitBreaker::builder(1, Duration::from_secs(60))
+                .clock(clock.clone())
+                .callback_min_interval(Duration::from_secs(10))
+                .on_open(move || { opened.fetch_add(1, Ordering::SeqCst); })
+                .on_close(move || { closed.fetch_add(1, Ordering::SeqCst); })
+                .build().unwrap()
+        };
+        let events = cb.subscribe();
+
+        // Flap five times within the interval.
+        for _ in 0..5 {
+            cb.handle_failure();
+            clock.advance(Duration::from_secs(1));
+            cb.force_close();
+        }
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+        assert_eq!(closed.load(Ordering::SeqCst), 1);
+        assert_eq!(events.try_iter().count(), 10);
+
+        // Once the interval has passed, each suppressed callback runs once more.
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+        assert_eq!(closed.load(Ordering::SeqCst), 2);
+        assert_eq!(cb.state(), CircuitState::Closed);
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+        assert_eq!(closed.load(Ordering::SeqCst), 2);
+
+        // A transition whose interval has passed runs its callback straight away.
+        clock.advance(Duration::from_secs(10));
+        cb.handle_failure();
+        assert_eq!(opened.load(Ordering::SeqCst), 3);
+        cb.force_close();
+        assert_eq!(closed.load(Ordering::SeqCst), 3);
+
+        // A suppressed one runs on the first use of the breaker after the interval.
+        cb.handle_failure();
+        assert_eq!(opened.load(Ordering::SeqCst), 3);
+        clock.advance(Duration::from_secs(10));
+        let _ = cb.execute(|| Ok::<_, std::io::Error>(()));
+        assert_eq!(opened.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_circuit_breaker_or_failure_policy() {
+        let rate = FailurePolicy::TimeWindow {
+            duration: Duration::from_secs(60),
+            buckets: 6,
+            failure_rate: 0.5,
+        };
+        let build = |policies: &[FailurePolicy]| {
+            let config = CircuitConfig {
+                failure_threshold: 5,
+                failure_policy: policies[0],
+                additional_failure_policies: policies[1..].to_vec(),
+                minimum_throughput: 10,
+                closed_reset_mode: ClosedResetMode::Reset,
+                ..CircuitConfig::default()
+            };
+            CircuitBreakerBuilder::from(config).clock(MockClock::new()).build().unwrap()
+        };
+        let alternating = |cb: &CircuitBreaker| {
+            for _ in 0..5 {
+                cb.handle_success();
+                cb.handle_failure();
+            }
+        };
+        let burst = |cb: &CircuitBreaker| {
+            for _ in 0..20 {
+                cb.handle_success();
+            }
+            for _ in 0..5 {
+                cb.handle_failure();
+            }
+        };
+
+        // Alternating outcomes never build a run of failures but reach a 50% failure rate.
+        let consecutive_only = build(&[FailurePolicy::ConsecutiveFailures]);
+        alternating(&consecutive_only);
+        assert_eq!(consecutive_only.state(), CircuitState::Closed);
+        let combined = build(&[FailurePolicy::ConsecutiveFailures, rate]);
+        alternating(&combined);
+        assert_eq!(combined.state(), CircuitState::Open);
+
+        // A burst after plenty of successes is a run of failures at only a 20% failure rate.
+        let rate_only = build(&[rate]);
+        burst(&rate_only);
+        assert_eq!(rate_only.state(), CircuitState::Closed);
+        let combined = build(&[FailurePolicy::ConsecutiveFailures, rate]);
+        burst(&combined);
+        assert_eq!(combined.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_time_since_last_success() {
+        let clock = MockClock::new();
+        let cb = CircuitBreaker::builder(3, Duration::from_secs(60))
+            .clock(clock.clone())
+            .build().unwrap();
+        assert_eq!(cb.time_since_last_success(), None);
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cb.time_since_last_success(), Some(Duration::from_secs(5)));
+
+        // Failures do not move it.
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cb.time_since_last_success(), Some(Duration::from_secs(7)));
+
+        cb.handle_success();
+        assert_eq!(cb.time_since_last_success(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_quota_under_race() {
+        const THREADS: usize = 16;
+        let clock = MockClock::new();
+        // Ignored errors keep the circuit half-open, so only the quota limits admissions.
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .error_classifier(|_| false)
+            .half_open_max_calls(2)
+            .build().unwrap();
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.peek_state(), CircuitState::Open);
+
+        let executed = AtomicUsize::new(0);
+        let barrier = std::sync::Barrier::new(THREADS);
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    barrier.wait();
+                    let _ = cb.execute(|| {
+                        executed.fetch_add(1, Ordering::SeqCst);
+                        Err::<(), _>(std::io::Error::other("ignored"))
+                    });
+                });
+            }
+        });
+
+        assert_eq!(executed.load(Ordering::SeqCst), 2);
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(cb.metrics().rejected_calls, (THREADS - 2) as u64);
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open_single_flight() {
+        const THREADS: usize = 8;
+        let run = |trial_succeeds: bool| {
+            let clock = MockClock::new();
+            let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+                .clock(clock.clone())
+                .half_open_single_flight(true)
+                .build().unwrap();
+            cb.handle_failure();
+            clock.advance(Duration::from_secs(10));
+
+            let half_open_runs = AtomicUsize::new(0);
+            let barrier = std::sync::Barrier::new(THREADS);
+            let results: Vec<_> = thread::scope(|s| {
+                let handles: Vec<_> = (0..THREADS)
+                    .map(|_| s.spawn(|| {
+                        barrier.wait();
+                        cb.execute(|| {
+                            if cb.state() == CircuitState::HalfOpen {
+                                half_open_runs.fetch_add(1, Ordering::SeqCst);
+                                // Hold the trial open long enough for every caller to arrive.
+                                thread::sleep(Duration::from_millis(100));
+                                if !trial_succeeds {
+                                    return Err(std::io::Error::other("still down"));
+                                }
+                            }
+                            Ok(())
+                        }).map_err(|e| e.to_string())
+                    }))
+                    .collect();
+                handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+            });
+            (cb, half_open_runs.into_inner(), results)
+        };
+
+        // A successful trial closes the circuit, and the waiting callers then proceed.
+        let (cb, half_open_runs, results) = run(true);
+        assert_eq!(half_open_runs, 1);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // A failed trial re-opens the circuit, and the waiting callers are rejected.
+        let (cb, half_open_runs, results) = run(false);
+        assert_eq!(half_open_runs, 1);
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), THREADS);
+        assert_eq!(cb.metrics().rejected_calls, (THREADS - 1) as u64);
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    /// Builds a single-flight breaker with a half-open circuit, driven by `clock`.
+    fn half_open_single_flight(clock: &Arc<MockClock>) -> CircuitBreaker {
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(10))
+            .clock(clock.clone())
+            .half_open_single_flight(true)
+            .build().unwrap();
+        cb.handle_failure();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        cb
+    }
+
+    #[test]
+    fn test_circuit_breaker_single_flight_trial_released_without_outcome() {
+        // An error the reason mapper ignores records nothing but frees the trial.
+        let cb = half_open_single_flight(&MockClock::new());
+        let result = cb.execute_with_reason(|| Err::<(), _>(std::io::Error::other("not found")), |_| None);
+        assert!(matches!(result, Err(BreakerError::Inner(_))));
+        assert!(cb.try_admit_trial());
+        cb.report_trial(true);
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // So does a trial that panics.
+        let cb = half_open_single_flight(&MockClock::new());
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cb.execute(|| -> Result<(), std::io::Error> { panic!("boom") })
+        }));
+        assert!(panicked.is_err());
+        assert!(cb.try_admit_trial());
+    }
+
+    #[test]
+    fn test_circuit_breaker_force_half_open() {
+        let half_opened = Arc::new(AtomicUsize::new(0));
+        let cb = {
+            let half_opened = half_opened.clone();
+            CircuitBreaker::builder(3, Duration::from_secs(60))
+                .half_open_max_calls(1)
+                .on_half_open(move || { half_opened.fetch_add(1, Ordering::SeqCst); })
+                .build().unwrap()
+        };
+
+        cb.force_half_open();
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert_eq!(half_opened.load(Ordering::SeqCst), 1);
+
+        // The quota admits one trial; a second concurrent trial is rejected.
+        let permit = cb.try_acquire().unwrap();
+        assert!(matches!(cb.try_acquire(), Err(CircuitBreakerError::HalfOpenQuotaExceeded { .. })));
+        permit.report_success();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // Forcing again from Open bypasses the reset timeout, with a fresh quota.
+        cb.force_open();
+        cb.force_half_open();
+        assert_eq!(half_opened.load(Ordering::SeqCst), 2);
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("error")));
+        assert_eq!(cb.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_max_concurrency() {
+        const THREADS: usize = 6;
+        let cb = CircuitBreaker::builder(1, Duration::from_secs(60))
+            .max_concurrency(2)
+            .build().unwrap();
+        let release = AtomicBool::new(false);
+
+        let rejections: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| s.spawn(|| {
+                    cb.execute(|| {
+                        while !release.load(Ordering::SeqCst) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        Ok::<_, std::io::Error>(())
+                    })
+                    .err()
+                    .map(|e| e.downcast_ref::<CircuitBreakerError>().map(CircuitBreakerError::code))
+                }))
+                .collect();
+
+            // Hold the admitted calls until every other call has been rejected.
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while cb.metrics().rejected_calls < (THREADS - 2) as u64 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(cb.in_flight(), 2);
+            release.store(true, Ordering::SeqCst);
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        assert_eq!(rejections.iter().filter(|rejection| rejection.is_none()).count(), 2);
+        assert_eq!(rejections.iter().filter(|rejection| **rejection == Some(Some("concurrency_limit"))).count(), THREADS - 2);
+        assert_eq!(cb.in_flight(), 0);
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        // A panicking call stops counting as it unwinds.
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cb.execute(|| -> Result<(), std::io::Error> { panic!("boom") })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(cb.in_flight(), 0);
+        assert!(cb.execute(|| Ok::<_, std::io::Error>(())).is_ok());
+    }
+
+    #[test]
+    fn test_circuit_breaker_error_codes() {
+        let errors = [
+            CircuitBreakerError::CircuitOpen { retry_after: None },
+            CircuitBreakerError::OperationPanicked,
+            CircuitBreakerError::HalfOpenQuotaExceeded { retry_after: Duration::ZERO },
+            CircuitBreakerError::ConcurrencyLimit { limit: 1 },
+        ];
+        let codes: Vec<_> = errors.iter().map(CircuitBreakerError::code).collect();
+        assert_eq!(codes, ["circuit_open", "operation_panicked", "half_open_quota", "concurrency_limit"]);
+
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        cb.handle_failure();
+        let error = cb.try_acquire().err().unwrap();
+        assert_eq!(error.code(), "circuit_open");
+    }
+
+    #[test]
+    fn test_export_prometheus() {
+        let payments = CircuitBreaker::new(3, Duration::from_secs(60));
+        let search = CircuitBreaker::new(1, Duration::from_secs(60));
+        payments.handle_success();
+        payments.handle_success();
+        payments.handle_failure();
+        let _ = search.execute(|| Err::<(), _>(std::io::Error::other("down")));
+        let _ = search.execute(|| Ok::<_, std::io::Error>(()));
+
+        let text = circuit_breaker::export_prometheus([("payments", &payments), ("search \"eu\"", &search)]);
+        let lines: Vec<_> = text.lines().collect();
+        for expected in [
+            "# TYPE circuit_breaker_state gauge",
+            "circuit_breaker_state{name=\"payments\",state=\"closed\"} 1",
+            "circuit_breaker_state{name=\"payments\",state=\"open\"} 0",
+            "circuit_breaker_state{name=\"search \\\"eu\\\"\",state=\"open\"} 1",
+            "circuit_breaker_state{name=\"search \\\"eu\\\"\",state=\"half_open\"} 0",
+            "# HELP circuit_breaker_failure_count The current value of the failure counter used for the threshold.",
+            "circuit_breaker_failure_count{name=\"payments\"} 1",
+            "# TYPE circuit_breaker_successes_total counter",
+            "circuit_breaker_successes_total{name=\"payments\"} 2",
+            "circuit_breaker_successes_total{name=\"search \\\"eu\\\"\"} 0",
+            "circuit_breaker_failures_total{name=\"search \\\"eu\\\"\"} 1",
+            "circuit_breaker_rejected_calls_total{name=\"search \\\"eu\\\"\"} 1",
+            "circuit_breaker_trips_total{name=\"payments\"} 0",
+            "circuit_breaker_trips_total{name=\"search \\\"eu\\\"\"} 1",
+        ] {
+            assert!(lines.contains(&expected), "missing {:?} in\n{}", expected, text);
+        }
+        assert_eq!(lines.iter().filter(|line| line.starts_with("# TYPE")).count(), 6);
+
+        // OpenMetrics names a counter's family without `_total`, so no other family may use that name.
+        let families: Vec<_> = lines.iter()
+            .filter_map(|line| line.strip_prefix("# TYPE "))
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        for family in &families {
+            if let Some(base) = family.strip_suffix("_total") {
+                assert!(!families.contains(&base), "{} collides with {}", base, family);
+            }
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_registry() {
+        let registry = CircuitBreakerRegistry::new();
+        let payments = registry.register("payments", CircuitBreaker::new(1, Duration::from_secs(60)));
+        registry.register("search", CircuitBreaker::new(1, Duration::from_secs(60)));
+        assert_eq!(registry.names(), ["payments", "search"]);
+
+        payments.handle_failure();
+        assert_eq!(registry.get("payments").unwrap().state(), CircuitState::Open);
+
+        // Replacing a breaker leaves the old one working for whoever still holds it.
+        registry.register("payments", CircuitBreaker::new(1, Duration::from_secs(60)));
+        assert_eq!(registry.get("payments").unwrap().state(), CircuitState::Closed);
+        assert_eq!(payments.state(), CircuitState::Open);
+
+        assert!(registry.remove("search").is_some());
+        assert!(registry.get("search").is_none());
+        assert_eq!(registry.names(), ["payments"]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_circuit_breaker_registry_export_prometheus() {
+        let registry = CircuitBreakerRegistry::new();
+        let payments = registry.register("payments", CircuitBreaker::new(3, Duration::from_secs(60)));
+        let search = registry.register("search", CircuitBreaker::new(1, Duration::from_secs(60)));
+        payments.handle_success();
+        payments.handle_failure();
+        let _ = search.execute(|| Err::<(), _>(std::io::Error::other("down")));
+        let _ = search.execute(|| Ok::<_, std::io::Error>(()));
+
+        let text = registry.export_prometheus().await;
+        let lines: Vec<_> = text.lines().collect();
+        for expected in [
+            "# HELP circuit_breaker_state Whether the circuit is in the given state.",
+            "circuit_breaker_state{name=\"payments\",state=\"closed\"} 1",
+            "circuit_breaker_state{name=\"search\",state=\"open\"} 1",
+            "circuit_breaker_state{name=\"search\",state=\"closed\"} 0",
+            "circuit_breaker_failure_count{name=\"payments\"} 1",
+            "circuit_breaker_successes_total{name=\"payments\"} 1",
+            "circuit_breaker_failures_total{name=\"search\"} 1",
+            "circuit_breaker_rejected_calls_total{name=\"search\"} 1",
+            "circuit_breaker_trips_total{name=\"search\"} 1",
+        ] {
+            assert!(lines.contains(&expected), "missing {:?} in\n{}", expected, text);
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_error_http_status() {
+        let errors = [
+            CircuitBreakerError::CircuitOpen { retry_after: Some(Duration::from_secs(1)) },
+            CircuitBreakerError::OperationPanicked,
+            CircuitBreakerError::HalfOpenQuotaExceeded { retry_after: Duration::ZERO },
+            CircuitBreakerError::ConcurrencyLimit { limit: 1 },
+        ];
+        let statuses: Vec<_> = errors.iter().map(CircuitBreakerError::http_status).collect();
+        assert_eq!(statuses, [503, 500, 429, 429]);
+
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        cb.handle_failure();
+        let error = cb.try_acquire().err().unwrap();
+        assert_eq!(error.http_status(), 503);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn test_circuit_breaker_tower_layer() {
+        use circuit_breaker::CircuitBreakerLayer;
+        use tower::{service_fn, Layer, ServiceExt};
+
+        let breaker = Arc::new(
+            CircuitBreaker::builder(2, Duration::from_secs(60))
+                .error_classifier(|e| !e.is::<std::fmt::Error>())
+                .build().unwrap(),
+        );
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = {
+            let calls = calls.clone();
+            service_fn(move |status: u16| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match status {
+                        500 => Err(Box::new(std::io::Error::other("server error")) as Box<dyn std::error::Error + Send + Sync>),
+                        404 => Err(Box::new(std::fmt::Error) as Box<dyn std::error::Error + Send + Sync>),
+                        _ => Ok(status),
+                    }
+                }
+            })
+        };
+        let service = CircuitBreakerLayer::new(breaker.clone()).layer(inner);
+
+        assert_eq!(service.clone().oneshot(200).await.unwrap(), 200);
+        assert!(service.clone().oneshot(404).await.is_err());
+        assert!(service.clone().oneshot(500).await.is_err());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(service.clone().oneshot(500).await.is_err());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // While open, the service is not ready and the inner service is not called.
+        let error = service.clone().oneshot(200).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<CircuitBreakerError>(), Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(breaker.metrics().rejected_calls, 1);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn test_circuit_breaker_tower_layer_cancelled_trial() {
+        use circuit_breaker::CircuitBreakerLayer;
+        use tower::{service_fn, Layer, Service, ServiceExt};
+
+        let breaker = Arc::new(half_open_single_flight(&MockClock::new()));
+        let inner = service_fn(|_: u32| std::future::pending::<Result<u32, std::io::Error>>());
+        let mut service = CircuitBreakerLayer::new(breaker.clone()).layer(inner);
+
+        // Dropping the response future before it completes releases the trial.
+        let response = service.ready().await.unwrap().call(1);
+        assert!(!breaker.try_admit_trial());
+        drop(response);
+        assert!(breaker.try_admit_trial());
+        assert_eq!(breaker.in_flight(), 1);
+        breaker.report_trial(true);
+        assert_eq!(breaker.in_flight(), 0);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn test_circuit_breaker_tower_layer_never_waits_for_reset() {
+        use circuit_breaker::CircuitBreakerLayer;
+        use tower::{service_fn, Layer, Service, ServiceExt};
+
+        // The clock never moves, so a call that waited would never return.
+        let breaker = Arc::new(
+            CircuitBreaker::builder(1, Duration::from_secs(60))
+                .clock(MockClock::new())
+                .open_behavior(OpenBehavior::WaitForReset { max: Duration::from_secs(3600) })
+                .build().unwrap(),
+        );
+        let inner = service_fn(|n: u32| async move { Ok::<_, std::io::Error>(n) });
+        let mut service = CircuitBreakerLayer::new(breaker.clone()).layer(inner);
+
+        // The circuit opens between the readiness check and the call.
+        let ready = service.ready().await.unwrap();
+        breaker.handle_failure();
+        let error = ready.call(1).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<CircuitBreakerError>(), Some(CircuitBreakerError::CircuitOpen { .. })));
+        assert_eq!(breaker.metrics().rejected_calls, 1);
+    }
+
+    /// Serves HTTP on a local port, answering each request with the next status in `statuses`.
+    #[cfg(feature = "reqwest")]
+    fn serve_statuses(statuses: Vec<u16>) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(stream, "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            }
+        });
+        format!("http://{}/", address)
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn test_circuit_breaker_reqwest_middleware() {
+        use circuit_breaker::CircuitBreakerMiddleware;
+
+        let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_secs(60)));
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(CircuitBreakerMiddleware::new(breaker.clone()))
+            .build();
+        let url = serve_statuses(vec![200, 404, 404, 500, 500]);
+
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+        for _ in 0..2 {
+            assert_eq!(client.get(&url).send().await.unwrap().status(), 404);
+        }
+        assert_eq!(breaker.current_failures(), 0);
+
+        // Server errors are returned to the caller but trip the circuit.
+        for _ in 0..2 {
+            assert_eq!(client.get(&url).send().await.unwrap().status(), 500);
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let error = client.get(&url).send().await.unwrap_err();
+        assert!(error.to_string().starts_with("Circuit is open"));
+        assert_eq!(breaker.metrics().total_successes, 1);
+        assert_eq!(breaker.metrics().rejected_calls, 1);
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn test_circuit_breaker_reqwest_middleware_client_error_trial() {
+        use circuit_breaker::CircuitBreakerMiddleware;
+
+        let breaker = Arc::new(half_open_single_flight(&MockClock::new()));
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(CircuitBreakerMiddleware::new(breaker.clone()))
+            .build();
+        let url = serve_statuses(vec![404]);
+
+        // A client error records nothing, so the trial is released for the next caller.
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 404);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_admit_trial());
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn test_circuit_breaker_reqwest_middleware_never_waits_for_reset() {
+        use circuit_breaker::CircuitBreakerMiddleware;
+
+        // The clock never moves, so a call that waited would never return.
+        let breaker = Arc::new(
+            CircuitBreaker::builder(1, Duration::from_secs(60))
+                .clock(MockClock::new())
+                .open_behavior(OpenBehavior::WaitForReset { max: Duration::from_secs(3600) })
+                .build().unwrap(),
+        );
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(CircuitBreakerMiddleware::new(breaker.clone()))
+            .build();
+        breaker.handle_failure();
+
+        let error = client.get(serve_statuses(vec![])).send().await.unwrap_err();
+        assert!(error.to_string().starts_with("Circuit is open"));
+        assert_eq!(breaker.metrics().rejected_calls, 1);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_testing_fake_clock_drives_breaker() {
+        use circuit_breaker::assert_state;
+        use circuit_breaker::testing::{fast_builder, FakeClock, FAST_RESET_TIMEOUT};
+
+        let clock = FakeClock::new();
+        let cb = fast_builder(&clock).success_threshold(2).build().unwrap();
+        assert_eq!(cb.failure_threshold(), 1);
+        assert_eq!(cb.reset_timeout(), FAST_RESET_TIMEOUT);
+
+        let _ = cb.execute(|| Err::<(), _>(std::io::Error::other("down")));
+        assert_state!(cb, Open);
+
+        clock.advance(FAST_RESET_TIMEOUT / 2);
+        assert_state!(cb, Open, "the reset timeout has not elapsed yet");
+        clock.advance(FAST_RESET_TIMEOUT / 2);
+        assert_eq!(clock.elapsed(), FAST_RESET_TIMEOUT);
+        assert_state!(cb, HalfOpen);
+
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_state!(cb, HalfOpen);
+        cb.execute(|| Ok::<_, std::io::Error>(())).unwrap();
+        assert_state!(cb, Closed);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    #[should_panic(expected = "still closed")]
+    fn test_testing_assert_state_failure() {
+        let cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        circuit_breaker::assert_state!(cb, Open, "still closed");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_circuit_breaker_on_open_future() {
+        let cb = Arc::new(CircuitBreaker::new(2, Duration::from_secs(60)));
+
+        let waiters: Vec<_> = (0..3).map(|_| tokio::spawn(cb.on_open_future())).collect();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(waiters.iter().all(|waiter| !waiter.is_finished()));
+
+        let tripper = {
+            let cb = cb.clone();
+            tokio::spawn(async move {
+                cb.handle_failure();
+                cb.handle_failure();
+            })
+        };
+        tripper.await.unwrap();
+        for waiter in waiters {
+            tokio::time::timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap();
+        }
+
+        // A future created after the transition waits for the next one.
+        let opened = cb.on_open_future();
+        cb.force_close();
+        cb.force_open();
+        tokio::time::timeout(Duration::from_secs(5), opened).await.unwrap();
+
+        // Futures dropped before the circuit opens do not stop later ones from completing.
+        for _ in 0..1000 {
+            drop(cb.on_open_future());
+        }
+        let opened = cb.on_open_future();
+        cb.force_close();
+        cb.force_open();
+        tokio::time::timeout(Duration::from_secs(5), opened).await.unwrap();
+    }
+}
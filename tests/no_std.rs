@@ -0,0 +1,60 @@
+//! Drives the tick-based core through a caller-supplied lock, using nothing from `std`.
+#![no_std]
+
+use circuit_breaker::{CircuitState, SharedStateMachine, StateLock, StateMachine};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A minimal spin lock, standing in for the critical section an embedded target would use.
+struct SpinLock {
+    locked: AtomicBool,
+    machine: UnsafeCell<StateMachine>,
+}
+
+// SAFETY: the state machine is only reached through `with`, which holds the lock.
+unsafe impl Sync for SpinLock {}
+
+impl SpinLock {
+    const fn new(machine: StateMachine) -> Self {
+        SpinLock { locked: AtomicBool::new(false), machine: UnsafeCell::new(machine) }
+    }
+}
+
+impl StateLock for SpinLock {
+    fn with<R>(&self, f: impl FnOnce(&mut StateMachine) -> R) -> R {
+        while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        // SAFETY: the lock is held, so no other reference to the state machine exists.
+        let result = f(unsafe { &mut *self.machine.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+static BREAKER: SharedStateMachine<SpinLock> = SharedStateMachine::new(SpinLock::new(StateMachine::new(2, 100)));
+
+#[test]
+fn test_shared_state_machine_driven_by_ticks() {
+    assert_eq!(BREAKER.state(), CircuitState::Closed);
+
+    BREAKER.record_failure(10);
+    assert!(BREAKER.try_acquire(15));
+    BREAKER.record_failure(20);
+    assert_eq!(BREAKER.state(), CircuitState::Open);
+    assert_eq!(BREAKER.failures(), 2);
+
+    // The reset timeout is counted in ticks from the last failure.
+    assert!(!BREAKER.try_acquire(119));
+    assert_eq!(BREAKER.poll(119), CircuitState::Open);
+    assert!(BREAKER.try_acquire(120));
+    assert_eq!(BREAKER.state(), CircuitState::HalfOpen);
+
+    BREAKER.record_failure(130);
+    assert_eq!(BREAKER.poll(229), CircuitState::Open);
+    assert_eq!(BREAKER.poll(230), CircuitState::HalfOpen);
+
+    BREAKER.record_success();
+    assert_eq!(BREAKER.state(), CircuitState::Closed);
+    assert_eq!(BREAKER.failures(), 0);
+}